@@ -0,0 +1,749 @@
+//! The exchanges of a run kept in a HAR file, the format of the network
+//! tools of the browsers: `--record` writes there the responses received,
+//! and `--replay` answers the same requests with them, without sending
+//! anything.
+
+use crate::client::ClientOptions;
+use crate::cookies;
+use crate::request::{OwnedResponse, Request, SavedBody};
+use crate::transport::{HttpTransport, Response};
+use anyhow::{Context, Result};
+use json::JsonValue;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Url, Version};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What a request is recognized by when replayed: its method, its URL with
+/// the parameters, and its body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub method: String,
+    pub url: String,
+    /// the size of the body and its SHA-256, in hex; none when read from the
+    /// standard input
+    pub body: Option<(u64, String)>,
+}
+
+impl Key {
+    /// The key of `req`, hashing the `< path` file as it's read, not to hold
+    /// it whole.
+    pub fn new(req: &Request) -> Result<Key> {
+        let body = match &req.body_file {
+            _ if req.body_stdin => None,
+            Some(path) => {
+                let read_error = || format!("unable to read the body file {}", path.display());
+                let mut file = File::open(path).with_context(read_error)?;
+                let mut hasher = Sha256::new();
+                let size = io::copy(&mut file, &mut hasher).with_context(read_error)?;
+                Some((size, format!("{:x}", hasher.finalize())))
+            }
+            None => Some(digest(&req.body)),
+        };
+        Ok(Key {
+            method: req.method.to_ascii_uppercase(),
+            url: req.target_url()?.to_string(),
+            body,
+        })
+    }
+
+    /// The key with the secrets of `req` in the URL replaced by `***`, as
+    /// recorded without `--show-secrets`.
+    fn redacted(&self, req: &Request) -> Key {
+        Key {
+            url: req.redact(&self.url),
+            ..self.clone()
+        }
+    }
+}
+
+fn digest(body: &[u8]) -> (u64, String) {
+    (body.len() as u64, format!("{:x}", Sha256::digest(body)))
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.url)?;
+        match &self.body {
+            Some((size, sha256)) if *size > 0 => {
+                write!(f, " with a body of {} bytes (SHA-256 {})", size, sha256)
+            }
+            Some(_) => Ok(()),
+            None => write!(f, " with a body from the standard input"),
+        }
+    }
+}
+
+/// A request and the response it got.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub key: Key,
+    /// the URL written to the archive, with the secrets replaced by `***`
+    /// unless `--show-secrets`
+    pub url: String,
+    /// the protocol of the request, as `HTTP/1.1`
+    pub protocol: String,
+    /// when the request was sent, in ISO 8601
+    pub started: String,
+    /// how long the response took
+    pub elapsed: Duration,
+    /// the headers sent, redacted as the URL, and the credentials always
+    pub request_headers: Vec<(String, String)>,
+    /// the text of the body, redacted as the URL; none for a body read from
+    /// a file or the standard input
+    pub request_body: Option<String>,
+    /// the `< path` file the body was read from, that isn't copied
+    pub request_file: Option<PathBuf>,
+    /// the response; when written to a file by `>> path` or `--output`, it
+    /// refers to the file instead of holding the body
+    pub response: OwnedResponse,
+}
+
+/// The entries of a HAR file.
+pub struct Archive {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Archive {
+    /// An empty archive, replacing `path` once saved.
+    pub fn create(path: &Path) -> Archive {
+        Archive {
+            path: path.to_path_buf(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads the entries of `path`, recorded by `--record` or exported by
+    /// a browser.
+    pub fn read(path: &Path) -> Result<Archive> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read the archive {}", path.display()))?;
+        let invalid =
+            |reason: &str| anyhow::anyhow!("invalid archive {}: {}", path.display(), reason);
+        let document = json::parse(&text).map_err(|_| invalid("not JSON"))?;
+        if !document["log"]["entries"].is_array() {
+            return Err(invalid("expected a HAR file, with log.entries"));
+        }
+        let entries = document["log"]["entries"]
+            .members()
+            .enumerate()
+            .map(|(index, entry)| {
+                parse_entry(entry).ok_or_else(|| invalid(&format!("malformed entry {}", index)))
+            })
+            .collect::<Result<Vec<Entry>>>()?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        self.entries.push(entry);
+    }
+
+    /// Writes the entries to the file, as HAR 1.2.
+    pub fn save(&self) -> Result<()> {
+        let mut creator = JsonValue::new_object();
+        creator["name"] = "httpclient".into();
+        creator["version"] = env!("CARGO_PKG_VERSION").into();
+        let mut log = JsonValue::new_object();
+        log["version"] = "1.2".into();
+        log["creator"] = creator;
+        log["entries"] = JsonValue::Array(self.entries.iter().map(entry_to_json).collect());
+        let mut document = JsonValue::new_object();
+        document["log"] = log;
+        fs::write(&self.path, json::stringify_pretty(document, 2))
+            .with_context(|| format!("unable to write the archive {}", self.path.display()))
+    }
+}
+
+/// The exchanges of a run for `--record`, kept as the responses are
+/// received, after the redirects, and written once the run is over.
+pub struct Recorder {
+    archive: Mutex<Archive>,
+}
+
+impl Recorder {
+    /// Records to `path`, replacing it once saved.
+    pub fn new(path: &Path) -> Recorder {
+        Recorder {
+            archive: Mutex::new(Archive::create(path)),
+        }
+    }
+
+    /// Keeps `req` and its `response`, sent `started`, in seconds since the
+    /// epoch, and received `elapsed` later. The secrets of the request are
+    /// replaced by `***` unless `show_secrets`, and the credentials of its
+    /// `Authorization` and `Cookie` headers always are, as with `-vv`.
+    pub fn add(
+        &self,
+        req: &Request,
+        response: &OwnedResponse,
+        started: u64,
+        elapsed: Duration,
+        show_secrets: bool,
+    ) -> Result<()> {
+        let key = Key::new(req)?;
+        let redact = |text: &str| match show_secrets {
+            true => text.to_string(),
+            false => req.redact(text),
+        };
+        let mut request_headers: Vec<(String, String)> = req
+            .format_headers()?
+            .iter()
+            .map(|(name, value)| {
+                let text = String::from_utf8_lossy(value.as_bytes());
+                let value = if name == header::AUTHORIZATION || name == header::PROXY_AUTHORIZATION
+                {
+                    match text.split_once(' ') {
+                        Some((scheme, _)) => format!("{} <redacted>", scheme),
+                        None => "<redacted>".to_string(),
+                    }
+                } else if name == header::COOKIE {
+                    "<redacted>".to_string()
+                } else {
+                    redact(&text)
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        request_headers.sort();
+        let entry = Entry {
+            url: redact(&key.url),
+            key,
+            protocol: req.protocol.clone(),
+            started: cookies::format_iso_date(started),
+            elapsed,
+            request_headers,
+            request_body: match &req.body_file {
+                None if !req.body_stdin => Some(redact(&String::from_utf8_lossy(&req.body))),
+                _ => None,
+            },
+            request_file: req.body_file.clone(),
+            response: response.clone(),
+        };
+        self.archive.lock().unwrap().push(entry);
+        Ok(())
+    }
+
+    /// Writes the exchanges recorded to the archive.
+    pub fn save(&self) -> Result<()> {
+        self.archive.lock().unwrap().save()
+    }
+}
+
+/// A transport answering with the responses of an archive, those to the
+/// same method, URL and body, in the order they were recorded; once they
+/// have all been replayed the last one is replayed again.
+pub struct Replay {
+    archive: Archive,
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl Replay {
+    /// Replays the responses of the archive at `path`.
+    pub fn open(path: &Path) -> Result<Replay> {
+        let archive = Archive::read(path)?;
+        let replayed = Mutex::new(vec![false; archive.entries.len()]);
+        Ok(Replay { archive, replayed })
+    }
+}
+
+impl HttpTransport for Replay {
+    fn send(
+        &self,
+        req: &Request,
+        _options: &ClientOptions,
+        _timeout: Duration,
+    ) -> Result<Response> {
+        let key = Key::new(req)?;
+        // recorded with its secrets or without them
+        let redacted = key.redacted(req);
+        let matching: Vec<usize> = (0..self.archive.entries.len())
+            .filter(|index| {
+                let recorded = &self.archive.entries[*index].key;
+                *recorded == key || *recorded == redacted
+            })
+            .collect();
+        let mut replayed = self.replayed.lock().unwrap();
+        let index = matching
+            .iter()
+            .find(|index| !replayed[**index])
+            .or_else(|| matching.last())
+            .copied()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no response recorded for {} in {}",
+                    redacted,
+                    self.archive.path.display()
+                )
+            })?;
+        replayed[index] = true;
+        to_response(&self.archive.entries[index].response)
+    }
+}
+
+/// A recorded response, to read again; a body written to a file is read
+/// back from there.
+fn to_response(owned: &OwnedResponse) -> Result<Response> {
+    let body = match &owned.saved {
+        Some(saved) => {
+            let read_error = || format!("unable to read the body file {}", saved.path.display());
+            let mut file = File::open(&saved.path).with_context(read_error)?;
+            file.seek(SeekFrom::Start(saved.skipped))
+                .with_context(read_error)?;
+            let mut body = Vec::new();
+            file.take(saved.size)
+                .read_to_end(&mut body)
+                .with_context(read_error)?;
+            body
+        }
+        None => owned.body.clone(),
+    };
+    let mut response = http::Response::builder()
+        .status(owned.status)
+        .version(owned.version);
+    for (name, value) in &owned.headers {
+        response = response.header(name, value);
+    }
+    Ok(response.body(body)?.into())
+}
+
+fn content_type<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> &'a str {
+    headers
+        .filter(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value)
+        .next()
+        .unwrap_or("")
+}
+
+fn entry_to_json(entry: &Entry) -> JsonValue {
+    let response = &entry.response;
+    let millis = entry.elapsed.as_secs_f64() * 1000.0;
+    let mut request = JsonValue::new_object();
+    request["method"] = entry.key.method.as_str().into();
+    request["url"] = entry.url.as_str().into();
+    request["httpVersion"] = entry.protocol.as_str().into();
+    request["cookies"] = JsonValue::new_array();
+    let request_headers = || {
+        entry
+            .request_headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    };
+    request["headers"] = headers_to_json(request_headers());
+    request["queryString"] = JsonValue::Array(match Url::parse(&entry.url) {
+        Ok(url) => url
+            .query_pairs()
+            .map(|(name, value)| {
+                let mut parameter = JsonValue::new_object();
+                parameter["name"] = name.as_ref().into();
+                parameter["value"] = value.as_ref().into();
+                parameter
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    });
+    request["headersSize"] = (-1).into();
+    let mut post_data = JsonValue::new_object();
+    post_data["mimeType"] = content_type(request_headers()).into();
+    match (&entry.key.body, &entry.request_body, &entry.request_file) {
+        (Some((size, sha256)), body, file) => {
+            request["bodySize"] = (*size).into();
+            // the text of a binary or redacted body isn't the one sent, but
+            // its hash is
+            request["_bodySha256"] = sha256.as_str().into();
+            match (body, file) {
+                (Some(body), _) if !body.is_empty() => {
+                    post_data["text"] = body.as_str().into();
+                    request["postData"] = post_data;
+                }
+                (_, Some(file)) => {
+                    post_data["text"] = "".into();
+                    post_data["_file"] = file.to_string_lossy().as_ref().into();
+                    request["postData"] = post_data;
+                }
+                _ => (),
+            }
+        }
+        (None, _, _) => {
+            request["bodySize"] = (-1).into();
+            request["_bodyStdin"] = true.into();
+        }
+    }
+
+    let mut content = JsonValue::new_object();
+    content["mimeType"] = response
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .into();
+    let body_size = match &response.saved {
+        Some(saved) => {
+            content["size"] = saved.size.into();
+            content["text"] = "".into();
+            content["_file"] = saved.path.to_string_lossy().as_ref().into();
+            content["_fileOffset"] = saved.skipped.into();
+            saved.size
+        }
+        None => {
+            content["size"] = response.body.len().into();
+            match String::from_utf8(response.body.clone()) {
+                Ok(text) if !response.is_binary() => content["text"] = text.into(),
+                _ => {
+                    content["text"] = base64::encode(&response.body).into();
+                    content["encoding"] = "base64".into();
+                }
+            }
+            response.body.len() as u64
+        }
+    };
+    let mut har_response = JsonValue::new_object();
+    har_response["status"] = response.status.as_u16().into();
+    har_response["statusText"] = response.status.canonical_reason().unwrap_or("").into();
+    har_response["httpVersion"] = format!("{:?}", response.version).into();
+    har_response["cookies"] = JsonValue::new_array();
+    har_response["headers"] = headers_to_json(
+        response
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?))),
+    );
+    har_response["content"] = content;
+    har_response["redirectURL"] = response
+        .headers
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .into();
+    har_response["headersSize"] = (-1).into();
+    har_response["bodySize"] = body_size.into();
+
+    let mut timings = JsonValue::new_object();
+    timings["send"] = 0.into();
+    timings["wait"] = millis.into();
+    timings["receive"] = 0.into();
+    let mut object = JsonValue::new_object();
+    object["startedDateTime"] = entry.started.as_str().into();
+    object["time"] = millis.into();
+    object["request"] = request;
+    object["response"] = har_response;
+    object["cache"] = JsonValue::new_object();
+    object["timings"] = timings;
+    object
+}
+
+fn headers_to_json<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> JsonValue {
+    JsonValue::Array(
+        headers
+            .map(|(name, value)| {
+                let mut header = JsonValue::new_object();
+                header["name"] = name.into();
+                header["value"] = value.into();
+                header
+            })
+            .collect(),
+    )
+}
+
+/// An entry of a HAR file; the ones exported by the browsers have no
+/// `_bodySha256`, and their bodies are taken as they are.
+fn parse_entry(entry: &JsonValue) -> Option<Entry> {
+    let request = &entry["request"];
+    let response = &entry["response"];
+    let request_body = request["postData"]["text"].as_str().map(str::to_string);
+    let body = match request["_bodySha256"].as_str() {
+        Some(sha256) => Some((request["bodySize"].as_u64()?, sha256.to_string())),
+        None if request["_bodyStdin"].as_bool() == Some(true) => None,
+        None => Some(digest(request_body.as_deref().unwrap_or("").as_bytes())),
+    };
+    let url = request["url"].as_str()?.to_string();
+    let key = Key {
+        method: request["method"].as_str()?.to_ascii_uppercase(),
+        url: url.clone(),
+        body,
+    };
+    let pairs = |headers: &JsonValue| -> Vec<(String, String)> {
+        headers
+            .members()
+            .filter_map(|header| {
+                Some((
+                    header["name"].as_str()?.to_string(),
+                    header["value"].as_str()?.to_string(),
+                ))
+            })
+            .collect()
+    };
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs(&response["headers"]) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    let content = &response["content"];
+    let saved = match content["_file"].as_str() {
+        Some(path) => Some(SavedBody {
+            path: PathBuf::from(path),
+            size: content["size"].as_u64()?,
+            skipped: content["_fileOffset"].as_u64().unwrap_or(0),
+            elapsed: Duration::new(0, 0),
+        }),
+        None => None,
+    };
+    let text = content["text"].as_str().unwrap_or("");
+    let body = match content["encoding"].as_str() {
+        _ if saved.is_some() => Vec::new(),
+        Some("base64") => base64::decode(text).ok()?,
+        _ => text.as_bytes().to_vec(),
+    };
+    Some(Entry {
+        key,
+        url,
+        protocol: request["httpVersion"]
+            .as_str()
+            .unwrap_or("HTTP/1.1")
+            .to_string(),
+        started: entry["startedDateTime"].as_str().unwrap_or("").to_string(),
+        elapsed: Duration::from_secs_f64(entry["time"].as_f64().unwrap_or(0.0).max(0.0) / 1000.0),
+        request_headers: pairs(&request["headers"]),
+        request_body,
+        request_file: request["postData"]["_file"].as_str().map(PathBuf::from),
+        response: OwnedResponse {
+            version: match response["httpVersion"].as_str() {
+                Some("HTTP/1.0") => Version::HTTP_10,
+                Some("HTTP/2.0") | Some("HTTP/2") | Some("h2") => Version::HTTP_2,
+                _ => Version::HTTP_11,
+            },
+            status: StatusCode::from_u16(response["status"].as_u16()?).ok()?,
+            headers,
+            body,
+            saved,
+            events: None,
+            truncated: false,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The whole response of `transport` to `req`.
+    fn send(transport: &dyn HttpTransport, req: &Request) -> Result<OwnedResponse> {
+        let response = transport.send(req, &ClientOptions::default(), Duration::from_secs(1))?;
+        OwnedResponse::from_response(response)
+    }
+
+    fn request(method: &str, url: &str, body: &str) -> Request {
+        let mut req = Request::new();
+        req.method = method.to_string();
+        req.url = url.to_string();
+        req.body = body.as_bytes().to_vec();
+        req
+    }
+
+    fn response(status: u16, content_type: &str, body: &[u8]) -> OwnedResponse {
+        let mut headers = HeaderMap::new();
+        if !content_type.is_empty() {
+            headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        }
+        OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::from_u16(status).unwrap(),
+            headers,
+            body: body.to_vec(),
+            saved: None,
+            events: None,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("httpclient-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.har");
+        let recorder = Recorder::new(&path);
+        let items = request("GET", "https://example.com/items?page=2&q=a%20b", "");
+        let created = request("POST", "https://example.com/items", r#"{"a":1}"#);
+        let second = Duration::from_secs(1);
+        for (req, response) in &[
+            (&items, response(200, "application/json", b"[1]")),
+            (&items, response(200, "application/json", b"[2]")),
+            (&created, response(201, "image/png", b"\x89PNG\r\n")),
+        ] {
+            recorder.add(req, response, 0, second, false).unwrap();
+        }
+        // written once the run is over
+        assert!(!path.exists());
+        recorder.save().unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let document = json::parse(&text).unwrap();
+        assert_eq!(document["log"]["version"], "1.2");
+        let entries = &document["log"]["entries"];
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["startedDateTime"], "1970-01-01T00:00:00Z");
+        assert_eq!(entries[0]["request"]["queryString"][1]["name"], "q");
+        assert_eq!(entries[0]["request"]["queryString"][1]["value"], "a b");
+        assert_eq!(entries[0]["response"]["content"]["text"], "[1]");
+        assert_eq!(entries[2]["request"]["postData"]["text"], r#"{"a":1}"#);
+        assert_eq!(entries[2]["response"]["content"]["encoding"], "base64");
+
+        let replay = Replay::open(&path).unwrap();
+        // in order, then the last one again
+        assert_eq!(send(&replay, &items).unwrap().text(), "[1]");
+        assert_eq!(send(&replay, &items).unwrap().text(), "[2]");
+        assert_eq!(send(&replay, &items).unwrap().text(), "[2]");
+        let response = send(&replay, &created).unwrap();
+        assert_eq!(response.status, StatusCode::CREATED);
+        assert_eq!(response.body, b"\x89PNG\r\n");
+        assert_eq!(response.headers["content-type"], "image/png");
+        // another body
+        let other = request("POST", "https://example.com/items", r#"{"a":2}"#);
+        assert_eq!(
+            send(&replay, &other).unwrap_err().to_string(),
+            format!(
+                "no response recorded for POST https://example.com/items with a body of 7 bytes (SHA-256 {:x}) in {}",
+                Sha256::digest(br#"{"a":2}"#),
+                path.display()
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn redacted_secrets() {
+        let dir = std::env::temp_dir().join(format!("httpclient-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.har");
+        let mut req = request(
+            "POST",
+            "https://example.com/login?key=s3cr3t",
+            "password=s3cr3t",
+        );
+        req.headers
+            .insert("X-Api-Key".to_string(), "s3cr3t".to_string());
+        req.headers
+            .insert("Authorization".to_string(), "Bearer t0k3n".to_string());
+        req.secrets = vec!["s3cr3t".to_string()];
+        let recorder = Recorder::new(&path);
+        let ok = response(200, "text/plain", b"welcome");
+        recorder
+            .add(&req, &ok, 0, Duration::from_secs(1), false)
+            .unwrap();
+        recorder.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("s3cr3t"), "{}", text);
+        assert!(!text.contains("t0k3n"), "{}", text);
+        let document = json::parse(&text).unwrap();
+        let recorded = &document["log"]["entries"][0]["request"];
+        assert_eq!(recorded["url"], "https://example.com/login?key=***");
+        assert_eq!(recorded["queryString"][0]["value"], "***");
+        assert_eq!(recorded["postData"]["text"], "password=***");
+        assert_eq!(recorded["headers"][0]["value"], "Bearer <redacted>");
+        assert_eq!(recorded["headers"][1]["value"], "***");
+        // still replayed, by the hash of the body sent
+        let replay = Replay::open(&path).unwrap();
+        assert_eq!(send(&replay, &req).unwrap().text(), "welcome");
+
+        // the secrets are kept with --show-secrets, not the credentials
+        let recorder = Recorder::new(&path);
+        recorder
+            .add(&req, &ok, 0, Duration::from_secs(1), true)
+            .unwrap();
+        recorder.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("key=s3cr3t"), "{}", text);
+        assert!(!text.contains("t0k3n"), "{}", text);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("httpclient-har-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.har");
+        let upload = dir.join("upload.bin");
+        fs::write(&upload, b"\x00\x01\x02").unwrap();
+        let download = dir.join("download.bin");
+        fs::write(&download, b"resumed, then the rest").unwrap();
+        let mut req = request("PUT", "https://example.com/blob", "");
+        req.body_file = Some(upload.clone());
+        let mut saved = response(206, "application/octet-stream", b"");
+        saved.saved = Some(SavedBody {
+            path: download.clone(),
+            size: 8,
+            skipped: 14,
+            elapsed: Duration::new(0, 0),
+        });
+        let recorder = Recorder::new(&path);
+        recorder
+            .add(&req, &saved, 0, Duration::from_secs(1), false)
+            .unwrap();
+        recorder.save().unwrap();
+        let document = json::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        let entry = &document["log"]["entries"][0];
+        // neither file is copied
+        assert_eq!(entry["request"]["bodySize"], 3);
+        assert_eq!(entry["request"]["postData"]["text"], "");
+        assert_eq!(
+            entry["request"]["postData"]["_file"],
+            upload.to_string_lossy().as_ref()
+        );
+        assert_eq!(entry["response"]["content"]["text"], "");
+        assert_eq!(entry["response"]["content"]["_fileOffset"], 14);
+        let replay = Replay::open(&path).unwrap();
+        assert_eq!(send(&replay, &req).unwrap().text(), "the rest");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn browser_archives() {
+        let dir = std::env::temp_dir().join(format!("httpclient-har-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("export.har");
+        fs::write(
+            &path,
+            r#"{"log": {"version": "1.2", "entries": [{
+                "startedDateTime": "2026-10-16T10:00:00.000Z",
+                "time": 12.5,
+                "request": {"method": "post", "url": "https://example.com/login",
+                    "headers": [], "postData": {"mimeType": "text/plain", "text": "me"}},
+                "response": {"status": 302, "httpVersion": "h2",
+                    "headers": [{"name": "Location", "value": "/home"}],
+                    "content": {"size": 0, "text": ""}}
+            }]}}"#,
+        )
+        .unwrap();
+        let replay = Replay::open(&path).unwrap();
+        let response = send(&replay, &request("POST", "https://example.com/login", "me")).unwrap();
+        assert_eq!(response.status, StatusCode::FOUND);
+        assert_eq!(response.version, Version::HTTP_2);
+        assert_eq!(response.headers["location"], "/home");
+
+        fs::write(&path, r#"{"entries": []}"#).unwrap();
+        assert_eq!(
+            Replay::open(&path).err().unwrap().to_string(),
+            format!(
+                "invalid archive {}: expected a HAR file, with log.entries",
+                path.display()
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use crate::jsonpath;
+use crate::request::OwnedResponse;
+use anyhow::Result;
+use json::JsonValue;
+use std::fmt;
+
+/// What an assertion checks in the response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subject {
+    /// The status code
+    Status,
+    /// The value at a JSONPath (such as `$.items[0].id`) of a JSON body
+    Body(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    /// `in a..b` (`b` excluded) or `in a..=b` (`b` included)
+    In,
+    /// substring of a string, or element of an array
+    Contains,
+    Exists,
+    NotExists,
+}
+
+/// A check on a response, written after the request as `?? status == 200`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assertion {
+    pub subject: Subject,
+    pub operator: Operator,
+    pub expected: String,
+    // the assertion as written in the file, for the report
+    text: String,
+}
+
+impl Assertion {
+    /// Parses the text of an assertion, without the leading `??`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use httpclient::assertion::{Assertion, Operator, Subject};
+    ///
+    /// let assertion = Assertion::parse("status in 200..300").unwrap();
+    /// assert_eq!(assertion.subject, Subject::Status);
+    /// assert_eq!(assertion.operator, Operator::In);
+    /// assert_eq!(assertion.expected, "200..300");
+    ///
+    /// let assertion = Assertion::parse("body items[0].id == 42").unwrap();
+    /// assert_eq!(assertion.subject, Subject::Body("$.items[0].id".to_string()));
+    /// ```
+    pub fn parse(text: &str) -> Result<Assertion> {
+        let text = text.trim();
+        let mut tokens = text.split_whitespace();
+        let subject = match tokens.next() {
+            Some("status") => Subject::Status,
+            Some("body") => {
+                let path = tokens
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing JSON path in assertion {}", text))?;
+                // dotted paths are relative to the root
+                let path = if path.starts_with('$') {
+                    path.to_string()
+                } else if path.starts_with('[') {
+                    format!("${}", path)
+                } else {
+                    format!("$.{}", path)
+                };
+                jsonpath::validate(&path)?;
+                Subject::Body(path)
+            }
+            Some(subject) => return Err(anyhow::anyhow!("unknown assertion subject {}", subject)),
+            None => return Err(anyhow::anyhow!("empty assertion")),
+        };
+        let operator = match tokens.next() {
+            Some("==") => Operator::Equal,
+            Some("!=") => Operator::NotEqual,
+            Some("<") => Operator::Less,
+            Some("<=") => Operator::LessOrEqual,
+            Some(">") => Operator::Greater,
+            Some(">=") => Operator::GreaterOrEqual,
+            Some("in") => Operator::In,
+            Some("contains") => Operator::Contains,
+            Some("exists") => Operator::Exists,
+            Some("not") if tokens.next() == Some("exists") => Operator::NotExists,
+            Some(operator) => {
+                return Err(anyhow::anyhow!("unknown assertion operator {}", operator))
+            }
+            None => return Err(anyhow::anyhow!("missing operator in assertion {}", text)),
+        };
+        let expected = tokens.collect::<Vec<_>>().join(" ");
+        match operator {
+            Operator::Exists | Operator::NotExists if !expected.is_empty() => {
+                return Err(anyhow::anyhow!("unexpected value in assertion {}", text));
+            }
+            Operator::Exists | Operator::NotExists => {}
+            _ if expected.is_empty() => {
+                return Err(anyhow::anyhow!("missing value in assertion {}", text));
+            }
+            Operator::In if parse_range(&expected).is_none() => {
+                return Err(anyhow::anyhow!("invalid range {} in assertion", expected));
+            }
+            _ => {}
+        }
+        Ok(Assertion {
+            subject,
+            operator,
+            expected,
+            text: text.to_string(),
+        })
+    }
+
+    /// Checks the assertion against a response; the error describes the
+    /// value that was actually found.
+    pub fn evaluate(&self, response: &OwnedResponse) -> Result<()> {
+        let actual = match &self.subject {
+            Subject::Status => Ok(JsonValue::from(response.status.as_u16())),
+            Subject::Body(path) => {
+                let document = json::parse(&response.text())
+                    .map_err(|_| anyhow::anyhow!("response is not JSON"))?;
+                jsonpath::lookup(&document, path).cloned()
+            }
+        };
+        match (&self.operator, actual) {
+            (Operator::Exists, actual) => actual.map(|_| ()),
+            (Operator::NotExists, Err(_)) => Ok(()),
+            (Operator::NotExists, Ok(value)) => Err(anyhow::anyhow!("got {}", value.dump())),
+            (_, Err(e)) => Err(e),
+            (operator, Ok(value)) => {
+                if compare(&value, operator, &self.expected)? {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("got {}", value.dump()))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(dest, "{}", self.text)
+    }
+}
+
+fn compare(actual: &JsonValue, operator: &Operator, expected: &str) -> Result<bool> {
+    // strings can be written with or without quotes
+    let expected = if expected.len() > 1 && expected.starts_with('"') && expected.ends_with('"') {
+        &expected[1..expected.len() - 1]
+    } else {
+        expected
+    };
+    let actual_text = jsonpath::to_plain_string(actual);
+    let numbers = || match (actual_text.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(e)) => Ok((a, e)),
+        _ => Err(anyhow::anyhow!(
+            "cannot compare {} with {}",
+            actual.dump(),
+            expected
+        )),
+    };
+    Ok(match operator {
+        Operator::Equal => actual_text == expected || matches!(numbers(), Ok((a, e)) if a == e),
+        Operator::NotEqual => !compare(actual, &Operator::Equal, expected)?,
+        Operator::Less => numbers().map(|(a, e)| a < e)?,
+        Operator::LessOrEqual => numbers().map(|(a, e)| a <= e)?,
+        Operator::Greater => numbers().map(|(a, e)| a > e)?,
+        Operator::GreaterOrEqual => numbers().map(|(a, e)| a >= e)?,
+        Operator::In => {
+            let (start, end, inclusive) = parse_range(expected)
+                .ok_or_else(|| anyhow::anyhow!("invalid range {}", expected))?;
+            let a = actual_text.parse::<f64>().map_err(|_| {
+                anyhow::anyhow!("cannot compare {} with {}", actual.dump(), expected)
+            })?;
+            a >= start && (a < end || (inclusive && a == end))
+        }
+        Operator::Contains => match actual {
+            JsonValue::Array(elements) => elements
+                .iter()
+                .any(|element| compare(element, &Operator::Equal, expected).unwrap_or(false)),
+            _ if actual.is_string() => actual_text.contains(expected),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} is neither a string nor an array",
+                    actual.dump()
+                ))
+            }
+        },
+        Operator::Exists | Operator::NotExists => true,
+    })
+}
+
+/// Parses `a..b` and `a..=b`, returning the bounds and whether `b` is included.
+fn parse_range(range: &str) -> Option<(f64, f64, bool)> {
+    let (start, end) = range.split_once("..")?;
+    let (end, inclusive) = match end.strip_prefix('=') {
+        Some(end) => (end, true),
+        None => (end, false),
+    };
+    Some((
+        start.trim().parse().ok()?,
+        end.trim().parse().ok()?,
+        inclusive,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+    use reqwest::{StatusCode, Version};
+
+    fn response(status: u16, body: &str) -> OwnedResponse {
+        OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: HeaderMap::new(),
+            body: body.as_bytes().to_vec(),
+            saved: None,
+            events: None,
+            truncated: false,
+        }
+    }
+
+    fn evaluate(text: &str, response: &OwnedResponse) -> Result<()> {
+        Assertion::parse(text).unwrap().evaluate(response)
+    }
+
+    #[test]
+    fn status_assertions() {
+        let created = response(201, "");
+        for text in &[
+            "status == 201",
+            "status != 200",
+            "status >= 201",
+            "status < 300",
+            "status in 200..299",
+            "status in 200..=201",
+        ] {
+            assert!(evaluate(text, &created).is_ok(), "{}", text);
+        }
+        let assertion = Assertion::parse("status in 200..201").unwrap();
+        assert_eq!(
+            assertion.evaluate(&created).unwrap_err().to_string(),
+            "got 201"
+        );
+        assert_eq!(assertion.to_string(), "status in 200..201");
+    }
+
+    #[test]
+    fn body_assertions() {
+        let ok = response(
+            200,
+            r#"{"items": [{"id": 42, "tags": ["a", "b"]}, {"id": 43, "name": "second"}], "total": 2}"#,
+        );
+        for text in &[
+            "body $.items[0].id == 42",
+            "body items[1].name == \"second\"",
+            "body items[1].name == second",
+            "body $.items[0].tags contains b",
+            "body $.items[1].name contains eco",
+            "body $.total exists",
+            "body $.error not exists",
+            "body $.items[2] not exists",
+            "body $.total <= 2",
+        ] {
+            assert!(evaluate(text, &ok).is_ok(), "{}", text);
+        }
+        assert_eq!(
+            evaluate("body $.items[1].id != 43", &ok)
+                .unwrap_err()
+                .to_string(),
+            "got 43"
+        );
+        assert_eq!(
+            evaluate("body $.items[1].name == first", &ok)
+                .unwrap_err()
+                .to_string(),
+            "got \"second\""
+        );
+        assert_eq!(
+            evaluate("body $.items[0].tags contains c", &ok)
+                .unwrap_err()
+                .to_string(),
+            "got [\"a\",\"b\"]"
+        );
+        assert_eq!(
+            evaluate("body $.items[0] not exists", &ok)
+                .unwrap_err()
+                .to_string(),
+            "got {\"id\":42,\"tags\":[\"a\",\"b\"]}"
+        );
+        assert_eq!(
+            evaluate("body $.items[3].id == 1", &ok)
+                .unwrap_err()
+                .to_string(),
+            "JSON path $.items[3].id not found: no element at index 3"
+        );
+        assert_eq!(
+            evaluate("body $.error exists", &ok)
+                .unwrap_err()
+                .to_string(),
+            "JSON path $.error not found: no key \"error\""
+        );
+    }
+
+    #[test]
+    fn non_json_body() {
+        let html = response(200, "<html></html>");
+        assert_eq!(
+            evaluate("body $.id exists", &html).unwrap_err().to_string(),
+            "response is not JSON"
+        );
+        assert!(evaluate("status == 200", &html).is_ok());
+    }
+
+    #[test]
+    fn invalid_assertions() {
+        assert_eq!(
+            Assertion::parse("status ~ 200").unwrap_err().to_string(),
+            "unknown assertion operator ~"
+        );
+        assert_eq!(
+            Assertion::parse("status ==").unwrap_err().to_string(),
+            "missing value in assertion status =="
+        );
+        assert_eq!(
+            Assertion::parse("status in 200-299")
+                .unwrap_err()
+                .to_string(),
+            "invalid range 200-299 in assertion"
+        );
+        assert_eq!(
+            Assertion::parse("body $.a exists 1")
+                .unwrap_err()
+                .to_string(),
+            "unexpected value in assertion body $.a exists 1"
+        );
+        assert!(Assertion::parse("body $.a[x] exists").is_err());
+        assert!(Assertion::parse("latency < 100").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// The percentiles of the latencies in the summary.
+const PERCENTILES: [u32; 4] = [50, 90, 95, 99];
+
+/// Why an execution got no response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    Timeout,
+    Connect,
+    Other,
+}
+
+/// Latencies and statuses of the executions of a request repeated many
+/// times with `--repeat`.
+///
+/// The statistics of the latencies are only of the successful executions:
+/// the ones failed, as they got no response or a 5xx one, are counted
+/// apart.
+#[derive(Default)]
+pub struct Benchmark {
+    latencies: Vec<Duration>,
+    statuses: BTreeMap<u16, usize>,
+    timeouts: usize,
+    connect_errors: usize,
+    other_errors: usize,
+    server_errors: usize,
+}
+
+impl Benchmark {
+    pub fn new() -> Benchmark {
+        Benchmark::default()
+    }
+
+    pub fn add_response(&mut self, status: u16, latency: Duration) {
+        match status {
+            500..=599 => self.server_errors += 1,
+            _ => self.latencies.push(latency),
+        }
+        *self.statuses.entry(status).or_insert(0) += 1;
+    }
+
+    /// Counts an execution that got no response, such as a connection error.
+    pub fn add_error(&mut self, failure: Failure) {
+        match failure {
+            Failure::Timeout => self.timeouts += 1,
+            Failure::Connect => self.connect_errors += 1,
+            Failure::Other => self.other_errors += 1,
+        }
+    }
+
+    fn errors(&self) -> usize {
+        self.timeouts + self.connect_errors + self.other_errors + self.server_errors
+    }
+
+    fn mean(&self) -> f64 {
+        milliseconds(&self.latencies.iter().sum()) / self.latencies.len() as f64
+    }
+
+    /// The population standard deviation.
+    fn standard_deviation(&self) -> f64 {
+        let mean = self.mean();
+        let variance = self
+            .latencies
+            .iter()
+            .map(|latency| (milliseconds(latency) - mean).powi(2))
+            .sum::<f64>()
+            / self.latencies.len() as f64;
+        variance.sqrt()
+    }
+
+    /// The latency that `percentile`% of the successful executions didn't
+    /// exceed, by the nearest-rank method, given the latencies sorted.
+    fn percentile(sorted: &[Duration], percentile: u32) -> Duration {
+        let rank = (sorted.len() * percentile as usize).div_ceil(100);
+        sorted[rank.max(1) - 1]
+    }
+}
+
+fn milliseconds(duration: &Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// A `key: value` line for each statistic, latencies in milliseconds, easy
+/// to parse and to compare between runs.
+impl fmt::Display for Benchmark {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(dest, "===== Summary")?;
+        let errors = self.errors();
+        writeln!(dest, "iterations: {}", self.latencies.len() + errors)?;
+        writeln!(dest, "errors: {}", errors)?;
+        if errors > 0 {
+            writeln!(dest, "errors_timeout: {}", self.timeouts)?;
+            writeln!(dest, "errors_connect: {}", self.connect_errors)?;
+            writeln!(dest, "errors_other: {}", self.other_errors)?;
+            writeln!(dest, "errors_5xx: {}", self.server_errors)?;
+        }
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        if let (Some(min), Some(max)) = (sorted.first(), sorted.last()) {
+            writeln!(dest, "min_ms: {:.3}", milliseconds(min))?;
+            writeln!(dest, "mean_ms: {:.3}", self.mean())?;
+            writeln!(dest, "max_ms: {:.3}", milliseconds(max))?;
+            writeln!(dest, "stddev_ms: {:.3}", self.standard_deviation())?;
+            for percentile in PERCENTILES {
+                let latency = Benchmark::percentile(&sorted, percentile);
+                writeln!(dest, "p{}_ms: {:.3}", percentile, milliseconds(&latency))?;
+            }
+        }
+        for (status, count) in &self.statuses {
+            writeln!(dest, "status_{}: {}", status, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut benchmark = Benchmark::new();
+        benchmark.add_response(200, Duration::from_millis(10));
+        benchmark.add_response(200, Duration::from_millis(20));
+        benchmark.add_response(404, Duration::from_millis(30));
+        benchmark.add_response(503, Duration::from_millis(40));
+        benchmark.add_error(Failure::Connect);
+        assert_eq!(
+            benchmark.to_string(),
+            "===== Summary
+iterations: 5
+errors: 2
+errors_timeout: 0
+errors_connect: 1
+errors_other: 0
+errors_5xx: 1
+min_ms: 10.000
+mean_ms: 20.000
+max_ms: 30.000
+stddev_ms: 8.165
+p50_ms: 20.000
+p90_ms: 30.000
+p95_ms: 30.000
+p99_ms: 30.000
+status_200: 2
+status_404: 1
+status_503: 1
+"
+        );
+    }
+
+    #[test]
+    fn percentiles() {
+        let latencies: Vec<Duration> = (1..=200).map(Duration::from_millis).collect();
+        let percentile = |p| Benchmark::percentile(&latencies, p);
+        assert_eq!(percentile(50), Duration::from_millis(100));
+        assert_eq!(percentile(90), Duration::from_millis(180));
+        assert_eq!(percentile(99), Duration::from_millis(198));
+        assert_eq!(percentile(100), Duration::from_millis(200));
+        let single = [Duration::from_millis(7)];
+        assert_eq!(Benchmark::percentile(&single, 50), single[0]);
+        assert_eq!(Benchmark::percentile(&single, 0), single[0]);
+    }
+
+    #[test]
+    fn only_errors() {
+        let mut benchmark = Benchmark::new();
+        benchmark.add_error(Failure::Timeout);
+        assert_eq!(
+            benchmark.to_string(),
+            "===== Summary\niterations: 1\nerrors: 1\nerrors_timeout: 1\nerrors_connect: 0\nerrors_other: 0\nerrors_5xx: 0\n"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use crate::jsonpath;
+use crate::request::{OwnedResponse, Request};
+use anyhow::Result;
+use regex::{bytes, Captures, Regex};
+use reqwest::header::HeaderName;
+use std::collections::HashMap;
+
+/// `{{<name>.response.<path>}}`
+const REFERENCE: &str = r"\{\{\s*(?P<name>[\w-]+)\.response\.(?P<path>[^}]+?)\s*\}\}";
+
+/// Resolves `{{<name>.response.<part>}}` placeholders against the responses
+/// of the requests already executed in this run.
+///
+/// `<part>` can be:
+/// - `status`, the response status code;
+/// - `headers.<Header-Name>`, the first value of a (case-insensitive) header,
+///   or `headers.<Header-Name>[i]` for the following ones;
+/// - `body.*`, the whole body, or `body.<path>` with a JSONPath such as `$.token`.
+///
+/// The body of the request can hold any bytes, and the whole body of a
+/// response is put in it as it is, even when it isn't text.
+///
+/// # Examples
+///
+/// ```
+/// use httpclient::request::{OwnedResponse, Request};
+/// use std::collections::HashMap;
+///
+/// let mut responses = HashMap::new();
+/// responses.insert(
+///     "login".to_string(),
+///     OwnedResponse {
+///         version: reqwest::Version::HTTP_11,
+///         status: reqwest::StatusCode::OK,
+///         headers: reqwest::header::HeaderMap::new(),
+///         body: br#"{"token": "abc"}"#.to_vec(),
+///         saved: None,
+///         events: None,
+///         truncated: false,
+///     },
+/// );
+/// let mut req = Request::new();
+/// req.url = "https://example.com".to_string();
+/// req.headers.insert(
+///     "Authorization".to_string(),
+///     "Bearer {{login.response.body.$.token}}".to_string(),
+/// );
+/// let resolved = httpclient::chain::resolve(&req, &responses).unwrap();
+/// assert_eq!(resolved.headers["Authorization"], "Bearer abc");
+/// ```
+pub fn resolve(req: &Request, responses: &HashMap<String, OwnedResponse>) -> Result<Request> {
+    let mut resolved = req.clone();
+    resolved.url = resolve_str(&req.url, responses)?;
+    for param in resolved.url_parameters.iter_mut() {
+        if let Some(value) = &param.1 {
+            param.1 = Some(resolve_str(value, responses)?);
+        }
+    }
+    for value in resolved.headers.values_mut() {
+        *value = resolve_str(value, responses)?;
+    }
+    for cookie in resolved.cookies.iter_mut() {
+        let name = resolve_str(&cookie.0, responses)?;
+        let value = resolve_str(&cookie.1, responses)?;
+        // a whole `Cookie: {{login.response.headers.set-cookie}}`, sent back
+        // without the attributes of the `Set-Cookie`
+        let whole = cookie.1.is_empty() && cookie.0.contains("{{");
+        *cookie = match name.split(';').next().and_then(|c| c.split_once('=')) {
+            Some((name, value)) if whole => (name.trim().to_string(), value.trim().to_string()),
+            _ => (name, value),
+        };
+    }
+    resolved.body = resolve_bytes(&req.body, responses)?;
+    Ok(resolved)
+}
+
+/// The names of the requests whose responses are referenced by a request.
+pub fn references(req: &Request) -> Vec<String> {
+    let re = Regex::new(REFERENCE).unwrap();
+    let body = String::from_utf8_lossy(&req.body);
+    let mut texts: Vec<&str> = vec![&req.url, &body];
+    texts.extend(
+        req.url_parameters
+            .iter()
+            .filter_map(|(_, value)| value.as_deref()),
+    );
+    texts.extend(req.headers.values().map(|value| value.as_str()));
+    texts.extend(
+        req.cookies
+            .iter()
+            .flat_map(|(name, value)| vec![name.as_str(), value.as_str()]),
+    );
+    let mut names: Vec<String> = Vec::new();
+    for caps in texts.iter().flat_map(|text| re.captures_iter(text)) {
+        if !names.iter().any(|name| name == &caps["name"]) {
+            names.push(caps["name"].to_string());
+        }
+    }
+    names
+}
+
+fn resolve_str(candidate: &str, responses: &HashMap<String, OwnedResponse>) -> Result<String> {
+    let re = Regex::new(REFERENCE).unwrap();
+    let mut error = None;
+    let result = re.replace_all(candidate, |caps: &Captures| {
+        match lookup(&caps["name"], &caps["path"], responses) {
+            Ok(value) => value,
+            Err(e) => {
+                error.get_or_insert(e);
+                String::new()
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(result.to_string()),
+    }
+}
+
+/// Like `resolve_str`, for a body.
+fn resolve_bytes(candidate: &[u8], responses: &HashMap<String, OwnedResponse>) -> Result<Vec<u8>> {
+    let re = bytes::Regex::new(REFERENCE).unwrap();
+    let mut error = None;
+    let result = re.replace_all(candidate, |caps: &bytes::Captures| {
+        let name = String::from_utf8_lossy(&caps["name"]);
+        let path = String::from_utf8_lossy(&caps["path"]);
+        let value = match (responses.get(name.as_ref()), path.as_ref()) {
+            (Some(response), "body") | (Some(response), "body.*") => Ok(response.body.clone()),
+            _ => lookup(&name, &path, responses).map(String::into_bytes),
+        };
+        value.unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            Vec::new()
+        })
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(result.into_owned()),
+    }
+}
+
+fn lookup(name: &str, path: &str, responses: &HashMap<String, OwnedResponse>) -> Result<String> {
+    let response = responses.get(name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "request \"{}\" is referenced but has not been executed yet",
+            name
+        )
+    })?;
+    if path == "status" {
+        return Ok(response.status.as_u16().to_string());
+    }
+    if let Some(header) = path.strip_prefix("headers.") {
+        return lookup_header(name, header, response);
+    }
+    let body_path = path
+        .strip_prefix("body")
+        .ok_or_else(|| anyhow::anyhow!("unsupported reference {}.response.{}", name, path))?;
+    match body_path {
+        "" | ".*" => Ok(response.text()),
+        _ => {
+            let json_path = body_path.strip_prefix('.').unwrap_or(body_path);
+            let document = json::parse(&response.text()).map_err(|_| {
+                anyhow::anyhow!(
+                    "response body of request \"{}\" is not JSON, cannot evaluate {}",
+                    name,
+                    json_path
+                )
+            })?;
+            let value = jsonpath::lookup(&document, json_path)
+                .map_err(|e| anyhow::anyhow!("in response of request \"{}\": {}", name, e))?;
+            Ok(jsonpath::to_plain_string(value))
+        }
+    }
+}
+
+fn lookup_header(name: &str, header: &str, response: &OwnedResponse) -> Result<String> {
+    let (header_name, index) = match header.strip_suffix(']').and_then(|h| h.split_once('[')) {
+        Some((header_name, index)) => (
+            header_name,
+            index.trim().parse::<usize>().map_err(|_| {
+                anyhow::anyhow!(
+                    "invalid header index in {}.response.headers.{}",
+                    name,
+                    header
+                )
+            })?,
+        ),
+        None => (header, 0),
+    };
+    let header_name = header_name.trim();
+    let value = response
+        .headers
+        .get_all(
+            HeaderName::from_bytes(header_name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("invalid header name {}", header_name))?,
+        )
+        .iter()
+        .nth(index)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "response of request \"{}\" has no header {}{}",
+                name,
+                header_name,
+                if index > 0 {
+                    format!(" at index {}", index)
+                } else {
+                    "".to_string()
+                }
+            )
+        })?;
+    Ok(value.to_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+    use reqwest::{StatusCode, Version};
+
+    fn responses(body: &str) -> HashMap<String, OwnedResponse> {
+        let mut headers = HeaderMap::new();
+        headers.insert("location", "/users/7".parse().unwrap());
+        headers.append("set-cookie", "a=1".parse().unwrap());
+        headers.append("set-cookie", "b=2".parse().unwrap());
+        let mut responses = HashMap::new();
+        responses.insert(
+            "login".to_string(),
+            OwnedResponse {
+                version: Version::HTTP_11,
+                status: StatusCode::CREATED,
+                headers,
+                body: body.as_bytes().to_vec(),
+                saved: None,
+                events: None,
+                truncated: false,
+            },
+        );
+        responses
+    }
+
+    #[test]
+    fn body_references() {
+        let responses = responses(r#"{"user": {"id": 7}, "token": "abc"}"#);
+        let mut req = Request::new();
+        req.url = "https://example.com/users/{{login.response.body.$.user.id}}".to_string();
+        req.url_parameters.push((
+            "t".to_string(),
+            Some("{{login.response.body.$.token}}".to_string()),
+        ));
+        req.body = b"{{login.response.body.*}}".to_vec();
+        let resolved = resolve(&req, &responses).unwrap();
+        assert_eq!(resolved.url, "https://example.com/users/7");
+        assert_eq!(resolved.url_parameters[0].1, Some("abc".to_string()));
+        assert_eq!(resolved.body, br#"{"user": {"id": 7}, "token": "abc"}"#);
+    }
+
+    #[test]
+    fn binary_bodies() {
+        let mut responses = responses("");
+        responses.get_mut("login").unwrap().body = vec![0xff, 0x00, 0xfe];
+        let mut req = Request::new();
+        req.body = b"\x89PNG {{login.response.body}} {{login.response.status}}".to_vec();
+        assert_eq!(
+            resolve(&req, &responses).unwrap().body,
+            b"\x89PNG \xff\x00\xfe 201"
+        );
+        assert_eq!(references(&req), vec!["login"]);
+    }
+
+    #[test]
+    fn status_and_header_references() {
+        let responses = responses("");
+        let mut req = Request::new();
+        req.url = "https://example.com{{login.response.headers.Location}}".to_string();
+        req.headers.insert(
+            "Cookie".to_string(),
+            "{{login.response.headers.set-cookie}}; {{login.response.headers.Set-Cookie[1]}}"
+                .to_string(),
+        );
+        req.body = b"{{login.response.status}}".to_vec();
+        let resolved = resolve(&req, &responses).unwrap();
+        assert_eq!(resolved.url, "https://example.com/users/7");
+        assert_eq!(resolved.headers["Cookie"], "a=1; b=2");
+        assert_eq!(resolved.body, b"201");
+
+        req.body = b"{{login.response.headers.ETag}}".to_vec();
+        assert_eq!(
+            resolve(&req, &responses).err().unwrap().to_string(),
+            "response of request \"login\" has no header ETag"
+        );
+        req.body = b"{{login.response.headers.Set-Cookie[2]}}".to_vec();
+        assert_eq!(
+            resolve(&req, &responses).err().unwrap().to_string(),
+            "response of request \"login\" has no header Set-Cookie at index 2"
+        );
+    }
+
+    #[test]
+    fn chained_cookies() {
+        let responses = responses(r#"{"sid": "xyz"}"#);
+        let contents = "GET https://example.com
+Cookie: {{login.response.headers.set-cookie}}; theme=dark
+# @cookie sid={{login.response.body.$.sid}}";
+        let req = &crate::worker::FileParser::new()
+            .parse_many(contents)
+            .unwrap()[0];
+        assert_eq!(references(req), vec!["login"]);
+        assert_eq!(
+            resolve(req, &responses).unwrap().cookies,
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+                ("sid".to_string(), "xyz".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn referenced_requests() {
+        let mut req = Request::new();
+        req.url = "https://example.com/users/{{login.response.body.$.id}}".to_string();
+        req.headers.insert(
+            "Authorization".to_string(),
+            "Bearer {{ token.response.body.$.value }}".to_string(),
+        );
+        req.body = b"{{login.response.status}} {{USER}}".to_vec();
+        let mut names = references(&req);
+        names.sort();
+        assert_eq!(names, vec!["login", "token"]);
+        assert!(references(&Request::new()).is_empty());
+    }
+
+    #[test]
+    fn missing_request_or_path() {
+        let responses = responses(r#"{"token": "abc"}"#);
+        let mut req = Request::new();
+        req.body = b"{{other.response.body.$.token}}".to_vec();
+        assert_eq!(
+            resolve(&req, &responses).err().unwrap().to_string(),
+            "request \"other\" is referenced but has not been executed yet"
+        );
+        req.body = b"{{login.response.body.$.missing}}".to_vec();
+        assert_eq!(
+            resolve(&req, &responses).err().unwrap().to_string(),
+            "in response of request \"login\": JSON path $.missing not found: no key \"missing\""
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! The colors of the responses and requests printed with `-v` and `-vv`, as
+//! ANSI escape codes; the bodies printed alone are never colored, so that
+//! piping them stays clean.
+
+use reqwest::StatusCode;
+use std::env;
+use std::ffi::OsStr;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const CYAN: &str = "\x1b[36m";
+
+/// Whether to color stdout, for `--color always`, `never` or `auto`: only
+/// when it's a terminal, and `NO_COLOR` isn't set.
+pub fn enabled(choice: &str) -> bool {
+    enabled_with(
+        choice,
+        env::var_os("NO_COLOR").as_deref(),
+        atty::is(atty::Stream::Stdout),
+    )
+}
+
+fn enabled_with(choice: &str, no_color: Option<&OsStr>, terminal: bool) -> bool {
+    match choice {
+        "always" => true,
+        "never" => false,
+        // an empty NO_COLOR doesn't count
+        _ => terminal && !matches!(no_color, Some(value) if !value.is_empty()),
+    }
+}
+
+fn paint(text: &str, code: &str, color: bool) -> String {
+    match color {
+        true => format!("{}{}{}", code, text, RESET),
+        false => text.to_string(),
+    }
+}
+
+/// The status line of a response, green for a success, cyan for a
+/// redirect, yellow for a client error and red for a server one.
+pub fn status(text: &str, status: StatusCode, color: bool) -> String {
+    let code = match status.as_u16() {
+        200..=299 => GREEN,
+        300..=399 => CYAN,
+        400..=499 => YELLOW,
+        500..=599 => RED,
+        _ => return text.to_string(),
+    };
+    paint(text, code, color)
+}
+
+/// A header name, dimmed.
+pub fn header_name(name: &str, color: bool) -> String {
+    paint(name, DIM, color)
+}
+
+/// Pretty-printed JSON, as `json::stringify_pretty` writes it, with its keys
+/// highlighted: the strings starting a line and followed by a colon.
+pub fn json_keys(pretty: &str, color: bool) -> String {
+    if !color {
+        return pretty.to_string();
+    }
+    let mut colored = String::with_capacity(pretty.len());
+    for (i, line) in pretty.split('\n').enumerate() {
+        if i > 0 {
+            colored.push('\n');
+        }
+        let indent = line.len() - line.trim_start().len();
+        match key_length(&line[indent..]) {
+            Some(length) => {
+                let (key, rest) = line[indent..].split_at(length);
+                colored.push_str(&line[..indent]);
+                colored.push_str(&paint(key, BLUE, true));
+                colored.push_str(rest);
+            }
+            None => colored.push_str(line),
+        }
+    }
+    colored
+}
+
+/// The length of the JSON string at the start of `text`, quotes included,
+/// if it's the key of an object member.
+fn key_length(text: &str) -> Option<usize> {
+    if !text.starts_with('"') {
+        return None;
+    }
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' if text[i + 1..].starts_with(':') => return Some(i + 1),
+            '"' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A request as printed with `-vv`: its first line in bold and the names of
+/// its headers dimmed.
+pub fn request(dump: &str, color: bool) -> String {
+    if !color {
+        return dump.to_string();
+    }
+    let mut colored = String::with_capacity(dump.len());
+    let mut headers = false;
+    for (i, line) in dump.split('\n').enumerate() {
+        if i > 0 {
+            colored.push('\n');
+        }
+        match line.find(": ") {
+            _ if i == 0 => colored.push_str(&paint(line, BOLD, true)),
+            Some(colon) if headers => {
+                let name = line[..colon].trim_start();
+                colored.push_str(&line[..colon - name.len()]);
+                colored.push_str(&header_name(name, true));
+                colored.push_str(&line[colon..]);
+            }
+            _ => {
+                // the headers are listed until the first empty line
+                headers = match line {
+                    "headers:" => true,
+                    "" => false,
+                    _ => headers,
+                };
+                colored.push_str(line);
+            }
+        }
+    }
+    colored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choices() {
+        assert!(enabled_with("always", Some(OsStr::new("1")), false));
+        assert!(!enabled_with("never", None, true));
+        assert!(enabled_with("auto", None, true));
+        assert!(!enabled_with("auto", None, false));
+        assert!(!enabled_with("auto", Some(OsStr::new("1")), true));
+        assert!(enabled_with("auto", Some(OsStr::new("")), true));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            status("HTTP/1.1 404 Not Found", StatusCode::NOT_FOUND, true),
+            "\x1b[33mHTTP/1.1 404 Not Found\x1b[0m"
+        );
+        assert_eq!(
+            status("HTTP/1.1 500", StatusCode::INTERNAL_SERVER_ERROR, false),
+            "HTTP/1.1 500"
+        );
+        assert_eq!(
+            json_keys(
+                "{\n  \"a\\\":\": \"b\",\n  \"c\": [\n    \"d\"\n  ]\n}",
+                true
+            ),
+            "{\n  \x1b[34m\"a\\\":\"\x1b[0m: \"b\",\n  \x1b[34m\"c\"\x1b[0m: [\n    \"d\"\n  ]\n}"
+        );
+        assert_eq!(
+            request(
+                "GET http://a/ HTTP/1.1\nheaders:\n   Accept: \"*/*\"\n\nbody:\nkey: value",
+                true
+            ),
+            "\x1b[1mGET http://a/ HTTP/1.1\x1b[0m\nheaders:\n   \x1b[2mAccept\x1b[0m: \"*/*\"\n\nbody:\nkey: value"
+        );
+    }
+}
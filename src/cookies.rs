@@ -0,0 +1,477 @@
+use crate::client::ClientOptions;
+use crate::request::Request;
+use crate::transport::{HttpTransport, Response};
+use anyhow::{Context, Result};
+use reqwest::{header, Url};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A cookie set by a response, as stored in the jar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// the host of the response, or the `Domain` attribute
+    pub domain: String,
+    /// whether it's sent to the subdomains too, as with a `Domain` attribute
+    pub include_subdomains: bool,
+    pub path: String,
+    /// whether it's sent only over https
+    pub secure: bool,
+    /// seconds since the epoch, none for the session cookies
+    pub expires: Option<u64>,
+}
+
+impl Cookie {
+    /// Whether the cookie is sent to `url` at `now`, following its domain,
+    /// path, `Secure` attribute and expiry.
+    fn matches(&self, url: &Url, now: u64) -> bool {
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+        domain_matches
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.expired(now)
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+}
+
+/// The cookies set by the responses, sent back with the following requests
+/// as a browser would; `--cookie-jar` keeps them in a JSON file between
+/// runs.
+#[derive(Debug, Default)]
+pub struct Jar {
+    cookies: Vec<Cookie>,
+}
+
+impl Jar {
+    /// Reads a jar written by `save`; a missing file is an empty jar.
+    pub fn load(path: &Path) -> Result<Jar> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Jar::default()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("unable to read the cookie jar {}", path.display()))
+            }
+        };
+        let invalid = || anyhow::anyhow!("invalid cookie jar {}", path.display());
+        let document = json::parse(&text).map_err(|_| invalid())?;
+        let mut cookies = Vec::new();
+        for cookie in document.members() {
+            let text = |key: &str| cookie[key].as_str().map(|s| s.to_string());
+            cookies.push(Cookie {
+                name: text("name").ok_or_else(invalid)?,
+                value: text("value").ok_or_else(invalid)?,
+                domain: text("domain").ok_or_else(invalid)?,
+                include_subdomains: cookie["include_subdomains"].as_bool().unwrap_or(false),
+                path: text("path").unwrap_or_else(|| "/".to_string()),
+                secure: cookie["secure"].as_bool().unwrap_or(false),
+                expires: cookie["expires"].as_u64(),
+            });
+        }
+        Ok(Jar { cookies })
+    }
+
+    /// Writes the cookies not expired yet, the session ones too.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let now = now();
+        let mut document = json::JsonValue::new_array();
+        for cookie in self.cookies.iter().filter(|c| !c.expired(now)) {
+            let mut object = json::JsonValue::new_object();
+            object["name"] = cookie.name.as_str().into();
+            object["value"] = cookie.value.as_str().into();
+            object["domain"] = cookie.domain.as_str().into();
+            object["include_subdomains"] = cookie.include_subdomains.into();
+            object["path"] = cookie.path.as_str().into();
+            object["secure"] = cookie.secure.into();
+            object["expires"] = cookie.expires.into();
+            document.push(object)?;
+        }
+        fs::write(path, json::stringify_pretty(document, 2))
+            .with_context(|| format!("unable to write the cookie jar {}", path.display()))
+    }
+
+    /// Stores the cookies of the `Set-Cookie` headers of a response from
+    /// `url`, replacing the ones with the same name, domain and path; the
+    /// expired ones are removed instead.
+    pub fn store(&mut self, url: &Url, headers: &header::HeaderMap, now: u64) {
+        for value in headers.get_all(header::SET_COOKIE) {
+            let cookie = match value.to_str().ok().and_then(|v| parse(url, v, now)) {
+                Some(cookie) => cookie,
+                None => continue,
+            };
+            self.cookies.retain(|c| {
+                (&c.name, &c.domain, &c.path) != (&cookie.name, &cookie.domain, &cookie.path)
+            });
+            if !cookie.expired(now) {
+                self.cookies.push(cookie);
+            }
+        }
+    }
+
+    /// The cookies to send to `url`, the ones with longer paths first.
+    pub fn matching(&self, url: &Url, now: u64) -> Vec<&Cookie> {
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url, now))
+            .collect();
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        cookies
+    }
+
+    /// Adds to the request the cookies for `url`, but the ones it sets
+    /// itself with `Cookie` or `# @cookie`.
+    pub fn attach(&self, req: &mut Request, url: &Url) {
+        for cookie in self.matching(url, now()) {
+            if !req.cookies.iter().any(|(name, _)| *name == cookie.name) {
+                req.cookies
+                    .push((cookie.name.to_string(), cookie.value.to_string()));
+            }
+        }
+    }
+}
+
+/// Sends the request through `transport` with the cookies of the jar, if
+/// any, then stores the ones set by the response.
+pub fn send(
+    req: &Request,
+    transport: &dyn HttpTransport,
+    options: &ClientOptions,
+    timeout: Duration,
+    jar: Option<&Mutex<Jar>>,
+) -> Result<Response> {
+    let jar = match jar {
+        Some(jar) => jar,
+        None => return transport.send(req, options, timeout),
+    };
+    let mut req = req.clone();
+    let url = req.target_url()?;
+    jar.lock().unwrap().attach(&mut req, &url);
+    let response = transport.send(&req, options, timeout)?;
+    jar.lock().unwrap().store(&url, response.headers(), now());
+    Ok(response)
+}
+
+/// Seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses a `Set-Cookie` header of a response from `url`, as in RFC 6265:
+/// `None` if malformed, or for a domain `url` can't set cookies for.
+fn parse(url: &Url, header: &str, now: u64) -> Option<Cookie> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().to_string(),
+        domain: host.clone(),
+        include_subdomains: false,
+        path: default_path(url.path()),
+        secure: false,
+        expires: None,
+    };
+    let mut max_age = None;
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if domain != host && !host.ends_with(&format!(".{}", domain)) {
+                    return None;
+                }
+                // not for a top-level domain, like `com`
+                if domain != host && !domain.contains('.') {
+                    return None;
+                }
+                // a `Domain` equal to a single-label host, like
+                // `localhost`, is ignored
+                cookie.include_subdomains = domain.contains('.');
+                cookie.domain = domain;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "secure" => cookie.secure = true,
+            "max-age" => {
+                if let Ok(seconds) = value.parse::<i64>() {
+                    max_age = Some(now.saturating_add(seconds.max(0) as u64));
+                }
+            }
+            "expires" => {
+                if let Some(expires) = parse_date(value) {
+                    cookie.expires = Some(expires);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Max-Age wins over Expires
+    if max_age.is_some() {
+        cookie.expires = max_age;
+    }
+    Some(cookie)
+}
+
+/// The directory of the path of the URL setting a cookie without `Path`.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => path[..index].to_string(),
+    }
+}
+
+/// Whether a request path is in the path of a cookie.
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// Parses the date of `Expires`, in seconds since the epoch, leniently as
+/// browsers do: `Wed, 21 Oct 2026 07:28:00 GMT` and `Wed, 21-Oct-26
+/// 07:28:00 GMT` are the same date.
+pub(crate) fn parse_date(text: &str) -> Option<u64> {
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    for token in text
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != ':')
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token.len() <= 4 && token.chars().all(|c| c.is_ascii_digit());
+        if time.is_none() && token.contains(':') {
+            let fields: Vec<Option<u64>> = token.split(':').map(|f| f.parse().ok()).collect();
+            if let [Some(hours), Some(minutes), Some(seconds)] = fields[..] {
+                time = Some(hours * 3600 + minutes * 60 + seconds);
+            }
+        } else if day.is_none() && digits && token.len() <= 2 {
+            day = token.parse::<i64>().ok();
+        } else if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            month = months
+                .iter()
+                .position(|m| *m == prefix)
+                .map(|m| m as i64 + 1);
+        } else if year.is_none() && digits {
+            year = token.parse::<i64>().ok().map(|year| match year {
+                0..=69 => year + 2000,
+                70..=99 => year + 1900,
+                _ => year,
+            });
+        }
+    }
+    let (time, day, month, year) = (time?, day?, month?, year?);
+    if !(1..=31).contains(&day) || time >= 86400 {
+        return None;
+    }
+    // days since the epoch of a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    // the dates before the epoch are just expired
+    Some((days.max(0) as u64) * 86400 + time)
+}
+
+/// Formats a date, in seconds since the epoch, as the HTTP headers do:
+/// `Wed, 21 Oct 2026 07:28:00 GMT`.
+pub(crate) fn format_date(seconds: u64) -> String {
+    let weekdays = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    let months = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        weekdays[(days % 7) as usize],
+        day,
+        months[month as usize - 1],
+        year,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Formats a date, in seconds since the epoch, as ISO 8601 in UTC:
+/// `2026-10-21T07:28:00Z`.
+pub(crate) fn format_iso_date(seconds: u64) -> String {
+    let time = seconds % 86400;
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// The year, month and day of the days since the epoch, see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_cookies(values: &[&str]) -> header::HeaderMap {
+        let mut headers = header::HeaderMap::new();
+        for value in values {
+            headers.append(header::SET_COOKIE, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn sent(jar: &Jar, url: &str, now: u64) -> Vec<String> {
+        jar.matching(&Url::parse(url).unwrap(), now)
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect()
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_date("Wed, 21 Oct 2026 07:28:00 GMT"),
+            Some(1792567680)
+        );
+        assert_eq!(parse_date("Wed, 21-Oct-26 07:28:00 GMT"), Some(1792567680));
+        assert_eq!(
+            parse_date("Sun, 29 Feb 2004 12:00:01 GMT"),
+            Some(1078056001)
+        );
+        assert_eq!(parse_date("tomorrow"), None);
+        assert_eq!(format_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_iso_date(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_date(1792567680), "Wed, 21 Oct 2026 07:28:00 GMT");
+        assert_eq!(format_date(1078056001), "Sun, 29 Feb 2004 12:00:01 GMT");
+        assert_eq!(format_iso_date(1078056001), "2004-02-29T12:00:01Z");
+        assert_eq!(format_date(951825600), "Tue, 29 Feb 2000 12:00:00 GMT");
+    }
+
+    #[test]
+    fn scopes() {
+        let url = Url::parse("https://api.example.com/v1/login").unwrap();
+        let mut jar = Jar::default();
+        jar.store(
+            &url,
+            &set_cookies(&[
+                "session=abc; Path=/; HttpOnly",
+                "theme=dark; Domain=.example.com; Path=/",
+                "scoped=1",
+                "secret=2; Secure",
+                "other=3; Domain=example.org",
+                "tld=4; Domain=com",
+            ]),
+            1000,
+        );
+        assert_eq!(
+            sent(&jar, "https://api.example.com/v1/users", 1000),
+            ["scoped=1", "secret=2", "session=abc", "theme=dark"]
+        );
+        // the path of `scoped` is /v1, only `theme` goes to the other
+        // subdomains
+        assert_eq!(sent(&jar, "https://www.example.com/", 1000), ["theme=dark"]);
+        assert_eq!(
+            sent(&jar, "http://api.example.com/v10", 1000),
+            ["session=abc", "theme=dark"]
+        );
+        assert!(sent(&jar, "https://example.org/", 1000).is_empty());
+    }
+
+    #[test]
+    fn expiry() {
+        let url = Url::parse("http://localhost:8080/").unwrap();
+        let mut jar = Jar::default();
+        jar.store(
+            &url,
+            &set_cookies(&[
+                "short=1; Max-Age=60",
+                "dated=2; Expires=Thu, 01 Jan 1970 00:30:00 GMT",
+                // Max-Age wins
+                "both=3; Max-Age=3600; Expires=Thu, 01 Jan 1970 00:00:01 GMT",
+            ]),
+            1000,
+        );
+        assert_eq!(
+            sent(&jar, "http://localhost:8080/", 1000),
+            ["short=1", "dated=2", "both=3"]
+        );
+        assert_eq!(sent(&jar, "http://localhost:8080/", 1900), ["both=3"]);
+        // deleted by an expired cookie with the same name
+        jar.store(&url, &set_cookies(&["both=; Max-Age=0"]), 1000);
+        assert_eq!(
+            sent(&jar, "http://localhost:8080/", 1000),
+            ["short=1", "dated=2"]
+        );
+    }
+
+    #[test]
+    fn persisted() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let mut jar = Jar::default();
+        jar.store(
+            &url,
+            &set_cookies(&["session=abc", "kept=1; Max-Age=3600"]),
+            now(),
+        );
+        let path = std::env::temp_dir().join(format!("httpclient-cookies-{}.json", now()));
+        jar.save(&path).unwrap();
+        let loaded = Jar::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.cookies, jar.cookies);
+        assert!(Jar::load(&path).unwrap().cookies.is_empty());
+
+        let mut req = Request::new();
+        req.cookies
+            .push(("session".to_string(), "mine".to_string()));
+        loaded.attach(&mut req, &url);
+        assert_eq!(
+            req.cookies,
+            vec![
+                ("session".to_string(), "mine".to_string()),
+                ("kept".to_string(), "1".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+/// Parses the content of a `.env` file: `KEY=value` lines, optionally
+/// prefixed by `export`, with `#` comments.
+///
+/// Values can be quoted with `"` (where `\n`, `\"` and `\\` are unescaped)
+/// or `'` (taken literally); unquoted values end at the first ` #`.
+///
+/// # Examples
+///
+/// ```
+/// let vars = httpclient::dotenv::parse("# local secrets\nAPI_KEY=\"a#b\" # quoted\nexport USER=me");
+/// assert_eq!(vars["API_KEY"], "a#b");
+/// assert_eq!(vars["USER"], "me");
+/// ```
+pub fn parse(content: &str) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        if key.is_empty() {
+            continue;
+        }
+        variables.insert(key.to_string(), parse_value(value));
+    }
+    variables
+}
+
+fn parse_value(value: &str) -> String {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some(escaped) => result.push(escaped),
+                    None => result.push('\\'),
+                },
+                _ => result.push(c),
+            }
+        }
+        return result;
+    }
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return quoted.split('\'').next().unwrap_or("").to_string();
+    }
+    match value.find(" #") {
+        Some(comment) => value[..comment].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let vars = parse(
+            "A=plain value # comment
+B=\"double \\\"quoted\\\" # not a comment\\nnext\"
+C='single $quoted # too'
+ export D = spaced
+E=
+not a variable
+=no key",
+        );
+        assert_eq!(vars["A"], "plain value");
+        assert_eq!(vars["B"], "double \"quoted\" # not a comment\nnext");
+        assert_eq!(vars["C"], "single $quoted # too");
+        assert_eq!(vars["D"], "spaced");
+        assert_eq!(vars["E"], "");
+        assert_eq!(vars.len(), 5);
+    }
+}
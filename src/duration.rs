@@ -0,0 +1,103 @@
+use anyhow::Result;
+use std::time::Duration;
+
+/// Parses a duration such as `500ms`, `2s`, `1.5m` or `1h`; a number without
+/// unit is in seconds.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// assert_eq!(httpclient::duration::parse("500ms").unwrap(), Duration::from_millis(500));
+/// assert_eq!(httpclient::duration::parse("2").unwrap(), Duration::from_secs(2));
+/// ```
+pub fn parse(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let seconds_per_unit = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(invalid(value)),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid(value))?;
+    let seconds = number * seconds_per_unit;
+    if seconds >= u64::MAX as f64 {
+        return Err(anyhow::anyhow!("invalid duration \"{}\": too long", value));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Formats a duration as `parse` reads it, in the largest unit it's a whole
+/// number of, like `500ms`, `30s` or `2m`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// assert_eq!(httpclient::duration::format(Duration::from_millis(1500)), "1500ms");
+/// assert_eq!(httpclient::duration::format(Duration::from_secs(600)), "10m");
+/// ```
+pub fn format(duration: Duration) -> String {
+    let milliseconds = duration.as_millis();
+    if Duration::from_millis(milliseconds as u64) != duration {
+        return format!("{}s", duration.as_secs_f64());
+    }
+    if milliseconds == 0 {
+        return "0s".to_string();
+    }
+    let (unit, size) = [("h", 3_600_000), ("m", 60_000), ("s", 1000), ("ms", 1)]
+        .iter()
+        .find(|(_, size)| milliseconds / size * size == milliseconds)
+        .unwrap();
+    format!("{}{}", milliseconds / size, unit)
+}
+
+fn invalid(value: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "invalid duration \"{}\": expected a number followed by ms, s, m or h",
+        value
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse("0").unwrap(), Duration::from_secs(0));
+        assert_eq!(parse("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse(" 2 m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse("1h").unwrap(), Duration::from_secs(3600));
+        for value in ["", "ms", "-1s", "1d", "1.2.3s"] {
+            assert_eq!(
+                parse(value).err().unwrap().to_string(),
+                format!(
+                    "invalid duration \"{}\": expected a number followed by ms, s, m or h",
+                    value.trim()
+                )
+            );
+        }
+        assert_eq!(
+            parse("99999999999999999999999h").err().unwrap().to_string(),
+            "invalid duration \"99999999999999999999999h\": too long"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        for value in ["0s", "500ms", "1500ms", "30s", "90s", "2m", "1h", "36h"] {
+            assert_eq!(format(parse(value).unwrap()), value);
+        }
+        assert_eq!(format(Duration::from_secs(120)), "2m");
+        assert_eq!(format(Duration::from_micros(1500)), "0.0015s");
+    }
+}
@@ -0,0 +1,325 @@
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// The errors of a request, naming its method and URL.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// a header name that isn't a valid token
+    #[error("{method} {url}: invalid header name {name:?}")]
+    InvalidHeaderName {
+        method: String,
+        url: String,
+        name: String,
+    },
+    /// a header value with characters not allowed in headers
+    #[error("{method} {url}: invalid value of the header {name}")]
+    InvalidHeaderValue {
+        method: String,
+        url: String,
+        name: String,
+    },
+    #[error("{method} {url}: unsupported protocol {protocol}")]
+    UnsupportedProtocol {
+        method: String,
+        url: String,
+        protocol: String,
+    },
+    #[error("{method} {url}: invalid URL ({reason})")]
+    InvalidUrl {
+        method: String,
+        url: String,
+        reason: String,
+    },
+    /// the request couldn't be sent, or its response received, `elapsed`
+    /// after sending it
+    #[error("{method} {url} failed after {elapsed:?}")]
+    Transport {
+        method: String,
+        url: String,
+        elapsed: Duration,
+        source: reqwest::Error,
+    },
+    #[error("{method} {url} timed out after {elapsed:?}")]
+    Timeout {
+        method: String,
+        url: String,
+        elapsed: Duration,
+        source: reqwest::Error,
+    },
+    /// the body of the response couldn't be read or decompressed
+    #[error("{method} {url}: unable to read the body of the response")]
+    BodyDecode {
+        method: String,
+        url: String,
+        source: reqwest::Error,
+    },
+}
+
+impl Error {
+    /// Tells the timeouts and the bodies that couldn't be read from the
+    /// other errors of reqwest, happened `elapsed` after sending the request.
+    pub fn from_reqwest(
+        method: &str,
+        url: &str,
+        elapsed: Duration,
+        error: reqwest::Error,
+    ) -> Error {
+        let (method, url) = (method.to_string(), url.to_string());
+        if error.is_timeout() {
+            Error::Timeout {
+                method,
+                url,
+                elapsed,
+                source: error,
+            }
+        } else if error.is_decode() || error.is_body() {
+            Error::BodyDecode {
+                method,
+                url,
+                source: error,
+            }
+        } else {
+            Error::Transport {
+                method,
+                url,
+                elapsed,
+                source: error,
+            }
+        }
+    }
+
+    /// Like `from_reqwest`, for the errors of reqwest behind an `anyhow`
+    /// one; the ones of the other transports get the method and URL as
+    /// context, and the others are returned as they are.
+    pub fn wrap(method: &str, url: &str, elapsed: Duration, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<reqwest::Error>() {
+            Ok(error) => Error::from_reqwest(method, url, elapsed, error).into(),
+            Err(error) => match error.downcast_ref::<TransportError>() {
+                Some(TransportError::Timeout) => {
+                    error.context(format!("{} {} timed out after {:?}", method, url, elapsed))
+                }
+                Some(_) => error.context(format!("{} {} failed after {:?}", method, url, elapsed)),
+                None => error,
+            },
+        }
+    }
+}
+
+/// The error of a transport other than reqwest, like `transport::Mock`,
+/// that couldn't send the request or receive its response.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum TransportError {
+    /// the server couldn't be reached
+    #[error("unable to connect: {0}")]
+    Connect(String),
+    /// the response didn't come in time
+    #[error("operation timed out")]
+    Timeout,
+    /// the exchange failed otherwise
+    #[error("{0}")]
+    Other(String),
+}
+
+/// The errors of the `.http` files, naming the line they're in.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    /// a line following the assertions, the output file or a `>>>` body
+    #[error("unexpected line after {after} in line {line}")]
+    UnexpectedLine { line: usize, after: &'static str },
+    /// a directive, or part of the request line, left empty
+    #[error("missing {what} in line {line}")]
+    Missing { line: usize, what: &'static str },
+    #[error("invalid {what} {value} in line {line}")]
+    Invalid {
+        line: usize,
+        what: &'static str,
+        value: String,
+    },
+    /// the value of a directive not in the expected form
+    #[error("expected {expected}, found \"{found}\" in line {line}")]
+    Unexpected {
+        line: usize,
+        expected: &'static str,
+        found: String,
+    },
+    /// a value rejected by the parser of its directive, like a duration
+    #[error("{error} in line {line}")]
+    Directive { line: usize, error: anyhow::Error },
+    #[error("invalid URL {url} in line {line}: {reason}")]
+    InvalidUrl {
+        line: usize,
+        url: String,
+        reason: String,
+    },
+    /// a path, with neither a `Host` header nor an `@host` variable
+    #[error(
+        "the URL {url} in line {line} is a path: add a Host header to the request \
+         or define the @host variable"
+    )]
+    RelativeUrl { line: usize, url: String },
+    #[error("unsupported protocol {protocol} in line {line}")]
+    UnsupportedProtocol { line: usize, protocol: String },
+    /// a `<<<` body never closed
+    #[error("missing >>> closing the body opened in line {line}")]
+    UnclosedBody { line: usize },
+    #[error("a request can be written to a single output file in line {line}")]
+    OutputFiles { line: usize },
+    #[error("unknown retry option \"{option}\" in line {line}: expected backoff=DURATION")]
+    RetryOption { line: usize, option: String },
+    #[error("invalid local address {address:?} in line {line}: expected an IP address")]
+    LocalAddress { line: usize, address: String },
+    #[error("invalid proxy {proxy} in line {line}: {reason}")]
+    Proxy {
+        line: usize,
+        proxy: String,
+        reason: String,
+    },
+    /// a placeholder that couldn't be replaced
+    #[error("{error} in line {line}")]
+    Expansion { line: usize, error: ExpansionError },
+}
+
+/// The errors replacing the `{{...}}` placeholders of a line.
+#[derive(Debug, thiserror::Error)]
+pub enum ExpansionError {
+    #[error("missing }}}} closing the call to ${function}")]
+    UnclosedCall { function: String },
+    #[error("unknown function ${function}")]
+    UnknownFunction { function: String },
+    /// the arguments of a function not in the expected form
+    #[error("expected {expected} for ${function}, found \"{found}\"")]
+    Arguments {
+        function: String,
+        expected: String,
+        found: String,
+    },
+    /// the `.env` file of `{{$dotenv}}` exists but couldn't be read
+    #[error("unable to read {path}: {error}")]
+    Dotenv { path: String, error: io::Error },
+    #[error("{0}")]
+    Keyring(anyhow::Error),
+    #[error("running {{{{$shell {command}}}}} is not allowed, use --allow-shell to enable it")]
+    ShellNotAllowed { command: String },
+    #[error("missing command for $shell")]
+    MissingCommand,
+    #[error("unable to run {command}: {error}")]
+    Command { command: String, error: io::Error },
+    #[error("command {command} failed ({status}): {stderr}")]
+    CommandFailed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// the variables whose values refer to each other, in order
+    #[error("cyclic variable definition: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("too many nested variables (more than {max}) expanding {variable}")]
+    TooDeep { max: usize, variable: String },
+}
+
+/// Tells whether an error is a failure to connect, whatever the transport.
+pub fn is_connect(error: &anyhow::Error) -> bool {
+    match reqwest_error(error) {
+        Some(e) => e.is_connect(),
+        None => matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::Connect(_))
+        ),
+    }
+}
+
+/// Tells whether an error is a timeout, whatever the transport.
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    match reqwest_error(error) {
+        Some(e) => e.is_timeout(),
+        None => matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::Timeout)
+        ),
+    }
+}
+
+/// Tells whether a request got no response, whatever the transport, rather
+/// than not being sent at all, like when its client couldn't be built.
+pub fn is_transport(error: &anyhow::Error) -> bool {
+    match reqwest_error(error) {
+        Some(e) => !e.is_builder(),
+        None => error.downcast_ref::<TransportError>().is_some(),
+    }
+}
+
+/// The error of reqwest behind an error, if any, as is or in an `Error`.
+pub fn reqwest_error(error: &anyhow::Error) -> Option<&reqwest::Error> {
+    match error.downcast_ref::<Error>() {
+        Some(Error::Transport { source, .. })
+        | Some(Error::Timeout { source, .. })
+        | Some(Error::BodyDecode { source, .. }) => Some(source),
+        _ => error.downcast_ref::<reqwest::Error>(),
+    }
+}
+
+/// An error on a single line: its own messages, then only the deepest of the
+/// causes reported by reqwest, whose messages already repeat the ones of
+/// hyper and of the system below them; the segments repeated anyway are
+/// dropped.
+pub fn describe(error: &anyhow::Error) -> String {
+    let mut messages: Vec<String> = Vec::new();
+    let mut reqwest = false;
+    for cause in error.chain() {
+        if cause.is::<reqwest::Error>() {
+            reqwest = true;
+            break;
+        }
+        messages.push(cause.to_string());
+    }
+    if reqwest {
+        messages.extend(error.chain().last().map(|cause| cause.to_string()));
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in messages.iter().flat_map(|message| message.split(": ")) {
+        if !segments.contains(&segment) {
+            segments.push(segment);
+        }
+    }
+    segments.join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions() {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}/", address);
+        let source = reqwest::blocking::get(&url).unwrap_err();
+        let error = anyhow::Error::from(Error::from_reqwest(
+            "GET",
+            &url,
+            Duration::from_millis(3),
+            source,
+        ))
+        .context("failed after 2 attempts");
+        let deepest = error.chain().last().unwrap().to_string();
+        // reqwest and hyper repeat it
+        assert!(format!("{:#}", error).matches(&deepest).count() > 1);
+        assert_eq!(
+            describe(&error),
+            format!(
+                "failed after 2 attempts: GET {} failed after 3ms: {}",
+                url, deepest
+            )
+        );
+        let error = anyhow::anyhow!("unable to connect: refused")
+            .context("GET http://a/ failed after 1ms")
+            .context("unable to connect");
+        assert_eq!(
+            describe(&error),
+            "unable to connect: GET http://a/ failed after 1ms: refused"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! The `ETag` and `Last-Modified` of the responses to `GET` requests, kept
+//! in a directory between runs (`--etag-cache` or `# @cache`), a JSON file
+//! for each URL: the following requests are sent with `If-None-Match` and
+//! `If-Modified-Since`, and a `304 Not Modified` response gets the body
+//! kept, if any.
+
+use crate::request::{OwnedResponse, Request};
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderValue};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The directory of `# @cache` without `--etag-cache`: the `httpclient`
+/// one of `$XDG_CACHE_HOME`, or of `~/.cache`.
+pub fn default_dir() -> Option<PathBuf> {
+    let cache = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache) if !cache.is_empty() => PathBuf::from(cache),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("httpclient"))
+}
+
+/// What is kept of a response.
+#[derive(Debug, Default, PartialEq)]
+pub struct Entry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+    /// only the text ones
+    pub body: Option<String>,
+}
+
+/// The entry of a URL, as read before sending the request.
+pub struct Cache {
+    path: PathBuf,
+    url: String,
+    entry: Option<Entry>,
+}
+
+/// How a response was completed by the cache.
+#[derive(Debug, PartialEq)]
+pub enum Revalidation {
+    /// a 304, with the body kept
+    NotModified,
+    /// a 304 for which no body was kept
+    NotModifiedWithoutBody,
+}
+
+impl Cache {
+    /// Reads the entry of `url` in `dir`; a missing one is no entry.
+    pub fn open(dir: &Path, url: &str) -> Result<Cache> {
+        let path = dir.join(format!("{:x}.json", Sha256::digest(url.as_bytes())));
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => Some(text),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("unable to read the cache entry {}", path.display()))
+            }
+        };
+        let entry = match text {
+            Some(text) => {
+                let document = json::parse(&text)
+                    .map_err(|_| anyhow::anyhow!("invalid cache entry {}", path.display()))?;
+                let text = |key: &str| document[key].as_str().map(|s| s.to_string());
+                // a file named after another URL isn't its entry
+                Some(Entry {
+                    etag: text("etag"),
+                    last_modified: text("last_modified"),
+                    content_type: text("content_type"),
+                    body: text("body"),
+                })
+                .filter(|_| text("url").as_deref() == Some(url))
+            }
+            None => None,
+        };
+        Ok(Cache {
+            path,
+            url: url.to_string(),
+            entry,
+        })
+    }
+
+    pub fn entry(&self) -> Option<&Entry> {
+        self.entry.as_ref()
+    }
+
+    /// Makes the request conditional on the entry, unless it already sets
+    /// the same headers.
+    pub fn condition(&self, req: &mut Request) {
+        let entry = match &self.entry {
+            Some(entry) => entry,
+            None => return,
+        };
+        let conditions = [
+            ("If-None-Match", &entry.etag),
+            ("If-Modified-Since", &entry.last_modified),
+        ];
+        for (name, value) in conditions.iter() {
+            let set = req.headers.keys().any(|n| n.eq_ignore_ascii_case(name));
+            if let (Some(value), false) = (value, set) {
+                req.headers.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Gives a 304 response the body kept, if any, or else keeps the
+    /// validators of a successful one, along with its body if it's text.
+    pub fn update(&self, response: &mut OwnedResponse) -> Result<Option<Revalidation>> {
+        if response.status == StatusCode::NOT_MODIFIED {
+            let (content_type, body) = match &self.entry {
+                Some(Entry {
+                    content_type,
+                    body: Some(body),
+                    ..
+                }) => (content_type, body),
+                _ => return Ok(Some(Revalidation::NotModifiedWithoutBody)),
+            };
+            response.body = body.clone().into_bytes();
+            let value = content_type
+                .as_deref()
+                .and_then(|ct| HeaderValue::from_str(ct).ok());
+            if let (Some(value), false) =
+                (value, response.headers.contains_key(header::CONTENT_TYPE))
+            {
+                response.headers.insert(header::CONTENT_TYPE, value);
+            }
+            return Ok(Some(Revalidation::NotModified));
+        }
+        let header = |name| {
+            response
+                .headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let entry = Entry {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            content_type: header(header::CONTENT_TYPE),
+            body: match response.is_binary() {
+                true => None,
+                false => String::from_utf8(response.body.clone()).ok(),
+            },
+        };
+        if !response.status.is_success() || (entry.etag.is_none() && entry.last_modified.is_none())
+        {
+            return Ok(None);
+        }
+        self.save(&entry)?;
+        Ok(None)
+    }
+
+    fn save(&self, entry: &Entry) -> Result<()> {
+        let mut document = json::JsonValue::new_object();
+        document["url"] = self.url.as_str().into();
+        document["etag"] = entry.etag.clone().into();
+        document["last_modified"] = entry.last_modified.clone().into();
+        document["content_type"] = entry.content_type.clone().into();
+        document["body"] = entry.body.clone().into();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("unable to create the cache {}", dir.display()))?;
+        }
+        fs::write(&self.path, json::stringify_pretty(document, 2))
+            .with_context(|| format!("unable to write the cache entry {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+    use reqwest::Version;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> OwnedResponse {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: map,
+            body: body.as_bytes().to_vec(),
+            saved: None,
+            events: None,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn revalidation() {
+        let dir = std::env::temp_dir().join(format!("httpclient-etag-{}", std::process::id()));
+        let url = "https://example.com/items";
+        let cache = Cache::open(&dir, url).unwrap();
+        assert_eq!(cache.entry(), None);
+        let mut req = Request::new();
+        cache.condition(&mut req);
+        assert!(req.headers.is_empty());
+
+        let mut ok = response(
+            200,
+            &[("ETag", "\"v1\""), ("Content-Type", "application/json")],
+            "[1, 2]",
+        );
+        assert_eq!(cache.update(&mut ok).unwrap(), None);
+        let cache = Cache::open(&dir, url).unwrap();
+        assert_eq!(
+            cache.entry(),
+            Some(&Entry {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                content_type: Some("application/json".to_string()),
+                body: Some("[1, 2]".to_string()),
+            })
+        );
+        // the entry is plain JSON, with the URL
+        let text = fs::read_to_string(&cache.path).unwrap();
+        assert!(text.contains("\"url\": \"https://example.com/items\""));
+
+        cache.condition(&mut req);
+        assert_eq!(req.headers["If-None-Match"], "\"v1\"");
+        assert!(!req.headers.contains_key("If-Modified-Since"));
+        // not overriding the headers of the request
+        let mut req = Request::new();
+        req.headers
+            .insert("if-none-match".to_string(), "*".to_string());
+        cache.condition(&mut req);
+        assert_eq!(req.headers.len(), 1);
+
+        let mut not_modified = response(304, &[("ETag", "\"v1\"")], "");
+        assert_eq!(
+            cache.update(&mut not_modified).unwrap(),
+            Some(Revalidation::NotModified)
+        );
+        assert_eq!(not_modified.body, b"[1, 2]");
+        assert_eq!(
+            not_modified.headers[header::CONTENT_TYPE],
+            "application/json"
+        );
+        // another URL has no entry
+        let other = Cache::open(&dir, "https://example.com/other").unwrap();
+        let mut not_modified = response(304, &[], "");
+        assert_eq!(
+            other.update(&mut not_modified).unwrap(),
+            Some(Revalidation::NotModifiedWithoutBody)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
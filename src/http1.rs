@@ -0,0 +1,619 @@
+//! HTTP/1.1 written and read by hand, for what reqwest doesn't do: the
+//! requests to unix domain sockets, and the ones waiting for a
+//! `100 Continue` before sending their body (`# @expect-continue`).
+
+use crate::client::ClientOptions;
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Method, StatusCode, Url, Version};
+use std::convert::TryFrom;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a `100 Continue` before sending the body anyway.
+pub const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What became of the `Expect: 100-continue` of a request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Continue {
+    /// the server answered `100 Continue` after this long
+    Received(Duration),
+    /// the server answered with this status before the body was sent, that
+    /// then wasn't
+    Rejected(StatusCode),
+    /// the server stayed silent this long, and the body was sent anyway
+    TimedOut(Duration),
+}
+
+/// How the length of the body is told, if it is.
+pub(crate) enum Length {
+    Known(u64),
+    Chunked,
+}
+
+/// The request line and the headers, with the `Host` of the URL unless set,
+/// and a connection closed after the response.
+pub(crate) fn head(
+    method: &Method,
+    url: &Url,
+    version: &str,
+    headers: &HeaderMap,
+    length: Option<Length>,
+) -> Vec<u8> {
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let mut request = format!("{} {} {}\r\n", method, target, version).into_bytes();
+    if !headers.contains_key(header::HOST) {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or("localhost"), port),
+            None => url.host_str().unwrap_or("localhost").to_string(),
+        };
+        request.extend(format!("Host: {}\r\n", host).into_bytes());
+    }
+    for (name, value) in headers {
+        request.extend(format!("{}: ", name).into_bytes());
+        request.extend(value.as_bytes());
+        request.extend(b"\r\n");
+    }
+    match length {
+        Some(Length::Known(size)) => {
+            request.extend(format!("Content-Length: {}\r\n", size).into_bytes())
+        }
+        Some(Length::Chunked) => request.extend(b"Transfer-Encoding: chunked\r\n"),
+        None => (),
+    }
+    request.extend(b"Connection: close\r\n\r\n");
+    request
+}
+
+/// Fails for the options that `send_expecting_continue`, connecting to the
+/// server by itself, would ignore: a proxy, an address of `--resolve` or to
+/// connect from, and those of TLS, as the body is sent without it.
+pub(crate) fn check_options(options: &ClientOptions, url: &Url) -> Result<()> {
+    let mut ignored = Vec::new();
+    if options.proxy.is_some() {
+        ignored.push("a proxy");
+    }
+    if options.resolved(url).is_some() {
+        ignored.push("an address of --resolve");
+    }
+    if options.local_address.is_some() {
+        ignored.push("a local address");
+    }
+    if options.insecure
+        || !options.ca_certificates.is_empty()
+        || options.ca_certificates_only
+        || options.client_certificate.is_some()
+    {
+        ignored.push("TLS options");
+    }
+    if url.scheme() != "http" {
+        ignored.push("TLS");
+    }
+    match ignored.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!(
+            "# @expect-continue connects to plain http:// servers directly, without {}",
+            ignored.join(", ")
+        )),
+    }
+}
+
+/// Sends a request with `Expect: 100-continue` over a connection of its
+/// own, waiting `wait` for the `100 Continue` before sending `body`, of
+/// `size` bytes or else chunked; a response coming first is the one of the
+/// request, whose body isn't sent. The body of the response is read as
+/// `Received::response` does, up to `max_size`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn send_expecting_continue<R: Read>(
+    method: &Method,
+    url: &Url,
+    version: Version,
+    headers: &HeaderMap,
+    mut body: R,
+    size: Option<u64>,
+    timeout: Option<Duration>,
+    wait: Duration,
+    max_size: u64,
+) -> Result<(http::Response<Vec<u8>>, Continue)> {
+    if url.scheme() != "http" {
+        return Err(anyhow::anyhow!(
+            "# @expect-continue needs an http:// URL, not {}: the body is sent without TLS",
+            url
+        ));
+    }
+    if version != Version::HTTP_11 {
+        return Err(anyhow::anyhow!(
+            "# @expect-continue needs HTTP/1.1, not {:?}",
+            version
+        ));
+    }
+    let mut stream = connect(url, timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let mut headers = headers.clone();
+    headers
+        .entry(header::EXPECT)
+        .or_insert(HeaderValue::from_static("100-continue"));
+    let length = match (headers.contains_key(header::CONTENT_LENGTH), size) {
+        (true, _) => None,
+        (false, Some(size)) => Some(Length::Known(size)),
+        (false, None) => Some(Length::Chunked),
+    };
+    let chunked = matches!(length, Some(Length::Chunked));
+    stream
+        .write_all(&head(method, url, "HTTP/1.1", &headers, length))
+        .with_context(|| format!("unable to send the request to {}", url))?;
+
+    let start = Instant::now();
+    let read_error = || format!("unable to read the response from {}", url);
+    let mut received = Received::new(stream);
+    let continued = loop {
+        match received.head().with_context(read_error)? {
+            Some(head) if head.status() == StatusCode::CONTINUE => {
+                break Continue::Received(start.elapsed())
+            }
+            Some(head) if head.status().is_informational() => continue,
+            Some(head) => {
+                // the response, without the body
+                let status = head.status();
+                received.rejection = Some(head);
+                break Continue::Rejected(status);
+            }
+            None => (),
+        }
+        let left = wait.saturating_sub(start.elapsed());
+        if left.is_zero() {
+            break Continue::TimedOut(start.elapsed());
+        }
+        received.stream.set_read_timeout(Some(left))?;
+        match received.read() {
+            Ok(0) => {
+                return Err(anyhow::anyhow!(
+                    "the connection was closed before a response"
+                ))
+            }
+            Ok(_) => (),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                break Continue::TimedOut(start.elapsed())
+            }
+            Err(e) => return Err(e).with_context(read_error),
+        }
+    };
+
+    // the server may answer while the body is sent, and close the connection
+    let unsent = match continued {
+        Continue::Rejected(_) => None,
+        _ => write_body(&mut received.stream, &mut body, chunked).err(),
+    };
+    received.stream.set_read_timeout(timeout)?;
+    match received.response(method, max_size) {
+        Ok(response) => Ok((response, continued)),
+        Err(e) => Err(match unsent {
+            Some(unsent) => unsent.context(format!("unable to send the body to {}", url)),
+            None => e.context(read_error()),
+        }),
+    }
+}
+
+fn connect(url: &Url, timeout: Option<Duration>) -> Result<TcpStream> {
+    let addresses = url
+        .socket_addrs(|| Some(80))
+        .with_context(|| format!("unable to resolve the host of {}", url))?;
+    let mut error = None;
+    for address in addresses {
+        let stream = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        };
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = Some(e),
+        }
+    }
+    let error = error.unwrap_or_else(|| io::Error::new(ErrorKind::NotFound, "no address"));
+    Err(error).with_context(|| format!("unable to connect to {}", url))
+}
+
+/// Sends the body, as it's read, in chunks without a length.
+fn write_body<R: Read>(stream: &mut TcpStream, body: &mut R, chunked: bool) -> Result<()> {
+    if !chunked {
+        io::copy(body, stream)?;
+        return Ok(());
+    }
+    let mut buffer = [0; 8192];
+    loop {
+        let read = body.read(&mut buffer)?;
+        if read == 0 {
+            stream.write_all(b"0\r\n\r\n")?;
+            return Ok(());
+        }
+        stream.write_all(format!("{:x}\r\n", read).as_bytes())?;
+        stream.write_all(&buffer[..read])?;
+        stream.write_all(b"\r\n")?;
+    }
+}
+
+/// The bytes received on a connection, read as they arrive: the end of a
+/// head is searched for from where the previous search stopped, and the body
+/// is taken out chunk by chunk, never going through what came before again.
+pub(crate) struct Received<S> {
+    pub(crate) stream: S,
+    buffer: Vec<u8>,
+    /// how much of `buffer` is known not to end a head
+    searched: usize,
+    /// the head of a response that came before the body was sent, for
+    /// `response`
+    rejection: Option<http::Response<()>>,
+}
+
+impl<S: Read> Received<S> {
+    pub(crate) fn new(stream: S) -> Received<S> {
+        Received {
+            stream,
+            buffer: Vec::new(),
+            searched: 0,
+            rejection: None,
+        }
+    }
+
+    /// Reads what arrived, returning how many bytes: 0 once the connection
+    /// is closed.
+    fn read(&mut self) -> io::Result<usize> {
+        let mut buffer = [0; 8192];
+        let read = self.stream.read(&mut buffer)?;
+        self.buffer.extend_from_slice(&buffer[..read]);
+        Ok(read)
+    }
+
+    /// Reads until `size` bytes are received.
+    fn fill(&mut self, size: usize) -> Result<()> {
+        while self.buffer.len() < size {
+            if self.read()? == 0 {
+                return Err(anyhow::anyhow!(
+                    "the connection was closed before the end of the response"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The head received of a response, interim or final, taken out of
+    /// the bytes received; none until its blank line came.
+    fn head(&mut self) -> Result<Option<http::Response<()>>> {
+        let from = self.searched.saturating_sub(3);
+        let end = match self.buffer[from..]
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            Some(position) => from + position + 4,
+            None => {
+                self.searched = self.buffer.len();
+                return Ok(None);
+            }
+        };
+        let mut headers = [httparse::EMPTY_HEADER; 128];
+        let mut response = httparse::Response::new(&mut headers);
+        if response
+            .parse(&self.buffer[..end])
+            .context("invalid response")?
+            .is_partial()
+        {
+            return Err(anyhow::anyhow!("invalid response"));
+        }
+        let mut builder = http::Response::builder()
+            .status(StatusCode::from_u16(response.code.unwrap_or_default())?)
+            .version(match response.version {
+                Some(0) => Version::HTTP_10,
+                _ => Version::HTTP_11,
+            });
+        for header in response.headers.iter() {
+            builder = builder.header(header.name, header.value);
+        }
+        let head = builder.body(())?;
+        self.buffer.drain(..end);
+        self.searched = 0;
+        Ok(Some(head))
+    }
+
+    /// Reads the final response, after the interim ones, to a request of
+    /// `method`: its body is read up to `max_size` bytes and one more,
+    /// telling that it's longer, unless zero.
+    pub(crate) fn response(
+        &mut self,
+        method: &Method,
+        max_size: u64,
+    ) -> Result<http::Response<Vec<u8>>> {
+        let head = loop {
+            if let Some(head) = self.rejection.take() {
+                break head;
+            }
+            match self.head()? {
+                Some(head) if head.status().is_informational() => continue,
+                Some(head) => break head,
+                None if self.read()? == 0 => {
+                    return Err(anyhow::anyhow!(match self.buffer.is_empty() {
+                        true => "the connection was closed before a response",
+                        false => "the connection was closed before the end of the response",
+                    }))
+                }
+                None => (),
+            }
+        };
+        let limit = match max_size {
+            0 => usize::MAX,
+            _ => usize::try_from(max_size)
+                .unwrap_or(usize::MAX)
+                .saturating_add(1),
+        };
+        let status = head.status();
+        let headers = head.headers();
+        let chunked = headers
+            .get_all(header::TRANSFER_ENCODING)
+            .iter()
+            .any(|value| {
+                String::from_utf8_lossy(value.as_bytes())
+                    .to_lowercase()
+                    .contains("chunked")
+            });
+        let content_length = match headers.get(header::CONTENT_LENGTH) {
+            Some(value) => {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .with_context(|| format!("invalid Content-Length {}", value))?,
+                )
+            }
+            None => None,
+        };
+        let body = if *method == Method::HEAD
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            Vec::new()
+        } else if chunked {
+            self.dechunk(limit)?
+        } else if let Some(length) = content_length {
+            self.fill(length.min(limit))?;
+            self.buffer.truncate(length.min(limit));
+            std::mem::take(&mut self.buffer)
+        } else {
+            // without a length, the body ends with the connection
+            while self.buffer.len() < limit && self.read()? > 0 {}
+            self.buffer.truncate(limit);
+            std::mem::take(&mut self.buffer)
+        };
+        Ok(head.map(|_| body))
+    }
+
+    /// The body of a `Transfer-Encoding: chunked` response, up to `limit`
+    /// bytes; the trailers are ignored.
+    fn dechunk(&mut self, limit: usize) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        let mut searched: usize = 0;
+        while body.len() < limit {
+            let line_end = match self.buffer[searched.saturating_sub(1)..]
+                .windows(2)
+                .position(|window| window == b"\r\n")
+            {
+                Some(position) => searched.saturating_sub(1) + position,
+                None => {
+                    searched = self.buffer.len();
+                    self.fill(searched + 1)?;
+                    continue;
+                }
+            };
+            searched = 0;
+            let line = String::from_utf8_lossy(&self.buffer[..line_end]).to_string();
+            // without the extensions, after a `;`
+            let size = usize::from_str_radix(line.split(';').next().unwrap_or("").trim(), 16)
+                .map_err(|_| anyhow::anyhow!("invalid chunk size {:?}", line))?;
+            self.buffer.drain(..line_end + 2);
+            if size == 0 {
+                break;
+            }
+            // the part of the chunk within the limit, with its line end if
+            // it's all
+            let wanted = size.min(limit - body.len());
+            self.fill(if wanted < size { wanted } else { size + 2 })?;
+            body.extend(self.buffer.drain(..wanted));
+            if wanted == size {
+                self.buffer.drain(..2);
+            }
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// A server answering a request with `interim`, if any, once its head
+    /// is received, and then with `response` once the body is, up to
+    /// `body_end`; returns what it received.
+    fn server(
+        interim: Option<&'static [u8]>,
+        body_end: &'static [u8],
+        response: &'static [u8],
+    ) -> (Url, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/upload", listener.local_addr().unwrap())).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let read = |stream: &mut std::net::TcpStream, received: &mut Vec<u8>| {
+                let mut buffer = [0; 8192];
+                let read = stream.read(&mut buffer).unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            };
+            while !received.windows(4).any(|window| window == b"\r\n\r\n") {
+                read(&mut stream, &mut received);
+            }
+            if let Some(interim) = interim {
+                stream.write_all(interim).unwrap();
+                if !interim.starts_with(b"HTTP/1.1 100") {
+                    return String::from_utf8_lossy(&received).to_string();
+                }
+            }
+            while !received.ends_with(body_end) {
+                read(&mut stream, &mut received);
+            }
+            stream.write_all(response).unwrap();
+            String::from_utf8_lossy(&received).to_string()
+        });
+        (url, server)
+    }
+
+    fn send(
+        url: &Url,
+        body: &'static [u8],
+        size: Option<u64>,
+    ) -> (http::Response<Vec<u8>>, Continue) {
+        send_expecting_continue(
+            &Method::PUT,
+            url,
+            Version::HTTP_11,
+            &HeaderMap::new(),
+            body,
+            size,
+            Some(Duration::from_secs(10)),
+            Duration::from_millis(200),
+            0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn continued() {
+        let (url, server) = server(
+            Some(b"HTTP/1.1 100 Continue\r\n\r\n"),
+            b"video",
+            b"HTTP/1.1 201 Created\r\nContent-Length: 4\r\n\r\ndone",
+        );
+        let (response, continued) = send(&url, b"video", Some(5));
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.body(), b"done");
+        assert!(
+            matches!(continued, Continue::Received(after) if after < Duration::from_millis(200))
+        );
+        assert_eq!(
+            server.join().unwrap(),
+            format!(
+                "PUT /upload HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nexpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\nvideo",
+                url.port().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn silent_servers() {
+        // the body is sent anyway, chunked without a size
+        let (url, server) = server(
+            None,
+            b"0\r\n\r\n",
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+        );
+        let (response, continued) = send(&url, b"video", None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            matches!(continued, Continue::TimedOut(after) if after >= Duration::from_millis(200))
+        );
+        assert!(server.join().unwrap().ends_with(
+            "Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nvideo\r\n0\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn rejections() {
+        let (url, server) = server(
+            Some(b"HTTP/1.1 413 Payload Too Large\r\nContent-Length: 8\r\n\r\ntoo big!"),
+            b"",
+            b"",
+        );
+        let (response, continued) = send(&url, b"video", Some(5));
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.body(), b"too big!");
+        assert_eq!(continued, Continue::Rejected(StatusCode::PAYLOAD_TOO_LARGE));
+        // the body wasn't sent
+        assert!(server.join().unwrap().ends_with("\r\n\r\n"));
+
+        let error = send_expecting_continue(
+            &Method::PUT,
+            &Url::parse("https://example.com/upload").unwrap(),
+            Version::HTTP_11,
+            &HeaderMap::new(),
+            io::empty(),
+            Some(0),
+            None,
+            CONTINUE_TIMEOUT,
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("# @expect-continue needs an http:// URL"));
+    }
+    /// A connection giving what it received a byte at a time.
+    struct Slow<'a>(&'a [u8]);
+
+    impl Read for Slow<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let read = self.0.len().min(buffer.len()).min(1);
+            buffer[..read].copy_from_slice(&self.0[..read]);
+            self.0 = &self.0[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn responses() {
+        let read = |received: &'static [u8], method: &Method, max_size| {
+            Received::new(Slow(received)).response(method, max_size)
+        };
+        let response = read(
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nContent-Length: 4\r\n\r\ndone",
+            &Method::GET,
+            0,
+        )
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.body(), b"done");
+
+        // without a length, the body ends with the connection
+        let response = read(b"HTTP/1.0 200 OK\r\n\r\nuntil closed", &Method::GET, 0).unwrap();
+        assert_eq!(response.version(), Version::HTTP_10);
+        assert_eq!(response.body(), b"until closed");
+
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4;a=b\r\nchun\r\n3\r\nked\r\n0\r\n\r\n";
+        assert_eq!(read(chunked, &Method::GET, 0).unwrap().body(), b"chunked");
+        // up to the limit, and the byte telling that there's more
+        assert_eq!(read(chunked, &Method::GET, 4).unwrap().body(), b"chunk");
+        assert_eq!(read(chunked, &Method::GET, 7).unwrap().body(), b"chunked");
+        let long = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123";
+        assert_eq!(read(long, &Method::GET, 2).unwrap().body(), b"012");
+
+        let head = read(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n",
+            &Method::HEAD,
+            0,
+        );
+        assert_eq!(head.unwrap().body(), b"");
+        assert_eq!(
+            read(long, &Method::GET, 0).err().unwrap().to_string(),
+            "the connection was closed before the end of the response"
+        );
+        assert_eq!(
+            read(b"", &Method::GET, 0).err().unwrap().to_string(),
+            "the connection was closed before a response"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Ctrl-C: the first one stops starting requests, so that the run is summed
+//! up with the ones done so far, the second one exits at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// The exit status after a Ctrl-C, as for the shells: 128 + SIGINT.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// The flag of `install`, set by the first Ctrl-C.
+static INTERRUPTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Handles Ctrl-C from now on, returning the flag it sets, to give to
+/// `Options::interrupted`; only on unix, elsewhere it still exits at once.
+pub fn install() -> Arc<AtomicBool> {
+    let interrupted = INTERRUPTED.get_or_init(Arc::default).clone();
+    #[cfg(unix)]
+    unsafe {
+        let handler: extern "C" fn(libc::c_int) = on_interrupt;
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+    interrupted
+}
+
+/// Only what is async-signal-safe: the flag, `write` and `_exit`.
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    let interrupted = match INTERRUPTED.get() {
+        Some(interrupted) => interrupted,
+        None => return,
+    };
+    if interrupted.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(EXIT_INTERRUPTED) };
+    }
+    let message = b"\ninterrupted: finishing the requests in flight, Ctrl-C again to exit now\n";
+    unsafe {
+        libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_interrupt() {
+        let interrupted = install();
+        assert!(!interrupted.load(Ordering::SeqCst));
+        unsafe { libc::raise(libc::SIGINT) };
+        assert!(interrupted.load(Ordering::SeqCst));
+    }
+}
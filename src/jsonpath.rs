@@ -0,0 +1,135 @@
+use anyhow::Result;
+use json::JsonValue;
+
+/// Looks up a value inside a parsed JSON document using a (simplified)
+/// JSONPath expression.
+///
+/// Supported syntax is the root `$`, dotted keys (`$.a.b`), bracketed keys
+/// (`$['a b']`) and array indexes (`$.items[0]`).
+///
+/// # Examples
+///
+/// ```
+/// let doc = json::parse(r#"{"items": [{"id": 42}]}"#).unwrap();
+/// let value = httpclient::jsonpath::lookup(&doc, "$.items[0].id").unwrap();
+/// assert_eq!(value.as_i32(), Some(42));
+/// ```
+pub fn lookup<'a>(document: &'a JsonValue, path: &str) -> Result<&'a JsonValue> {
+    let mut current = document;
+    for segment in parse_path(path)? {
+        current = match &segment {
+            Segment::Key(key) => {
+                if !current.is_object() || !current.has_key(key) {
+                    return Err(anyhow::anyhow!(
+                        "JSON path {} not found: no key \"{}\"",
+                        path,
+                        key
+                    ));
+                }
+                &current[key.as_str()]
+            }
+            Segment::Index(index) => {
+                if !current.is_array() || *index >= current.len() {
+                    return Err(anyhow::anyhow!(
+                        "JSON path {} not found: no element at index {}",
+                        path,
+                        index
+                    ));
+                }
+                &current[*index]
+            }
+        };
+    }
+    Ok(current)
+}
+
+/// Checks the syntax of a JSONPath expression, without evaluating it.
+pub fn validate(path: &str) -> Result<()> {
+    parse_path(path).map(|_| ())
+}
+
+/// Renders a JSON value the way it should be substituted into a request:
+/// strings without quotes, everything else as compact JSON.
+pub fn to_plain_string(value: &JsonValue) -> String {
+    match value.as_str() {
+        Some(s) => s.to_string(),
+        None => value.dump(),
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let invalid = || anyhow::anyhow!("invalid JSON path: {}", path);
+    let rest = path.trim().strip_prefix('$').ok_or_else(invalid)?;
+    let chars: Vec<char> = rest.chars().collect();
+    let mut segments = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                if i == start {
+                    return Err(invalid());
+                }
+                segments.push(Segment::Key(chars[start..i].iter().collect()));
+            }
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .map(|p| p + i)
+                    .ok_or_else(invalid)?;
+                let inner: String = chars[i + 1..end].iter().collect();
+                let quoted = inner.len() >= 2
+                    && ((inner.starts_with('\'') && inner.ends_with('\''))
+                        || (inner.starts_with('"') && inner.ends_with('"')));
+                if quoted {
+                    segments.push(Segment::Key(inner[1..inner.len() - 1].to_string()));
+                } else {
+                    segments.push(Segment::Index(
+                        inner.trim().parse::<usize>().map_err(|_| invalid())?,
+                    ));
+                }
+                i = end + 1;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_lookup() {
+        let doc = json::parse(r#"{"a": {"b": [1, {"c": "x"}]}, "d e": true}"#).unwrap();
+        assert_eq!(lookup(&doc, "$.a.b[1].c").unwrap().as_str(), Some("x"));
+        assert_eq!(lookup(&doc, "$['d e']").unwrap().as_bool(), Some(true));
+        assert_eq!(
+            to_plain_string(lookup(&doc, "$.a.b").unwrap()),
+            "[1,{\"c\":\"x\"}]"
+        );
+    }
+
+    #[test]
+    fn missing_path() {
+        let doc = json::parse(r#"{"a": [1]}"#).unwrap();
+        let err = lookup(&doc, "$.a[3]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "JSON path $.a[3] not found: no element at index 3"
+        );
+        assert!(lookup(&doc, "$.b").is_err());
+        assert!(lookup(&doc, "a.b").is_err());
+    }
+}
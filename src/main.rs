@@ -1,15 +1,30 @@
 extern crate clap;
 use anyhow::Result;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 
-fn main() -> Result<()> {
+fn main() {
+    if let Err(e) = run() {
+        // on a single line, with the cause at its root
+        eprintln!("Error: {}", httpclient::error::describe(&e));
+        process::exit(httpclient::exit_code(&e));
+    }
+}
+
+fn run() -> Result<()> {
     let matches = App::new("httpclient")
         .version("0.1.0")
         .author("Alessio Giambrone <AlessioGiambrone@users.noreply.github.com>")
         .about("")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("INPUT")
-                .help("Path to the .HTTP file to use")
+                .help("Path to the .HTTP file to use, or - to read it from the standard input")
                 .required(true)
                 .min_values(1)
                 .index(1),
@@ -27,7 +42,380 @@ Numbering starts from 0; use \"a\" to execute them all",
             Arg::with_name("timeout")
                 .default_value("120")
                 .short("t")
-                .help("request timeout, in seconds"),
+                .help("request timeout, as 30 (seconds), 500ms, 2m or 1h; 0 for none, waiting the responses forever"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Executes only the requests with this `# @tag` (all of them, unless -n is given)"),
+        )
+        .arg(
+            Arg::with_name("exclude-tag")
+                .long("exclude-tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Skips the requests with this `# @tag` (all of them are considered, unless -n is given)"),
+        )
+        .arg(
+            Arg::with_name("parallel")
+                .long("parallel")
+                .takes_value(true)
+                .default_value("1")
+                .help("Executes up to this many requests at the same time (the chained ones in order)"),
+        )
+        .arg(
+            Arg::with_name("stream-results")
+                .long("stream-results")
+                .help("With --parallel, prints the output of each request when it's done, instead of in file order"),
+        )
+        .arg(
+            Arg::with_name("retry")
+                .long("retry")
+                .takes_value(true)
+                .default_value("0")
+                .help("Sends again, up to this many times, the requests failing to connect or timing out"),
+        )
+        .arg(
+            Arg::with_name("retry-server-errors")
+                .long("retry-server-errors")
+                .help("Retries the requests with a 5xx response too"),
+        )
+        .arg(
+            Arg::with_name("retry-all-methods")
+                .long("retry-all-methods")
+                .help("Retries POST and PATCH requests for any reason, not only when they couldn't connect"),
+        )
+        .arg(
+            Arg::with_name("respect-retry-after")
+                .long("respect-retry-after")
+                .help("Retries 429 and 503 responses, waiting as their Retry-After header asks"),
+        )
+        .arg(
+            Arg::with_name("repeat")
+                .long("repeat")
+                .takes_value(true)
+                .default_value("1")
+                .help("Executes the request this many times, printing latency statistics instead of the responses"),
+        )
+        .arg(
+            Arg::with_name("warmup")
+                .long("warmup")
+                .takes_value(true)
+                .default_value("0")
+                .help("With --repeat, executes the request this many times before, leaving them out of the statistics"),
+        )
+        .arg(
+            Arg::with_name("delay")
+                .long("delay")
+                .takes_value(true)
+                .default_value("0")
+                .help("Waits this long between two requests, e.g. 500ms or 2s"),
+        )
+        .arg(
+            Arg::with_name("local-address")
+                .long("local-address")
+                .takes_value(true)
+                .value_name("IP")
+                .help("Connects from this address of the host, as curl's --interface; # @local-address overrides it"),
+        )
+        .arg(
+            Arg::with_name("pool-idle-timeout")
+                .long("pool-idle-timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Closes the connections idle for this long, as 30s [default: 90s]; 0 keeps them open"),
+        )
+        .arg(
+            Arg::with_name("pool-max-idle-per-host")
+                .long("pool-max-idle-per-host")
+                .takes_value(true)
+                .value_name("N")
+                .help("Keeps open up to this many idle connections to each host [default: no limit]"),
+        )
+        .arg(
+            Arg::with_name("no-keepalive")
+                .long("no-keepalive")
+                .conflicts_with_all(&["pool-idle-timeout", "pool-max-idle-per-host"])
+                .help("Opens a new connection for each request, never reusing them, to measure the cost of connecting"),
+        )
+        .arg(
+            Arg::with_name("tcp-nodelay")
+                .long("tcp-nodelay")
+                .takes_value(true)
+                .possible_values(&["on", "off"])
+                .default_value("on")
+                .help("Sends the small packets at once, disabling Nagle's algorithm"),
+        )
+        .arg(
+            Arg::with_name("tcp-keepalive")
+                .long("tcp-keepalive")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Sends TCP keep-alive probes on the idle connections this often, as 60s"),
+        )
+        .arg(
+            Arg::with_name("deadline")
+                .long("deadline")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("Stops the run after this long, e.g. 2m, aborting the request in flight and skipping the following ones"),
+        )
+        .arg(
+            Arg::with_name("max-redirects")
+                .long("max-redirects")
+                .takes_value(true)
+                .default_value("10")
+                .help("Fails the requests redirected more than this many times"),
+        )
+        .arg(
+            Arg::with_name("no-follow")
+                .long("no-follow")
+                .conflicts_with("max-redirects")
+                .help("Doesn't follow the redirects, showing the redirect responses"),
+        )
+        .arg(
+            Arg::with_name("proxy")
+                .long("proxy")
+                .takes_value(true)
+                .help("Sends all the requests through this proxy, e.g. http://proxy.local:3128"),
+        )
+        .arg(
+            Arg::with_name("proxy-user")
+                .long("proxy-user")
+                .takes_value(true)
+                .requires("proxy")
+                .help("Authenticates to the proxy as user:password"),
+        )
+        .arg(
+            Arg::with_name("no-proxy")
+                .long("no-proxy")
+                .conflicts_with("proxy")
+                .help("Ignores the HTTP_PROXY and HTTPS_PROXY environment variables"),
+        )
+        .arg(
+            Arg::with_name("insecure")
+                .short("k")
+                .long("insecure")
+                .help("Accepts any TLS certificate, even self-signed or for another host"),
+        )
+        .arg(
+            Arg::with_name("cacert")
+                .long("cacert")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Trusts the CA certificates of this PEM file too"),
+        )
+        .arg(
+            Arg::with_name("cacert-only")
+                .long("cacert-only")
+                .requires("cacert")
+                .help("Trusts only the CA certificates of --cacert, not the ones of the system"),
+        )
+        .arg(
+            Arg::with_name("connect-timeout")
+                .long("connect-timeout")
+                .takes_value(true)
+                .help("How long connecting to a server can take, as 5 or 500ms: by default, only --timeout applies"),
+        )
+        .arg(
+            Arg::with_name("resolve")
+                .long("resolve")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("host:port:address")
+                .help("Connects to this address for the host and port, instead of the one of the DNS"),
+        )
+        .arg(
+            Arg::with_name("cert")
+                .long("cert")
+                .takes_value(true)
+                .requires("key")
+                .help("Authenticates with the client certificate of this PEM file (with --features rustls)"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .takes_value(true)
+                .requires("cert")
+                .help("The PEM file with the private key of --cert"),
+        )
+        .arg(
+            Arg::with_name("cert-p12")
+                .long("cert-p12")
+                .takes_value(true)
+                .conflicts_with("cert")
+                .help("Authenticates with the client certificate of this PKCS#12 archive"),
+        )
+        .arg(
+            Arg::with_name("cert-password")
+                .long("cert-password")
+                .takes_value(true)
+                .requires("cert-p12")
+                .help("The password of --cert-p12"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Writes the response body to this file as it's received, instead of printing it"),
+        )
+        .arg(
+            Arg::with_name("binary-stdout")
+                .long("binary-stdout")
+                .help("Writes the binary bodies, like images, to stdout as they are, instead of describing them"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help("Colors the status lines, the header names and the JSON keys printed with -v; auto does only on a terminal, unless NO_COLOR is set"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Doesn't show the progress of the responses written to a file"),
+        )
+        .arg(
+            Arg::with_name("max-pretty-size")
+                .long("max-pretty-size")
+                .takes_value(true)
+                .default_value("1048576")
+                .help("Pretty-prints with -v the JSON and XML bodies up to this many bytes"),
+        )
+        .arg(
+            Arg::with_name("max-response-size")
+                .long("max-response-size")
+                .takes_value(true)
+                .default_value("1GiB")
+                .value_name("SIZE")
+                .help("Stops reading the bodies longer than this, like 50MiB, truncating them; 0 for no limit. The ones written to a file aren't limited"),
+        )
+        .arg(
+            Arg::with_name("no-decompress")
+                .long("no-decompress")
+                .help("Shows the gzip and brotli bodies as received, with their Content-Encoding and Content-Length"),
+        )
+        .arg(
+            Arg::with_name("fail")
+                .short("f")
+                .long("fail")
+                .help("Fails on the responses with a 4xx or 5xx status, exiting with 4 or 5 (7 when a request gets no response)"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .help("Stops at the first request failing, or failing its assertions, listing the skipped ones, instead of executing them and summing up how each one went"),
+        )
+        .arg(
+            Arg::with_name("user-agent")
+                .long("user-agent")
+                .takes_value(true)
+                .value_name("VALUE")
+                .help("The User-Agent of the requests without one, instead of httpclient/VERSION"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Builds the requests and prints them as with -vv, with their final URL, without sending them; fails if any can't be built"),
+        )
+        .arg(
+            Arg::with_name("continue-at")
+                .long("continue-at")
+                .short("C")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .value_name("-")
+                .help("Resumes the downloads to an existing file from its size, with a Range header"),
+        )
+        .arg(
+            Arg::with_name("etag-cache")
+                .long("etag-cache")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Keeps the ETag and Last-Modified of the GET responses in this directory, sending the requests again conditionally"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the requests and their responses to this HAR file, to replay them with --replay"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("record")
+                .help("Answers the requests with the responses of this HAR file, with the same method, URL and body, sending nothing"),
+        )
+        .arg(
+            Arg::with_name("netrc")
+                .long("netrc")
+                .help("Sends the credentials of ~/.netrc for the host of each request, unless it sets Authorization"),
+        )
+        .arg(
+            Arg::with_name("netrc-file")
+                .long("netrc-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Like --netrc, with the credentials of this file"),
+        )
+        .arg(
+            Arg::with_name("cookie-jar")
+                .long("cookie-jar")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Reads the cookies from this JSON file before the requests, and writes them to it after"),
+        )
+        .arg(
+            Arg::with_name("no-cookies")
+                .long("no-cookies")
+                .conflicts_with("cookie-jar")
+                .help("Doesn't send the cookies set by the responses with the following requests"),
+        )
+        .arg(
+            Arg::with_name("no-assert")
+                .long("no-assert")
+                .help("Skips the evaluation of the `??` assertions"),
+        )
+        .arg(
+            Arg::with_name("allow-shell")
+                .long("allow-shell")
+                .help("Runs the commands of the `{{$shell command}}` placeholders"),
+        )
+        .arg(
+            Arg::with_name("default-scheme")
+                .long("default-scheme")
+                .takes_value(true)
+                .possible_values(&["http", "https"])
+                .help("Scheme of the URLs written without one [default: http, https for paths]"),
+        )
+        .arg(
+            Arg::with_name("http2-prior-knowledge")
+                .long("http2-prior-knowledge")
+                .conflicts_with("http1.1")
+                .help("Sends the requests without a protocol with HTTP/2 right away, without upgrade, as for HTTP/2 ones"),
+        )
+        .arg(
+            Arg::with_name("http1.1")
+                .long("http1.1")
+                .help("Sends the HTTP/2 requests with HTTP/1.1 instead"),
+        )
+        .arg(
+            Arg::with_name("show-secrets")
+                .long("show-secrets")
+                .help("Shows the values of the `{{$keyring entry}}` placeholders with -vv"),
         )
         .arg(
             Arg::with_name("v")
@@ -47,24 +435,290 @@ Numbering starts from 0; use \"a\" to execute them all",
 ",
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("secret")
+                .about("Manages the secrets of the `{{$keyring service/entry}}` placeholders")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about(
+                            "Stores a secret, read from the standard input, in the system keyring",
+                        )
+                        .arg(
+                            Arg::with_name("ENTRY")
+                                .help("The entry to store, as service/entry")
+                                .required(true),
+                        ),
+                ),
+        )
         .get_matches();
 
-    let verbosity = matches.occurrences_of("v");
+    if let Some(secret_matches) = matches.subcommand_matches("secret") {
+        if let Some(set_matches) = secret_matches.subcommand_matches("set") {
+            let entry = set_matches.value_of("ENTRY").unwrap();
+            eprintln!("Secret for {} (end with a newline):", entry);
+            let mut secret = String::new();
+            io::stdin().read_line(&mut secret)?;
+            httpclient::secrets::set(entry, secret.trim_end_matches(&['\r', '\n'][..]))?;
+        }
+        return Ok(());
+    }
+
+    let mut options = httpclient::Options {
+        verbosity: matches.occurrences_of("v"),
+        request_timeout: httpclient::duration::parse(matches.value_of("timeout").unwrap())
+            .map_err(|e| e.context("invalid -t"))?,
+        assert: !matches.is_present("no-assert"),
+        show_secrets: matches.is_present("show-secrets"),
+        tags: tag_values(&matches, "tag"),
+        exclude_tags: tag_values(&matches, "exclude-tag"),
+        parallel: match matches.value_of("parallel").unwrap().parse::<usize>() {
+            Ok(parallel) if parallel > 0 => parallel,
+            _ => return Err(anyhow::anyhow!("--parallel expects a positive number")),
+        },
+        stream_results: matches.is_present("stream-results"),
+        retry: httpclient::retry::RetryPolicy {
+            retries: matches
+                .value_of("retry")
+                .unwrap()
+                .parse::<u32>()
+                .map_err(|_| anyhow::anyhow!("--retry expects a number"))?,
+            server_errors: matches.is_present("retry-server-errors"),
+            all_methods: matches.is_present("retry-all-methods"),
+            respect_retry_after: matches.is_present("respect-retry-after"),
+            ..httpclient::retry::RetryPolicy::default()
+        },
+        repeat: match matches.value_of("repeat").unwrap().parse::<usize>() {
+            Ok(repeat) if repeat > 0 => repeat,
+            _ => return Err(anyhow::anyhow!("--repeat expects a positive number")),
+        },
+        warmup: match matches.value_of("warmup").unwrap().parse::<usize>() {
+            Ok(0) => 0,
+            Ok(_) if matches.occurrences_of("repeat") == 0 => {
+                return Err(anyhow::anyhow!("--warmup needs --repeat"))
+            }
+            Ok(warmup) => warmup,
+            _ => return Err(anyhow::anyhow!("--warmup expects a number")),
+        },
+        delay: httpclient::duration::parse(matches.value_of("delay").unwrap())
+            .map_err(|e| e.context("invalid --delay"))?,
+        deadline: matches
+            .value_of("deadline")
+            .map(|deadline| {
+                httpclient::duration::parse(deadline).map_err(|e| e.context("invalid --deadline"))
+            })
+            .transpose()?,
+        output: matches.value_of_os("output").map(PathBuf::from),
+        continue_at: match matches.value_of("continue-at") {
+            Some("-") => true,
+            Some(_) => {
+                return Err(anyhow::anyhow!(
+                    "--continue-at expects -, resuming from the size of the output file"
+                ))
+            }
+            None => false,
+        },
+        max_pretty_size: matches
+            .value_of("max-pretty-size")
+            .unwrap()
+            .parse::<usize>()
+            .map_err(|_| anyhow::anyhow!("--max-pretty-size expects a number of bytes"))?,
+        max_response_size: httpclient::progress::parse_size(
+            matches.value_of("max-response-size").unwrap(),
+        )
+        .map_err(|e| e.context("invalid --max-response-size"))?,
+        binary_stdout: matches.is_present("binary-stdout"),
+        color: httpclient::color::enabled(matches.value_of("color").unwrap()),
+        quiet: matches.is_present("quiet"),
+        fail_fast: matches.is_present("fail-fast"),
+        fail: matches.is_present("fail"),
+        cookies: !matches.is_present("no-cookies"),
+        cookie_jar: matches.value_of_os("cookie-jar").map(PathBuf::from),
+        etag_cache: matches.value_of_os("etag-cache").map(PathBuf::from),
+        dry_run: matches.is_present("dry-run"),
+        netrc: match (
+            matches.value_of_os("netrc-file"),
+            matches.is_present("netrc"),
+        ) {
+            (Some(path), _) => Some(httpclient::netrc::Netrc::read(Path::new(path))?),
+            (None, true) => {
+                let path = httpclient::netrc::default_path()
+                    .ok_or_else(|| anyhow::anyhow!("--netrc needs $HOME, to read ~/.netrc"))?;
+                Some(httpclient::netrc::Netrc::read(&path)?)
+            }
+            (None, false) => None,
+        },
+        interrupted: httpclient::interrupt::install(),
+        transport: Arc::new(httpclient::transport::Reqwest::new()),
+        record: None,
+        client: httpclient::client::ClientOptions {
+            max_redirects: if matches.is_present("no-follow") {
+                0
+            } else {
+                matches
+                    .value_of("max-redirects")
+                    .unwrap()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("--max-redirects expects a number"))?
+            },
+            proxy: matches.value_of("proxy").map(|proxy| proxy.to_string()),
+            proxy_user: match matches.value_of("proxy-user") {
+                Some(proxy_user) => match proxy_user.split_once(':') {
+                    Some((user, password)) => Some((user.to_string(), password.to_string())),
+                    None => return Err(anyhow::anyhow!("--proxy-user expects user:password")),
+                },
+                None => None,
+            },
+            no_proxy: matches.is_present("no-proxy"),
+            insecure: matches.is_present("insecure"),
+            decompress: !matches.is_present("no-decompress"),
+            user_agent: matches
+                .value_of("user-agent")
+                .unwrap_or(httpclient::client::DEFAULT_USER_AGENT)
+                .to_string(),
+            local_address: matches
+                .value_of("local-address")
+                .map(|address| {
+                    address.parse().map_err(|_| {
+                        anyhow::anyhow!("--local-address expects an IP address, not {}", address)
+                    })
+                })
+                .transpose()?,
+            pool_idle_timeout: matches
+                .value_of("pool-idle-timeout")
+                .map(|timeout| {
+                    httpclient::duration::parse(timeout)
+                        .map_err(|e| e.context("invalid --pool-idle-timeout"))
+                })
+                .transpose()?,
+            pool_max_idle_per_host: matches
+                .value_of("pool-max-idle-per-host")
+                .map(|max| {
+                    max.parse::<usize>()
+                        .map_err(|_| anyhow::anyhow!("--pool-max-idle-per-host expects a number"))
+                })
+                .transpose()?,
+            tcp_nodelay: matches.value_of("tcp-nodelay") == Some("on"),
+            tcp_keepalive: matches
+                .value_of("tcp-keepalive")
+                .map(|interval| {
+                    httpclient::duration::parse(interval)
+                        .map_err(|e| e.context("invalid --tcp-keepalive"))
+                })
+                .transpose()?
+                .filter(|interval| !interval.is_zero()),
+            keepalive: !matches.is_present("no-keepalive"),
+            ca_certificates: matches
+                .values_of_os("cacert")
+                .map(|paths| paths.map(PathBuf::from).collect())
+                .unwrap_or_default(),
+            ca_certificates_only: matches.is_present("cacert-only"),
+            connect_timeout: matches
+                .value_of("connect-timeout")
+                .map(|timeout| {
+                    httpclient::duration::parse(timeout)
+                        .map_err(|e| e.context("invalid --connect-timeout"))
+                })
+                .transpose()?,
+            resolve: matches
+                .values_of("resolve")
+                .map(|values| values.map(httpclient::client::Resolve::parse).collect())
+                .transpose()?
+                .unwrap_or_default(),
+            client_certificate: match (
+                matches.value_of_os("cert"),
+                matches.value_of_os("key"),
+                matches.value_of_os("cert-p12"),
+            ) {
+                (Some(certificate), Some(key), _) => {
+                    Some(httpclient::client::ClientCertificate::Pem {
+                        certificate: PathBuf::from(certificate),
+                        key: PathBuf::from(key),
+                    })
+                }
+                (_, _, Some(archive)) => Some(httpclient::client::ClientCertificate::Pkcs12 {
+                    archive: PathBuf::from(archive),
+                    password: matches.value_of("cert-password").unwrap_or("").to_string(),
+                }),
+                _ => None,
+            },
+        },
+    };
+    if let Some(path) = matches.value_of_os("record") {
+        options.record = Some(httpclient::archive::Recorder::new(Path::new(path)));
+    }
+    if let Some(path) = matches.value_of_os("replay") {
+        options.transport = Arc::new(httpclient::archive::Replay::open(Path::new(path))?);
+    }
+    if options.client.insecure {
+        eprintln!("warning: TLS certificates are not verified (--insecure)");
+    }
     let filepaths: Vec<_> = matches.values_of("INPUT").unwrap().collect();
-    let request_timeout: u64 = matches.value_of("timeout").unwrap().parse::<u64>()?;
     let selected_req_number_str = matches.value_of("request number").unwrap();
+    let filtering_tags = !options.tags.is_empty() || !options.exclude_tags.is_empty();
     let selected_req_number: isize = match selected_req_number_str {
+        // the tags select among all the requests, unless one is chosen
+        _ if filtering_tags && matches.occurrences_of("request number") == 0 => -1,
         "a" => -1,
         "" => 0,
         _ => selected_req_number_str.parse::<isize>()?,
     };
 
+    // non-Unicode values can't be substituted, as if they were missing
+    let env_vars: HashMap<String, String> = env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+
     for filepath in filepaths {
-        let rqsp = httpclient::worker::FileParser {};
-        let reqs = rqsp.parse_from_file(&filepath)?;
+        let mut rqsp = httpclient::worker::FileParser::with_vars(env_vars.clone())
+            .use_env(false)
+            .allow_shell(matches.is_present("allow-shell"));
+        if let Some(scheme) = matches.value_of("default-scheme") {
+            rqsp = rqsp.default_scheme(scheme);
+        }
+        if matches.is_present("http2-prior-knowledge") {
+            rqsp = rqsp.default_protocol("HTTP/2");
+        }
+        let mut reqs = match filepath {
+            "-" => {
+                let mut contents = String::new();
+                io::stdin().read_to_string(&mut contents)?;
+                rqsp.parse_from_str(&contents)?
+            }
+            _ => rqsp.parse_from_file(&filepath)?,
+        };
+        if filepath == "-" && reqs.iter().any(|req| req.body_stdin) {
+            return Err(anyhow::anyhow!(
+                "the .http file is read from the standard input, so it can't be the body of a request too (< - or # @body-stdin)"
+            ));
+        }
+        if matches.is_present("http1.1") {
+            for req in reqs
+                .iter_mut()
+                .filter(|req| req.protocol.starts_with("HTTP/2"))
+            {
+                req.protocol = "HTTP/1.1".to_string();
+            }
+        }
+        if reqs.is_empty() {
+            return Err(anyhow::anyhow!(
+                "no requests found in {}",
+                match filepath {
+                    "-" => "standard input",
+                    _ => filepath,
+                }
+            ));
+        }
 
-        httpclient::execute_requests(verbosity, request_timeout, reqs, selected_req_number)?;
+        httpclient::execute_requests(&options, reqs, selected_req_number)?;
     }
 
     Ok(())
 }
+
+fn tag_values(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(|value| value.to_string()).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,252 @@
+//! The credentials of `~/.netrc`, as curl reads them: `machine`, `login`
+//! and `password` tokens separated by spaces or newlines, with a `default`
+//! entry for the other hosts.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file of `--netrc`: `~/.netrc`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(PathBuf::from(std::env::var_os("HOME")?).join(".netrc"))
+}
+
+/// An entry of the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Machine {
+    /// the host, `None` for the `default` entry
+    pub host: Option<String>,
+    pub login: Option<String>,
+    pub password: Option<String>,
+}
+
+/// The entries of a netrc file, in their order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Netrc {
+    pub machines: Vec<Machine>,
+}
+
+impl Netrc {
+    pub fn read(path: &Path) -> Result<Netrc> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("unable to read the netrc file {}", path.display()))?;
+        Netrc::parse(&text).with_context(|| format!("invalid netrc file {}", path.display()))
+    }
+
+    /// Parses the tokens of a netrc file, ignoring the `account` ones and the
+    /// `macdef` macros, up to the following blank line, as well as the
+    /// lines starting with `#`; the values can be quoted, with `\` escapes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let netrc = httpclient::netrc::Netrc::parse(
+    ///     "machine api.example.com login me password s3cr3t\ndefault login anonymous",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(
+    ///     netrc.credentials("api.example.com", None),
+    ///     Some(("me".to_string(), "s3cr3t".to_string()))
+    /// );
+    /// ```
+    pub fn parse(text: &str) -> Result<Netrc> {
+        let mut tokens = Tokens::new(text);
+        let mut machines: Vec<Machine> = Vec::new();
+        while let Some(token) = tokens.next() {
+            let mut value = |name: &str| {
+                tokens
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing the value of {}", name))
+            };
+            match token.as_str() {
+                "machine" => machines.push(Machine {
+                    host: Some(value("machine")?),
+                    ..Machine::default()
+                }),
+                "default" => machines.push(Machine::default()),
+                "login" | "password" | "account" => {
+                    let value = value(&token)?;
+                    let machine = machines.last_mut().ok_or_else(|| {
+                        anyhow::anyhow!("{} outside of a machine or default entry", token)
+                    })?;
+                    match token.as_str() {
+                        "login" => machine.login = Some(value),
+                        "password" => machine.password = Some(value),
+                        _ => (),
+                    }
+                }
+                "macdef" => {
+                    value("macdef")?;
+                    tokens.skip_macro();
+                }
+                _ => return Err(anyhow::anyhow!("unexpected token {:?}", token)),
+            }
+        }
+        Ok(Netrc { machines })
+    }
+
+    /// The login and password for a host, whatever the port, as curl: the
+    /// ones of the first entry of the host, with the login of the URL if it
+    /// has one, or else of the `default` entry; none without a password.
+    pub fn credentials(&self, host: &str, login: Option<&str>) -> Option<(String, String)> {
+        let matches = |machine: &&Machine| match (&machine.login, login) {
+            (Some(machine_login), Some(login)) => machine_login == login,
+            _ => true,
+        };
+        let machine = self
+            .machines
+            .iter()
+            .filter(|machine| match &machine.host {
+                Some(machine_host) => machine_host.eq_ignore_ascii_case(host),
+                None => false,
+            })
+            .find(matches)
+            .or_else(|| {
+                self.machines
+                    .iter()
+                    .filter(|machine| machine.host.is_none())
+                    .find(matches)
+            })?;
+        let login = login
+            .map(|login| login.to_string())
+            .or_else(|| machine.login.clone())
+            .unwrap_or_default();
+        Some((login, machine.password.clone()?))
+    }
+}
+
+/// The tokens of a netrc file.
+struct Tokens<'a> {
+    text: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Tokens<'a> {
+        Tokens { text }
+    }
+
+    /// Skips the lines of a macro, up to a blank one.
+    fn skip_macro(&mut self) {
+        // the rest of the macdef line
+        let rest = self
+            .text
+            .find('\n')
+            .map(|end| end + 1)
+            .unwrap_or(self.text.len());
+        self.text = &self.text[rest..];
+        loop {
+            let end = self.text.find('\n').unwrap_or(self.text.len());
+            let line = &self.text[..end];
+            self.text = &self.text[(end + 1).min(self.text.len())..];
+            if line.trim().is_empty() {
+                return;
+            }
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            self.text = self.text.trim_start();
+            if !self.text.starts_with('#') {
+                break;
+            }
+            let end = self.text.find('\n').unwrap_or(self.text.len());
+            self.text = &self.text[end..];
+        }
+        if self.text.is_empty() {
+            return None;
+        }
+        let mut token = String::new();
+        if let Some(quoted) = self.text.strip_prefix('"') {
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => token.push('\n'),
+                        Some((_, 't')) => token.push('\t'),
+                        Some((_, escaped)) => token.push(escaped),
+                        None => (),
+                    },
+                    _ => token.push(c),
+                }
+            }
+            self.text = &quoted[end..];
+            return Some(token);
+        }
+        let end = self
+            .text
+            .find(char::is_whitespace)
+            .unwrap_or(self.text.len());
+        token.push_str(&self.text[..end]);
+        self.text = &self.text[end..];
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials() {
+        let netrc = Netrc::parse(
+            "# work\nmachine api.example.com\n  login ci password \"p@ss word\"\n\
+             machine api.example.com login admin password root\n\
+             macdef init\ncd /pub\n\n\
+             machine nopassword.example.com login me\n\
+             default login anonymous password guest account x\n",
+        )
+        .unwrap();
+        assert_eq!(netrc.machines.len(), 4);
+        let credentials =
+            |user: &str, password: &str| Some((user.to_string(), password.to_string()));
+        // the first entry, whatever the port
+        assert_eq!(
+            netrc.credentials("API.example.com", None),
+            credentials("ci", "p@ss word")
+        );
+        // the one of the login of the URL
+        assert_eq!(
+            netrc.credentials("api.example.com", Some("admin")),
+            credentials("admin", "root")
+        );
+        assert_eq!(
+            netrc.credentials("other.example.com", None),
+            credentials("anonymous", "guest")
+        );
+        assert_eq!(netrc.credentials("nopassword.example.com", None), None);
+        assert_eq!(
+            Netrc::parse("machine a.example.com login me")
+                .unwrap()
+                .credentials("b.example.com", None),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert_eq!(
+            Netrc::parse("login me").unwrap_err().to_string(),
+            "login outside of a machine or default entry"
+        );
+        assert_eq!(
+            Netrc::parse("machine").unwrap_err().to_string(),
+            "missing the value of machine"
+        );
+        assert_eq!(
+            Netrc::parse("machine a.example.com user me")
+                .unwrap_err()
+                .to_string(),
+            "unexpected token \"user\""
+        );
+    }
+}
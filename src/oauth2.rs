@@ -0,0 +1,234 @@
+use anyhow::Result;
+use reqwest::blocking::Client;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Parameters of an OAuth2 client-credentials grant, as given with
+/// `# @oauth2 token_url=... client_id=... client_secret=... [scope=...]`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+impl ClientCredentials {
+    /// Parses the `key=value` arguments of the `# @oauth2` directive.
+    pub fn parse(arguments: &str) -> Result<ClientCredentials> {
+        let mut params: HashMap<&str, &str> = HashMap::new();
+        for argument in arguments.split_whitespace() {
+            match argument.split_once('=') {
+                Some((key, value))
+                    if ["token_url", "client_id", "client_secret", "scope"].contains(&key) =>
+                {
+                    params.insert(key, value);
+                }
+                _ => return Err(anyhow::anyhow!("invalid oauth2 argument {}", argument)),
+            }
+        }
+        let required = |key: &str| {
+            params
+                .get(key)
+                .map(|v| v.to_string())
+                .ok_or_else(|| anyhow::anyhow!("missing oauth2 argument {}", key))
+        };
+        Ok(ClientCredentials {
+            token_url: required("token_url")?,
+            client_id: required("client_id")?,
+            client_secret: required("client_secret")?,
+            scope: params.get("scope").map(|v| v.to_string()),
+        })
+    }
+
+    /// Requests a new access token to the token endpoint, waiting `timeout`
+    /// at most, or forever if zero.
+    pub fn fetch_token(&self, timeout: Duration) -> Result<String> {
+        let mut request = Client::builder()
+            .timeout(None)
+            .build()?
+            .post(&self.token_url)
+            .form(&self.form());
+        if !timeout.is_zero() {
+            request = request.timeout(timeout);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        let body = response.text()?;
+        self.parse_token_response(status, &body)
+    }
+
+    /// Requests a new access token to the token endpoint, with an async
+    /// client.
+    #[cfg(feature = "async")]
+    pub async fn fetch_token_async(
+        &self,
+        client: &reqwest::Client,
+        timeout: Duration,
+    ) -> Result<String> {
+        let mut request = client.post(&self.token_url).form(&self.form());
+        if !timeout.is_zero() {
+            request = request.timeout(timeout);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        self.parse_token_response(status, &body)
+    }
+
+    fn form(&self) -> Vec<(&str, &str)> {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        form
+    }
+
+    fn parse_token_response(&self, status: reqwest::StatusCode, body: &str) -> Result<String> {
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "unable to get an OAuth2 token from {}: {}\n{}",
+                self.token_url,
+                status,
+                body
+            ));
+        }
+        let parsed = json::parse(body).map_err(|_| {
+            anyhow::anyhow!(
+                "invalid OAuth2 token response from {}: {}",
+                self.token_url,
+                body
+            )
+        })?;
+        parsed["access_token"]
+            .as_str()
+            .map(|token| token.to_string())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no access_token in OAuth2 token response from {}: {}",
+                    self.token_url,
+                    body
+                )
+            })
+    }
+}
+
+/// Keeps the tokens obtained during a run, so that requests with the same
+/// `# @oauth2` parameters don't fetch a new one each time.
+#[derive(Default)]
+pub struct TokenCache {
+    tokens: HashMap<ClientCredentials, String>,
+}
+
+impl TokenCache {
+    pub fn new() -> TokenCache {
+        TokenCache::default()
+    }
+
+    pub fn token(&mut self, credentials: &ClientCredentials, timeout: Duration) -> Result<String> {
+        if let Some(token) = self.tokens.get(credentials) {
+            return Ok(token.to_string());
+        }
+        let token = credentials.fetch_token(timeout)?;
+        self.tokens.insert(credentials.clone(), token.to_string());
+        Ok(token)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn token_async(
+        &mut self,
+        credentials: &ClientCredentials,
+        client: &reqwest::Client,
+        timeout: Duration,
+    ) -> Result<String> {
+        if let Some(token) = self.tokens.get(credentials) {
+            return Ok(token.to_string());
+        }
+        let token = credentials.fetch_token_async(client, timeout).await?;
+        self.tokens.insert(credentials.clone(), token.to_string());
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    /// Serves the given raw HTTP responses, one per connection, returning the
+    /// URL to connect to.
+    fn serve(responses: Vec<&'static str>) -> String {
+        let mut responses = responses.into_iter();
+        let address = test_server::serve(move |_| responses.next().unwrap_or_default());
+        format!("http://{}/token", address)
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let credentials = ClientCredentials::parse(
+            "token_url=https://example.com/token client_id=id client_secret=s",
+        )
+        .unwrap();
+        assert_eq!(credentials.token_url, "https://example.com/token");
+        assert_eq!(credentials.client_id, "id");
+        assert_eq!(credentials.client_secret, "s");
+        assert_eq!(credentials.scope, None);
+        assert_eq!(
+            ClientCredentials::parse("token_url=https://example.com/token client_id=id")
+                .unwrap_err()
+                .to_string(),
+            "missing oauth2 argument client_secret"
+        );
+        assert!(ClientCredentials::parse("audience=x").is_err());
+    }
+
+    #[test]
+    fn cached_token() {
+        let token_url = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 26\r\n\r\n{\"access_token\": \"abc123\"}",
+        ]);
+        let credentials = ClientCredentials {
+            token_url,
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+            scope: Some("read".to_string()),
+        };
+        let mut cache = TokenCache::new();
+        assert_eq!(
+            cache.token(&credentials, Duration::from_secs(5)).unwrap(),
+            "abc123"
+        );
+        // the server answers only once
+        assert_eq!(
+            cache.token(&credentials, Duration::from_secs(5)).unwrap(),
+            "abc123"
+        );
+    }
+
+    #[test]
+    fn token_endpoint_error() {
+        let token_url = serve(vec![
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 14\r\n\r\ninvalid_client",
+        ]);
+        let credentials = ClientCredentials {
+            token_url: token_url.to_string(),
+            client_id: "id".to_string(),
+            client_secret: "wrong".to_string(),
+            scope: None,
+        };
+        assert_eq!(
+            credentials
+                .fetch_token(Duration::from_secs(5))
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "unable to get an OAuth2 token from {}: 401 Unauthorized\ninvalid_client",
+                token_url
+            )
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::http1;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the progress line is drawn again.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether to show the progress: not with `--quiet`, nor when stderr isn't
+/// a terminal, as when it's redirected to a log.
+pub fn visible(quiet: bool) -> bool {
+    !quiet && atty::is(atty::Stream::Stderr)
+}
+
+/// The progress of a download, drawn in place on stderr, so that stdout
+/// stays clean for piping.
+pub struct Progress {
+    /// the `Content-Length`, when known
+    total: Option<u64>,
+    received: u64,
+    start: Instant,
+    drawn: Option<Instant>,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(total: Option<u64>, visible: bool) -> Progress {
+        Progress {
+            total,
+            received: 0,
+            start: Instant::now(),
+            drawn: None,
+            visible,
+        }
+    }
+
+    pub fn add(&mut self, bytes: u64) {
+        self.received += bytes;
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        if matches!(self.drawn, Some(drawn) if now - drawn < REDRAW_INTERVAL) {
+            return;
+        }
+        self.drawn = Some(now);
+        eprint!("\r{}\x1b[K", self.line(now - self.start));
+    }
+
+    /// Clears the progress line, returning how long the download took.
+    pub fn finish(&mut self) -> Duration {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+            io::stderr().flush().ok();
+        }
+        self.start.elapsed()
+    }
+
+    fn line(&self, elapsed: Duration) -> String {
+        let received = format_size(self.received);
+        let rate = format_rate(self.received, elapsed);
+        match self.total {
+            Some(total) if total > 0 => format!(
+                "{} / {} ({}%) {}",
+                received,
+                format_size(total),
+                self.received * 100 / total,
+                rate
+            ),
+            _ => format!("{} {}", received, rate),
+        }
+    }
+}
+
+/// Writes to `inner`, adding what's written to the progress.
+pub struct ProgressWriter<W: Write> {
+    pub inner: W,
+    pub progress: Progress,
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.progress.add(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The progress of sending a request body as it's read, shown if `visible`;
+/// the clones share what was sent, to report it once done.
+#[derive(Clone, Debug, Default)]
+pub struct Upload {
+    visible: bool,
+    sent: Arc<Mutex<Sent>>,
+}
+
+/// The bytes of a body sent, and how long reading them took.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sent {
+    pub size: u64,
+    pub elapsed: Duration,
+    /// what the server answered to `Expect: 100-continue`, if sent
+    pub continued: Option<http1::Continue>,
+}
+
+impl Upload {
+    pub fn new(visible: bool) -> Upload {
+        Upload {
+            visible,
+            sent: Arc::default(),
+        }
+    }
+
+    /// What the last reader made of this sent, sending the body again
+    /// starting over.
+    pub fn sent(&self) -> Sent {
+        *self.sent.lock().unwrap()
+    }
+
+    /// Wraps the body read, of `total` bytes when known.
+    pub fn reader<R: Read>(&self, inner: R, total: Option<u64>) -> ProgressReader<R> {
+        *self.sent.lock().unwrap() = Sent::default();
+        ProgressReader {
+            inner,
+            progress: Progress::new(total, self.visible),
+            upload: self.clone(),
+        }
+    }
+
+    pub fn set_continued(&self, continued: http1::Continue) {
+        self.sent.lock().unwrap().continued = Some(continued);
+    }
+}
+
+/// Reads from `inner`, adding what's read to the progress of an upload.
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    progress: Progress,
+    upload: Upload,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.add(read as u64);
+        let elapsed = match read {
+            0 => self.progress.finish(),
+            _ => self.progress.start.elapsed(),
+        };
+        let mut sent = self.upload.sent.lock().unwrap();
+        sent.size = self.progress.received;
+        sent.elapsed = elapsed;
+        Ok(read)
+    }
+}
+
+/// Formats a number of bytes in the largest binary unit it reaches, like
+/// `512 B`, `1.5 KiB` or `12.3 MiB`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(httpclient::progress::format_size(1536), "1.5 KiB");
+/// ```
+pub fn format_size(bytes: u64) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// Parses a number of bytes, optionally with a binary unit, like `512`,
+/// `64KiB` or `1.5 GiB`; `K`, `M` and `G` are the same units.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(httpclient::progress::parse_size("50MiB").unwrap(), 50 * 1024 * 1024);
+/// ```
+pub fn parse_size(text: &str) -> anyhow::Result<u64> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "invalid size {}: expected a number of bytes, optionally followed by KiB, MiB or GiB",
+            text
+        )
+    };
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1024,
+        "m" | "mib" => 1024 * 1024,
+        "g" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    match number.parse::<u64>() {
+        Ok(number) => number.checked_mul(multiplier).ok_or_else(invalid),
+        // the fractions of a unit, not of a byte
+        Err(_) if multiplier > 1 => number
+            .parse::<f64>()
+            .map(|number| (number * multiplier as f64) as u64)
+            .map_err(|_| invalid()),
+        Err(_) => Err(invalid()),
+    }
+}
+
+/// Formats the bytes received per second, like `14.3 MiB/s`.
+pub fn format_rate(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    if seconds == 0.0 {
+        return "- B/s".to_string();
+    }
+    format!("{}/s", format_size((bytes as f64 / seconds) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(100 * 1024 * 1024), "100.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        assert_eq!(
+            format_rate(15 * 1024 * 1024, Duration::from_secs(2)),
+            "7.5 MiB/s"
+        );
+        assert_eq!(format_rate(1, Duration::from_secs(0)), "- B/s");
+    }
+
+    #[test]
+    fn parsed_sizes() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64KiB").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("1.5 GiB").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_size("2m").unwrap(), 2 * 1024 * 1024);
+        for invalid in ["", "MiB", "1.5", "10 MB", "-1"] {
+            assert_eq!(
+                parse_size(invalid).unwrap_err().to_string(),
+                format!("invalid size {}: expected a number of bytes, optionally followed by KiB, MiB or GiB", invalid)
+            );
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let mut progress = Progress::new(Some(4 * 1024 * 1024), false);
+        progress.add(1024 * 1024);
+        assert_eq!(
+            progress.line(Duration::from_secs(1)),
+            "1.0 MiB / 4.0 MiB (25%) 1.0 MiB/s"
+        );
+        let mut progress = Progress::new(None, false);
+        progress.add(2048);
+        assert_eq!(progress.line(Duration::from_secs(2)), "2.0 KiB 1.0 KiB/s");
+        // nothing drawn, nothing to clear
+        progress.finish();
+        assert!(progress.drawn.is_none());
+    }
+
+    #[test]
+    fn upload() {
+        let upload = Upload::new(false);
+        let mut reader = upload.reader(&[7u8; 10000][..], Some(10000));
+        let mut buffer = [0; 4096];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 4096);
+        assert_eq!(upload.sent().size, 4096);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(upload.clone().sent().size, 10000);
+        // sent again
+        upload.reader(&b""[..], None);
+        assert_eq!(upload.sent(), Sent::default());
+    }
+}
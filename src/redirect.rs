@@ -0,0 +1,276 @@
+use crate::client::ClientOptions;
+use crate::cookies;
+use crate::request::Request;
+use crate::transport::{HttpTransport, Response};
+use anyhow::Result;
+use reqwest::{header, StatusCode, Url};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Headers not sent again when redirected to another host.
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization", "host"];
+
+/// A redirect response, followed to get the final one.
+#[derive(Debug, PartialEq)]
+pub struct Redirect {
+    pub status: StatusCode,
+    pub url: Url,
+    /// the `Location` header, resolved against `url`
+    pub location: Url,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            dest,
+            "{} {} -> {} - {:?}",
+            self.status, self.url, self.location, self.elapsed
+        )
+    }
+}
+
+/// Sends a request through `transport`, with `options` not following the
+/// redirects, then follows them one at a time, up to `max_redirects`, recording each one.
+///
+/// As browsers do, a `303 See Other`, or a `301` or `302` answering a
+/// `POST`, is followed by a `GET` without body, and the credentials aren't
+/// sent to other hosts; the cookies of `jar`, if any, are sent to each one,
+/// storing the ones set along the way.
+///
+/// The body of the final response is left to read, to print or save it.
+pub fn follow(
+    req: &Request,
+    transport: &dyn HttpTransport,
+    options: &ClientOptions,
+    timeout: Duration,
+    max_redirects: usize,
+    jar: Option<&Mutex<cookies::Jar>>,
+) -> Result<(Response, Vec<Redirect>)> {
+    let mut req = req.clone();
+    let mut url = req.target_url()?;
+    let mut requested = HashSet::new();
+    requested.insert(url.clone());
+    let mut redirects = Vec::new();
+    loop {
+        let start_instant = Instant::now();
+        let response = cookies::send(&req, transport, options, timeout, jar)?;
+        let status = response.status();
+        let location = match location(&url, status, response.headers())? {
+            Some(location) => location,
+            None => return Ok((response, redirects)),
+        };
+        // the body of a redirect isn't printed, yet it's part of its time
+        response.bytes()?;
+        let elapsed = start_instant.elapsed();
+        if redirects.len() == max_redirects {
+            return Err(anyhow::anyhow!(
+                "too many redirects: more than {} from {}",
+                max_redirects,
+                redirects.first().map(|r: &Redirect| &r.url).unwrap_or(&url)
+            ));
+        }
+        if !requested.insert(location.clone()) {
+            return Err(anyhow::anyhow!(
+                "redirect loop: {} redirects to {}, already requested",
+                url,
+                location
+            ));
+        }
+        req = redirected(&req, &url, &location, status);
+        redirects.push(Redirect {
+            status,
+            url,
+            location: location.clone(),
+            elapsed,
+        });
+        url = location;
+    }
+}
+
+/// Where a redirect response points to, `None` for the other responses.
+fn location(url: &Url, status: StatusCode, headers: &header::HeaderMap) -> Result<Option<Url>> {
+    if !matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    ) {
+        return Ok(None);
+    }
+    match headers.get(header::LOCATION) {
+        Some(location) => {
+            let location = location.to_str()?;
+            Ok(Some(url.join(location).map_err(|e| {
+                anyhow::anyhow!("invalid Location {} from {}: {}", location, url, e)
+            })?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The request to send to `to` after the redirect from `from`.
+fn redirected(req: &Request, from: &Url, to: &Url, status: StatusCode) -> Request {
+    let mut next = req.clone();
+    next.url = to.to_string();
+    next.url_parameters.clear();
+    next.fragment = None;
+    next.unicode_host = None;
+    let becomes_get = match status {
+        StatusCode::SEE_OTHER => next.method != "HEAD",
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => next.method == "POST",
+        _ => false,
+    };
+    if becomes_get {
+        next.method = "GET".to_string();
+        next.body.clear();
+        next.body_file = None;
+        next.body_stdin = false;
+        // no body to wait for
+        next.expect_continue = false;
+        next.headers.retain(|name, _| {
+            !name.eq_ignore_ascii_case("content-type")
+                && !name.eq_ignore_ascii_case("content-length")
+        });
+    }
+    if from.host_str() != to.host_str()
+        || from.port_or_known_default() != to.port_or_known_default()
+    {
+        next.headers.retain(|name, _| {
+            !SENSITIVE_HEADERS
+                .iter()
+                .any(|sensitive| name.eq_ignore_ascii_case(sensitive))
+        });
+        next.cookies.clear();
+        next.basic_auth = None;
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::OwnedResponse;
+    use crate::test_server;
+    use crate::transport::Reqwest;
+    use std::net::SocketAddr;
+
+    /// A server redirecting `/1` to `/2` with a `302`, `/2` to `/3` with a
+    /// `307`, `/loop` to itself, and answering the other paths with the
+    /// method of the request.
+    fn redirect_server() -> SocketAddr {
+        test_server::serve(|received| {
+            let mut request_line = received.split(' ');
+            let method = request_line.next().unwrap_or("").to_string();
+            let response = match request_line.next() {
+                Some("/1") => "HTTP/1.1 302 Found\r\nLocation: /2\r\n",
+                Some("/2") => "HTTP/1.1 307 Temporary Redirect\r\nLocation: /3\r\n",
+                Some("/loop") => "HTTP/1.1 302 Found\r\nLocation: /loop\r\n",
+                _ => "HTTP/1.1 200 OK\r\n",
+            };
+            format!(
+                "{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response,
+                method.len(),
+                method
+            )
+        })
+    }
+
+    fn follow_from(
+        path: &str,
+        method: &str,
+        max_redirects: usize,
+    ) -> Result<(OwnedResponse, Vec<Redirect>)> {
+        let address = redirect_server();
+        let mut req = Request::new();
+        req.method = method.to_string();
+        req.url = format!("http://{}{}", address, path);
+        req.body = b"{}".to_vec();
+        let options = ClientOptions {
+            max_redirects: 0,
+            ..ClientOptions::default()
+        };
+        let (response, redirects) = follow(
+            &req,
+            &Reqwest::new(),
+            &options,
+            Duration::from_secs(10),
+            max_redirects,
+            None,
+        )?;
+        Ok((OwnedResponse::from_response(response)?, redirects))
+    }
+
+    #[test]
+    fn redirect_chain() {
+        let (response, redirects) = follow_from("/1", "POST", 10).unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        // the 302 turns the POST into a GET, kept by the 307
+        assert_eq!(response.text(), "GET");
+        let hops: Vec<(StatusCode, &str, &str)> = redirects
+            .iter()
+            .map(|r| (r.status, r.url.path(), r.location.path()))
+            .collect();
+        assert_eq!(
+            hops,
+            vec![
+                (StatusCode::FOUND, "/1", "/2"),
+                (StatusCode::TEMPORARY_REDIRECT, "/2", "/3")
+            ]
+        );
+
+        let (response, redirects) = follow_from("/2", "PUT", 10).unwrap();
+        assert_eq!(response.text(), "PUT");
+        assert_eq!(redirects.len(), 1);
+    }
+
+    #[test]
+    fn redirect_limits() {
+        let error = follow_from("/1", "GET", 1).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("too many redirects: more than 1 from http://"));
+        let error = follow_from("/loop", "GET", 10).err().unwrap();
+        assert!(error.to_string().starts_with("redirect loop: http://"));
+    }
+
+    #[test]
+    fn credentials_to_other_hosts() {
+        let mut req = Request::new();
+        req.method = "POST".to_string();
+        req.body = b"{}".to_vec();
+        req.headers
+            .insert("Authorization".to_string(), "Bearer t".to_string());
+        req.headers
+            .insert("Content-Type".to_string(), "application/json".to_string());
+        req.basic_auth = Some(("user".to_string(), "password".to_string()));
+        let from = Url::parse("https://example.com/a").unwrap();
+
+        let same_host = redirected(
+            &req,
+            &from,
+            &Url::parse("https://example.com/b").unwrap(),
+            StatusCode::PERMANENT_REDIRECT,
+        );
+        assert_eq!(same_host.method, "POST");
+        assert_eq!(same_host.body, b"{}");
+        assert_eq!(same_host.headers.len(), 2);
+        assert!(same_host.basic_auth.is_some());
+
+        let other_host = redirected(
+            &req,
+            &from,
+            &Url::parse("https://example.org/b").unwrap(),
+            StatusCode::SEE_OTHER,
+        );
+        assert_eq!(other_host.method, "GET");
+        assert_eq!(other_host.body, b"");
+        assert!(other_host.headers.is_empty());
+        assert!(other_host.basic_auth.is_none());
+    }
+}
@@ -1,89 +1,731 @@
+use crate::error::Error;
+#[cfg(unix)]
+use crate::unix;
+use crate::{assertion, client, color, http1, oauth2, progress, sigv4, stream, transport, xml};
 use anyhow::{Context, Result};
 use reqwest::blocking::{Client, Response};
 use reqwest::header;
-use reqwest::Method;
+use reqwest::{Method, StatusCode, Version};
 use std::collections::HashMap;
 use std::fmt;
-use std::time::Duration;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant, SystemTime};
 
+#[derive(Clone)]
 pub struct Request {
+    pub name: Option<String>,
+    /// the `# @tag` labels, to select the requests to execute
+    pub tags: Vec<String>,
+    /// the names of the requests executed before this one with
+    /// `# @depends-on`, that is skipped if any of them fails
+    pub depends_on: Vec<String>,
     pub method: String,
     pub url: String,
-    pub url_parameters: Vec<(String, String)>,
+    /// the host as written, when it's an internationalized domain name, that
+    /// is in its punycode form in `url`
+    pub unicode_host: Option<String>,
+    /// the part of the URL after `#`, not sent to the server
+    pub fragment: Option<String>,
+    /// keys and values, `None` for the keys sent without a value
+    pub url_parameters: Vec<(String, Option<String>)>,
+    /// whether to percent-encode the URL parameters (`# @no-encode` sends
+    /// them as written)
+    pub encode_url_parameters: bool,
+    /// whether the URL parameters replace the ones with the same key in the
+    /// query of the URL (`# @merge-params replace`), instead of following them
+    pub replace_url_parameters: bool,
     pub headers: HashMap<String, String>,
+    pub cookies: Vec<(String, String)>,
+    /// user and password, sent base64-encoded in the `Authorization` header
+    pub basic_auth: Option<(String, String)>,
+    /// region and service to sign the request for with AWS SigV4
+    pub aws_sigv4: Option<(String, String)>,
+    /// client credentials to get the bearer token with, before executing
+    pub oauth2: Option<oauth2::ClientCredentials>,
     pub protocol: String,
-    pub body: String,
+    /// `http` or `https`, used with the `Host` header when the URL is a path
+    pub scheme: String,
+    /// the bytes sent: the text of the `.http` files, or any of the
+    /// requests built by hand
+    pub body: Vec<u8>,
+    /// the file whose bytes are sent as they are, instead of `body`
+    /// (`< path`); it's read when sending the request
+    pub body_file: Option<PathBuf>,
+    /// the file whose modification time is sent as `If-Modified-Since`
+    /// (`# @if-newer path`), when it exists
+    pub if_newer: Option<PathBuf>,
+    /// whether the body is streamed from the standard input instead
+    /// (`< -` or `# @body-stdin`)
+    pub body_stdin: bool,
+    /// the progress of sending the body streamed from the standard input or
+    /// from the file, if tracked
+    pub upload: Option<progress::Upload>,
+    /// whether the body waits for a `100 Continue` from the server
+    /// (`# @expect-continue`), or for `http1::CONTINUE_TIMEOUT`
+    pub expect_continue: bool,
+    /// how many times to send the request again when it fails (`# @retry`),
+    /// instead of the default
+    pub retries: Option<u32>,
+    /// the wait before the first retry (`# @retry N backoff=2s`), instead of
+    /// the default
+    pub retry_delay: Option<Duration>,
+    /// how long to wait before sending the request (`# @delay`), instead of
+    /// the default wait between requests
+    pub delay: Option<Duration>,
+    /// whether to follow the redirects, unless disabled by `# @no-redirect`
+    pub follow_redirects: bool,
+    /// whether to decompress the body of the response, unless disabled by
+    /// `# @no-decompress`
+    pub decompress: bool,
+    /// whether the `ETag` and `Last-Modified` of the response are kept, to
+    /// send the request again conditionally (`# @cache`)
+    pub cache: bool,
+    /// whether the response is printed as it arrives (`# @stream`), as the
+    /// event streams are
+    pub stream: bool,
+    /// the proxy to send the request through (`# @proxy`), instead of the
+    /// default one
+    pub proxy: Option<String>,
+    /// whether to accept any TLS certificate (`# @insecure`)
+    pub insecure: bool,
+    /// the certificate to authenticate with (`# @client-cert`), instead of
+    /// the one of the options
+    pub client_certificate: Option<client::ClientCertificate>,
+    /// the addresses of some hosts, instead of the ones of the DNS
+    /// (`# @resolve`)
+    pub resolve: Vec<client::Resolve>,
+    /// the unix domain socket to send the request to (`# @unix-socket`),
+    /// as a `unix:///path/to.sock:/path` URL does
+    pub unix_socket: Option<PathBuf>,
+    /// how long connecting to the server can take (`# @connect-timeout`)
+    pub connect_timeout: Option<Duration>,
+    /// the address to connect from (`# @local-address`)
+    pub local_address: Option<std::net::IpAddr>,
+    /// how long the request can take (`# @timeout`), instead of the timeout
+    /// given to `execute_with`; zero for no timeout
+    pub timeout: Option<Duration>,
+    /// the file to write the response body to (`>> file`), instead of
+    /// printing it
+    pub output: Option<OutputFile>,
+    /// how much of the body is read of the responses read by hand, from the
+    /// unix sockets or after an `Expect: 100-continue`, and one more byte
+    /// telling it's longer; zero for all of it
+    pub max_response_size: u64,
+    /// `??` checks on the response, not sent to the server
+    pub assertions: Vec<assertion::Assertion>,
+    /// the `{{$randomString}}` and `{{$randomHex}}` placeholders of the
+    /// request, with the values generated for them
+    pub generated_values: Vec<(String, String)>,
+    /// values read from the keyring, hidden when printing the request
+    pub secrets: Vec<String>,
 }
 
 impl Request {
     pub fn new() -> Request {
         Request {
+            name: None,
+            tags: Vec::new(),
+            depends_on: Vec::new(),
             headers: HashMap::new(),
+            cookies: Vec::new(),
+            basic_auth: None,
+            aws_sigv4: None,
+            oauth2: None,
             url: "".to_string(),
+            unicode_host: None,
+            fragment: None,
             url_parameters: Vec::new(),
+            encode_url_parameters: true,
+            replace_url_parameters: false,
             method: "".to_string(),
             protocol: "HTTP/1.1".to_string(),
-            body: "".to_string(),
+            scheme: "https".to_string(),
+            body: Vec::new(),
+            body_file: None,
+            if_newer: None,
+            body_stdin: false,
+            upload: None,
+            expect_continue: false,
+            retries: None,
+            retry_delay: None,
+            delay: None,
+            follow_redirects: true,
+            decompress: true,
+            cache: false,
+            stream: false,
+            proxy: None,
+            insecure: false,
+            client_certificate: None,
+            resolve: Vec::new(),
+            unix_socket: None,
+            connect_timeout: None,
+            local_address: None,
+            timeout: None,
+            output: None,
+            max_response_size: 0,
+            assertions: Vec::new(),
+            generated_values: Vec::new(),
+            secrets: Vec::new(),
         }
     }
 
-    pub fn execute(&self, timeout: u64) -> anyhow::Result<Response> {
-        let client = Client::new();
-        let response_body = client
-            .request(
-                Method::from_bytes(self.method.as_bytes())?,
-                self.get_url_with_parameters().with_context(|| {
-                    format!(
-                        "unable to get valid URL to call for request\nrequest URL: {}\nrequest URL params: {:#?}",
-                        self.url, self.url_parameters,
-                    )
-                })?,
-            )
-            .headers(
-                self.format_headers()
-                    .with_context(|| format!("unable to format headers {:#?}", self.headers))?,
-            )
-            .timeout(Duration::new(timeout, 0))
-            .body(self.body.to_string())
-            .send()?;
+    /// Sends the request, that can take `timeout`, or forever if zero.
+    pub fn execute(&self, timeout: Duration) -> anyhow::Result<Response> {
+        self.execute_with(&self.client(&client::ClientOptions::default())?, timeout)
+    }
+
+    /// Builds a client fit for the protocol of the request, that can be
+    /// reused to send it many times with `execute_with`.
+    pub fn client(&self, options: &client::ClientOptions) -> Result<Client> {
+        let version = self.version()?;
+        let mut client_builder = options.for_request(self).blocking_builder()?;
+        if version == Version::HTTP_10 {
+            // no keep-alive: never reuse the connection
+            client_builder = client_builder.pool_max_idle_per_host(0);
+        } else if version == Version::HTTP_2 {
+            client_builder = client_builder.http2_prior_knowledge();
+        }
+        // the timeout is the one of each request, if any, as with the async
+        // clients: not the 30 seconds of the blocking ones
+        client_builder
+            .timeout(None)
+            .build()
+            .map_err(|e| options.explain_build_error(e))
+    }
+
+    /// Like `client`, for `execute_async`.
+    #[cfg(feature = "async")]
+    pub fn async_client(&self, options: &client::ClientOptions) -> Result<reqwest::Client> {
+        let version = self.version()?;
+        let mut client_builder = options.for_request(self).async_builder()?;
+        if version == Version::HTTP_10 {
+            client_builder = client_builder.pool_max_idle_per_host(0);
+        } else if version == Version::HTTP_2 {
+            client_builder = client_builder.http2_prior_knowledge();
+        }
+        client_builder
+            .build()
+            .map_err(|e| options.explain_build_error(e))
+    }
+
+    /// Sends the request with a client of `client`, waiting for the response
+    /// `timeout`, or forever if zero, unless `# @timeout` says otherwise.
+    pub fn execute_with(&self, client: &Client, timeout: Duration) -> Result<Response> {
+        if self.body_stdin {
+            return self.send_with(client, timeout, Some((io::stdin(), None)));
+        }
+        match &self.body_file {
+            // the signature needs the whole body
+            Some(path) if self.aws_sigv4.is_none() => {
+                let open_error = || format!("unable to read the body file {}", path.display());
+                let file = File::open(path).with_context(open_error)?;
+                let size = file.metadata().with_context(open_error)?.len();
+                self.send_with(client, timeout, Some((file, Some(size))))
+            }
+            _ => self.send_with::<io::Empty>(client, timeout, None),
+        }
+    }
+
+    /// Sends the request as `execute_with` does, streaming the body from
+    /// `stream`, if any, of the size given or else with chunked transfer
+    /// encoding.
+    fn send_with<R: Read + Send + 'static>(
+        &self,
+        client: &Client,
+        timeout: Duration,
+        stream: Option<(R, Option<u64>)>,
+    ) -> Result<Response> {
+        let (method, url, version, headers, body) = self.prepare(stream.is_some())?;
+        // the one of the wait between the events instead
+        let timeout = self.effective_timeout(timeout).filter(|_| !self.streams());
+        let (body, stream) = match stream {
+            // the unix sockets get the whole body at once
+            Some((stream, _)) if self.unix_socket.is_some() || url.scheme() == "unix" => {
+                (read_all(stream)?, None)
+            }
+            stream => (body, stream),
+        };
+        if let Some(response) =
+            self.send_to_unix_socket(&method, &url, version, &headers, &body, timeout)?
+        {
+            return Ok(Response::from(response));
+        }
+        if self.expect_continue {
+            let (stream, size): (Box<dyn Read>, _) = match stream {
+                Some((stream, size)) => (Box::new(stream), size),
+                None => {
+                    let size = body.len() as u64;
+                    (Box::new(io::Cursor::new(body)), Some(size))
+                }
+            };
+            let stream: Box<dyn Read> = match &self.upload {
+                Some(upload) => Box::new(upload.reader(stream, size)),
+                None => stream,
+            };
+            let (response, continued) = http1::send_expecting_continue(
+                &method,
+                &url,
+                version,
+                &headers,
+                stream,
+                size,
+                timeout,
+                http1::CONTINUE_TIMEOUT,
+                self.max_response_size,
+            )?;
+            if let Some(upload) = &self.upload {
+                upload.set_continued(continued);
+            }
+            return Ok(Response::from(response));
+        }
+        let mut request = client
+            .request(method, url)
+            .version(version)
+            .headers(headers);
+        request = match (stream, &self.upload) {
+            (Some((stream, Some(size))), Some(upload)) => request.body(
+                reqwest::blocking::Body::sized(upload.reader(stream, Some(size)), size),
+            ),
+            (Some((stream, None)), Some(upload)) => {
+                request.body(reqwest::blocking::Body::new(upload.reader(stream, None)))
+            }
+            (Some((stream, Some(size))), None) => {
+                request.body(reqwest::blocking::Body::sized(stream, size))
+            }
+            (Some((stream, None)), None) => request.body(reqwest::blocking::Body::new(stream)),
+            (None, _) => request.body(body),
+        };
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let start_instant = Instant::now();
+        request.send().map_err(|e| {
+            Error::from_reqwest(&self.method, &self.url, start_instant.elapsed(), e).into()
+        })
+    }
+
+    /// Sends the request with an async client, like the one of
+    /// `async_client`; the timeout is the one of `execute_with`.
+    ///
+    /// The body file and the standard input are read, and the unix sockets
+    /// written to, on the blocking pool of the runtime, not to block its
+    /// workers.
+    #[cfg(feature = "async")]
+    pub async fn execute_async(
+        &self,
+        client: &reqwest::Client,
+        timeout: Duration,
+    ) -> Result<reqwest::Response> {
+        let (method, url, version, headers, body) =
+            match self.body_stdin || self.body_file.is_some() {
+                true => {
+                    let req = self.clone();
+                    blocking(move || {
+                        let (method, url, version, headers, mut body) = req.prepare(false)?;
+                        if req.body_stdin {
+                            // read at once, not streamed
+                            body = read_all(io::stdin())?;
+                        }
+                        Ok((method, url, version, headers, body))
+                    })
+                    .await?
+                }
+                false => self.prepare(false)?,
+            };
+        let timeout = self.effective_timeout(timeout);
+        if self.unix_socket.is_some() || url.scheme() == "unix" {
+            let req = self.clone();
+            let response = blocking(move || {
+                req.send_to_unix_socket(&method, &url, version, &headers, &body, timeout)
+            })
+            .await?;
+            if let Some(response) = response {
+                return Ok(reqwest::Response::from(response));
+            }
+            return Err(anyhow::anyhow!("no unix socket for {}", self.url));
+        }
+        let mut request = client
+            .request(method, url)
+            .version(version)
+            .headers(headers)
+            .body(body);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let start_instant = Instant::now();
+        request.send().await.map_err(|e| {
+            Error::from_reqwest(&self.method, &self.url, start_instant.elapsed(), e).into()
+        })
+    }
 
-        Ok(response_body)
+    /// Tells whether the response is printed as it arrives, with
+    /// `# @stream` or when only accepting an event stream or JSON lines.
+    pub fn streams(&self) -> bool {
+        self.stream
+            || self.headers.iter().any(|(name, value)| {
+                name.eq_ignore_ascii_case("accept") && stream::Format::accepted(value)
+            })
     }
 
-    fn format_headers(&self) -> Result<header::HeaderMap> {
+    /// The timeout of `# @timeout`, or else `timeout`: none if zero, never
+    /// an immediate one.
+    pub fn effective_timeout(&self, timeout: Duration) -> Option<Duration> {
+        Some(self.timeout.unwrap_or(timeout)).filter(|timeout| !timeout.is_zero())
+    }
+
+    /// Builds the request as it would be sent, without sending it, to tell
+    /// whether it can be: the URL, the headers and the body are checked.
+    pub fn check(&self) -> Result<()> {
+        self.prepare(false).map(|_| ())
+    }
+
+    /// Builds what's sent for the request, whatever the client: the method,
+    /// the URL with the parameters, the HTTP version, the headers, signed if
+    /// needed, and the body, left empty when `streamed` instead.
+    #[allow(clippy::type_complexity)]
+    fn prepare(
+        &self,
+        streamed: bool,
+    ) -> Result<(Method, reqwest::Url, Version, header::HeaderMap, Vec<u8>)> {
+        let version = self.version()?;
+        let url = self.target_url()?;
+        let mut headers = self.format_headers()?;
+        let body = match streamed {
+            true => Vec::new(),
+            false => self.body_bytes()?,
+        };
+        if self.body_stdin && self.aws_sigv4.is_some() {
+            return Err(anyhow::anyhow!(
+                "the body read from the standard input can't be signed with # @aws-sigv4"
+            ));
+        }
+        if let Some((region, service)) = &self.aws_sigv4 {
+            let signer = sigv4::Signer {
+                region: region.to_string(),
+                service: service.to_string(),
+                credentials: sigv4::Credentials::from_env()?,
+            };
+            let signature_headers =
+                signer.sign(&self.method, &url, &headers, &body, SystemTime::now())?;
+            headers.extend(signature_headers);
+        }
+        Ok((
+            Method::from_bytes(self.method.as_bytes())?,
+            url,
+            version,
+            headers,
+            body,
+        ))
+    }
+
+    /// The bytes of the body: the ones of the `< path` file, or the text;
+    /// none for the standard input, that is read when sending.
+    pub fn body_bytes(&self) -> Result<Vec<u8>> {
+        if self.body_stdin {
+            return Ok(Vec::new());
+        }
+        match &self.body_file {
+            Some(path) => fs::read(path)
+                .with_context(|| format!("unable to read the body file {}", path.display())),
+            None => Ok(self.body.clone()),
+        }
+    }
+
+    /// Sends the request through its unix domain socket, if it has one.
+    #[cfg(unix)]
+    fn send_to_unix_socket(
+        &self,
+        method: &Method,
+        url: &reqwest::Url,
+        version: Version,
+        headers: &header::HeaderMap,
+        body: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<Option<http::Response<Vec<u8>>>> {
+        match unix::target(self.unix_socket.as_deref(), url)? {
+            Some((socket, url)) => Ok(Some(unix::send(
+                &socket,
+                method,
+                &url,
+                version,
+                headers,
+                body,
+                timeout,
+                self.max_response_size,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn send_to_unix_socket(
+        &self,
+        _method: &Method,
+        url: &reqwest::Url,
+        _version: Version,
+        _headers: &header::HeaderMap,
+        _body: &[u8],
+        _timeout: Option<Duration>,
+    ) -> Result<Option<http::Response<Vec<u8>>>> {
+        match self.unix_socket.is_some() || url.scheme() == "unix" {
+            true => Err(anyhow::anyhow!("unix sockets are only supported on unix")),
+            false => Ok(None),
+        }
+    }
+
+    /// The HTTP version of the `protocol` of the request.
+    fn version(&self) -> Result<Version, Error> {
+        parse_http_version(&self.protocol).ok_or_else(|| Error::UnsupportedProtocol {
+            method: self.method.to_string(),
+            url: self.url.to_string(),
+            protocol: self.protocol.to_string(),
+        })
+    }
+
+    /// The URL the request is sent to, with the parameters and without the
+    /// fragment.
+    pub fn target_url(&self) -> Result<reqwest::Url> {
+        let invalid = |reason: String| Error::InvalidUrl {
+            method: self.method.to_string(),
+            url: self.url.to_string(),
+            reason,
+        };
+        let url = self
+            .get_url_with_parameters()
+            .map_err(|e| invalid(format!("{:#}", e)))?;
+        let mut url = reqwest::Url::parse(&url).map_err(|e| invalid(e.to_string()))?;
+        // the fragment is only meaningful to the client
+        url.set_fragment(None);
+        Ok(url)
+    }
+
+    pub(crate) fn format_headers(&self) -> Result<header::HeaderMap, Error> {
+        let value = |name: &str, value: &str| {
+            header::HeaderValue::from_str(value).map_err(|_| Error::InvalidHeaderValue {
+                method: self.method.to_string(),
+                url: self.url.to_string(),
+                name: name.to_string(),
+            })
+        };
         let mut reqw_headers = header::HeaderMap::new();
         for v in self.headers.iter() {
-            reqw_headers.append(
-                header::HeaderName::from_bytes(v.0.as_bytes())?,
-                header::HeaderValue::from_str(v.1)?,
+            let name = header::HeaderName::from_bytes(v.0.as_bytes()).map_err(|_| {
+                Error::InvalidHeaderName {
+                    method: self.method.to_string(),
+                    url: self.url.to_string(),
+                    name: v.0.to_string(),
+                }
+            })?;
+            reqw_headers.append(name, value(v.0, v.1)?);
+        }
+        if let Some(cookie) = self.cookie_header() {
+            reqw_headers.insert(header::COOKIE, value("Cookie", &cookie)?);
+        }
+        if let Some((user, password)) = &self.basic_auth {
+            let credentials = base64::encode(format!("{}:{}", user, password));
+            reqw_headers.insert(
+                header::AUTHORIZATION,
+                value("Authorization", &format!("Basic {}", credentials))?,
             );
         }
         Ok(reqw_headers)
     }
 
+    /// Merges the request cookies into a single `Cookie` header value.
+    fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        let cookies: Vec<String> = self
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        Some(cookies.join("; "))
+    }
+
     fn get_url_with_parameters(&self) -> Result<String> {
-        let url: reqwest::Url;
-        if self.url_parameters.len() > 0 {
-            url = reqwest::Url::parse_with_params(&self.url, self.url_parameters.iter())?;
+        let absolute_url = self.absolute_url()?;
+        let mut url = reqwest::Url::parse(&absolute_url)?;
+        let encode = |component: &str| {
+            if self.encode_url_parameters {
+                encode_query_component(component)
+            } else {
+                component.to_string()
+            }
+        };
+        let parameters: Vec<(String, Option<String>)> = self
+            .url_parameters
+            .iter()
+            .map(|(key, value)| (encode(key), value.as_deref().map(encode)))
+            .collect();
+        // the pairs of the URL first, without the empty ones left by a
+        // trailing `?` or `&`
+        let mut query: Vec<String> = url
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or("");
+                !(self.replace_url_parameters && parameters.iter().any(|(k, _)| k == key))
+            })
+            .map(|pair| pair.to_string())
+            .collect();
+        for (key, value) in parameters {
+            query.push(match value {
+                Some(value) => format!("{}={}", key, value),
+                None => key,
+            });
+        }
+        // characters that can't be in a URL at all are encoded anyway
+        if query.is_empty() {
+            url.set_query(None);
         } else {
-            url = reqwest::Url::parse(&self.url)?;
+            url.set_query(Some(&query.join("&")));
         }
+        url.set_fragment(self.fragment.as_deref());
         Ok(url.as_str().to_string())
     }
 
+    /// Builds the URL of an origin-form request (`GET /path HTTP/1.1`) from
+    /// its `Host` header; other URLs are returned as they are.
+    fn absolute_url(&self) -> Result<String> {
+        if !self.url.starts_with('/') {
+            return Ok(self.url.to_string());
+        }
+        // the servers of the unix sockets are local
+        let host = match (self.host_header(), &self.unix_socket) {
+            (Some(host), _) => host,
+            (None, Some(_)) => "localhost",
+            (None, None) => {
+                return Err(anyhow::anyhow!(
+                    "the URL {} is a path, but there's no Host header",
+                    self.url
+                ))
+            }
+        };
+        // and without TLS
+        let scheme = match self.unix_socket {
+            Some(_) => "http",
+            None => &self.scheme,
+        };
+        Ok(format!("{}://{}{}", scheme, host.trim(), self.url))
+    }
+
+    /// The value of the `Host` header, if any.
+    pub fn host_header(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+            .map(|(_, value)| value.as_str())
+    }
+
     fn print_request_headers(&self) -> String {
         let mut headers_string_buffer = "".to_string();
         for k in &self.headers {
             headers_string_buffer.push_str(&format!("   {}: {:?}\n", k.0, k.1));
         }
+        if let Some(cookie) = self.cookie_header() {
+            headers_string_buffer.push_str(&format!("   Cookie: {:?}\n", cookie));
+        }
+        if self.basic_auth.is_some() {
+            // never show the credentials, not even encoded
+            headers_string_buffer.push_str("   Authorization: \"Basic <redacted>\"\n");
+        }
         headers_string_buffer
     }
 
     fn print_url_parameters(&self) -> String {
         let mut buffer = "".to_string();
         for k in &self.url_parameters {
-            buffer.push_str(&format!("   {}: {}\n", k.0, k.1));
+            match &k.1 {
+                Some(value) => buffer.push_str(&format!("   {}: {}\n", k.0, value)),
+                None => buffer.push_str(&format!("   {} (no value)\n", k.0)),
+            }
+        }
+        buffer
+    }
+
+    /// The request as printed with `-vv`, with the secrets replaced by `***`.
+    pub fn redacted(&self) -> String {
+        self.redact(&self.to_string())
+    }
+
+    /// `text`, with the secrets of the request replaced by `***`.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in self.secrets.iter().filter(|secret| !secret.is_empty()) {
+            text = text.replace(secret.as_str(), "***");
+        }
+        text
+    }
+
+    /// The body as printed with `-vv`: for a `< path` file, its size, along
+    /// with the hex dump of its first bytes when binary.
+    fn print_body(&self) -> String {
+        if self.body_stdin {
+            return "< - (standard input)".to_string();
+        }
+        let path = match &self.body_file {
+            Some(path) => path,
+            None => {
+                return match std::str::from_utf8(&self.body) {
+                    Ok(text) => text.to_string(),
+                    Err(_) => format!(
+                        "({} bytes)\n{}",
+                        self.body.len(),
+                        hex_dump(&self.body[..self.body.len().min(BODY_PREVIEW_SIZE)])
+                    ),
+                }
+            }
+        };
+        let mut preview = [0; BODY_PREVIEW_SIZE];
+        let read = File::open(path).and_then(|mut file| {
+            let size = file.metadata()?.len();
+            let mut read = 0;
+            loop {
+                match file.read(&mut preview[read..])? {
+                    0 => break,
+                    n => read += n,
+                }
+                if read == preview.len() {
+                    break;
+                }
+            }
+            Ok((size, read))
+        });
+        let (size, read) = match read {
+            Ok(read) => read,
+            Err(e) => return format!("< {} ({})", path.display(), e),
+        };
+        let preview = &preview[..read];
+        // a character cut at the end of the preview is still text
+        let binary = preview.contains(&0)
+            || matches!(std::str::from_utf8(preview), Err(e) if e.error_len().is_some());
+        match binary {
+            true => format!(
+                "< {} ({} bytes)\n{}",
+                path.display(),
+                size,
+                hex_dump(preview)
+            ),
+            false => format!("< {} ({} bytes)", path.display(), size),
+        }
+    }
+
+    fn print_generated_values(&self) -> String {
+        if self.generated_values.is_empty() {
+            return "".to_string();
+        }
+        let mut buffer = "generated values:\n".to_string();
+        for (placeholder, value) in &self.generated_values {
+            buffer.push_str(&format!("   {}: {}\n", placeholder, value));
         }
+        buffer.push('\n');
         buffer
     }
 }
@@ -92,58 +734,1104 @@ impl fmt::Display for Request {
     fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
         write!(
             dest,
-            "{} {} {}\nheaders:\n{}\nurl parameters:\n{}\nbody:\n{}",
+            "{} {}{} {}\n{}headers:\n{}\nurl parameters:\n{}\n{}body:\n{}",
             self.method,
             self.url,
+            match &self.fragment {
+                Some(fragment) => format!("#{}", fragment),
+                None => "".to_string(),
+            },
             self.protocol,
+            match &self.unicode_host {
+                Some(host) => format!("host: {}\n", host),
+                None => "".to_string(),
+            },
             self.print_request_headers(),
             self.print_url_parameters(),
-            self.body,
+            self.print_generated_values(),
+            self.print_body(),
         )
     }
 }
 
-fn print_response_headers(h: &header::HeaderMap) -> String {
+/// Reads the whole body of `< -` from `stream`, the standard input but in
+/// the tests.
+/// Runs `task` on the blocking pool of the runtime, its panics being the
+/// ones of the caller.
+#[cfg(feature = "async")]
+async fn blocking<T, F>(task: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(task).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_all(mut stream: impl Read) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    stream
+        .read_to_end(&mut body)
+        .context("unable to read the body from the standard input")?;
+    Ok(body)
+}
+
+/// Percent-encodes a URL parameter key or value, leaving the sequences that
+/// are already encoded, like `%20`, as they are.
+fn encode_query_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut encoded = String::new();
+    for (i, &b) in bytes.iter().enumerate() {
+        let already_encoded = b == b'%'
+            && bytes.len() > i + 2
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit();
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b'!' | b'$' | b'\'' | b'(' | b')' | b'*' | b',' | b';' | b':' | b'@' | b'/' | b'?' => {
+                encoded.push(b as char)
+            }
+            b'%' if already_encoded => encoded.push('%'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+/// Maps the protocol of the request line to the HTTP version to use.
+pub fn parse_http_version(protocol: &str) -> Option<Version> {
+    match protocol {
+        "HTTP/1.0" => Some(Version::HTTP_10),
+        "HTTP/1.1" => Some(Version::HTTP_11),
+        "HTTP/2" | "HTTP/2.0" => Some(Version::HTTP_2),
+        _ => None,
+    }
+}
+
+/// How many bytes of a binary `< path` body are dumped with `-vv`.
+const BODY_PREVIEW_SIZE: usize = 64;
+
+/// The spaces indenting each level of the JSON and XML bodies pretty-printed
+/// with `-v`.
+const PRETTY_INDENT: u16 = 2;
+
+/// The file a response body is written to, set by `>> path`, or by
+/// `>>! path` to overwrite it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    pub overwrite: bool,
+}
+
+impl OutputFile {
+    /// The path to write to: unless overwriting, an existing file is kept,
+    /// numbering the new one as `report-1.csv`, `report-2.csv`...
+    pub fn destination(&self) -> PathBuf {
+        if self.overwrite || !self.path.exists() {
+            return self.path.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = self
+            .path
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        (1..)
+            .map(|n| {
+                self.path
+                    .with_file_name(format!("{}-{}{}", stem, n, extension))
+            })
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+}
+
+/// Where a response body was written, instead of keeping it in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedBody {
+    pub path: PathBuf,
+    /// the bytes written
+    pub size: u64,
+    /// the bytes already in the file, when resuming a download
+    pub skipped: u64,
+    /// how long receiving the body took
+    pub elapsed: Duration,
+}
+
+/// A response whose body has been fully read, so that it can be both printed
+/// and referenced by later requests.
+///
+/// When saved to a file the body is empty, for the assertions and the
+/// references of the following requests too.
+#[derive(Clone, Debug)]
+pub struct OwnedResponse {
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: header::HeaderMap,
+    pub body: Vec<u8>,
+    pub saved: Option<SavedBody>,
+    /// what was received of a body printed as it arrived, kept in `body`
+    /// too
+    pub events: Option<stream::Events>,
+    /// whether the body was cut, longer than the limit it was read with
+    pub truncated: bool,
+}
+
+impl OwnedResponse {
+    /// The bytes of the body as received, not as printed, even when saved
+    /// to a file.
+    pub fn size(&self) -> u64 {
+        match &self.saved {
+            Some(saved) => saved.size,
+            None => self.body.len() as u64,
+        }
+    }
+
+    /// The bytes of the headers, as `name: value` lines ending with CRLF.
+    pub fn header_size(&self) -> u64 {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str().len() + value.len() + 4) as u64)
+            .sum()
+    }
+
+    pub fn from_response(response: transport::Response) -> Result<OwnedResponse> {
+        OwnedResponse::from_response_within(response, 0)
+    }
+
+    /// Like `from_response`, reading `max_size` bytes of the body at most,
+    /// unless zero: a longer one is `truncated` there, without reading the
+    /// rest.
+    pub fn from_response_within(
+        response: transport::Response,
+        max_size: u64,
+    ) -> Result<OwnedResponse> {
+        let version = response.version();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let mut body = Vec::new();
+        if max_size == 0 {
+            body = response.bytes()?;
+        } else {
+            response
+                .take(max_size + 1)
+                .read_to_end(&mut body)
+                .map_err(body_read_error)?;
+        }
+        let truncated = max_size > 0 && body.len() as u64 > max_size;
+        if truncated {
+            body.truncate(max_size as usize);
+        }
+        Ok(OwnedResponse {
+            version,
+            status,
+            headers,
+            body,
+            saved: None,
+            events: None,
+            truncated,
+        })
+    }
+
+    /// Like `from_response`, writing the body to `path` as it's received,
+    /// without ever holding all of it, and showing the progress if
+    /// `show_progress`.
+    ///
+    /// With `resume`, the bytes already in the file that were asked not to
+    /// be sent again with a `Range` header, a 206 response is appended to
+    /// them, while a 416 one leaves the file as it is, already complete.
+    pub fn save_response(
+        mut response: transport::Response,
+        path: &Path,
+        resume: u64,
+        show_progress: bool,
+    ) -> Result<OwnedResponse> {
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            // the file is left as it is
+            return OwnedResponse::from_response(response);
+        }
+        if resume > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(OwnedResponse {
+                version: response.version(),
+                status,
+                headers: response.headers().clone(),
+                body: Vec::new(),
+                saved: Some(SavedBody {
+                    path: path.to_path_buf(),
+                    size: 0,
+                    skipped: resume,
+                    elapsed: Duration::new(0, 0),
+                }),
+                events: None,
+                truncated: false,
+            });
+        }
+        // a 200 is the whole body, from servers ignoring the ranges
+        let skipped = match status {
+            StatusCode::PARTIAL_CONTENT if resume > 0 => {
+                check_content_range(response.headers(), resume)?;
+                resume
+            }
+            _ => 0,
+        };
+        let inner = match skipped {
+            0 => create_output(path)?,
+            _ => append_output(path, skipped)?,
+        };
+        let mut file = progress::ProgressWriter {
+            inner,
+            progress: progress::Progress::new(response.content_length(), show_progress),
+        };
+        let write_error = || format!("unable to write the response to {}", path.display());
+        let copied = io::copy(&mut response, &mut file)
+            .map_err(body_read_error)
+            .with_context(write_error)
+            .and_then(|size| file.flush().with_context(write_error).map(|_| size));
+        let elapsed = file.progress.finish();
+        let size = copied?;
+        Ok(OwnedResponse {
+            version: response.version(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: Vec::new(),
+            saved: Some(SavedBody {
+                path: path.to_path_buf(),
+                size,
+                skipped,
+                elapsed,
+            }),
+            events: None,
+            truncated: false,
+        })
+    }
+
+    /// Like `from_response`, writing the body to `out` as it arrives, event
+    /// by event for the event streams and else line by line, with the JSON
+    /// lines pretty-printed if `pretty`, until it ends or `interrupted`;
+    /// fails after waiting `idle_timeout` for the next one.
+    pub fn stream_response(
+        response: transport::Response,
+        pretty: bool,
+        max_size: u64,
+        idle_timeout: Option<Duration>,
+        interrupted: &AtomicBool,
+        out: &mut dyn Write,
+    ) -> Result<OwnedResponse> {
+        let version = response.version();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let format = stream::Format::of(&headers).unwrap_or_default();
+        let (body, events) = stream::read(
+            response,
+            format,
+            pretty,
+            max_size,
+            idle_timeout,
+            interrupted,
+            out,
+        )?;
+        Ok(OwnedResponse {
+            version,
+            status,
+            headers,
+            body,
+            saved: None,
+            truncated: events.truncated,
+            events: Some(events),
+        })
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn from_async_response(response: reqwest::Response) -> Result<OwnedResponse> {
+        OwnedResponse::from_async_response_within(response, 0).await
+    }
+
+    /// Like `from_response_within`, for the async client.
+    #[cfg(feature = "async")]
+    pub async fn from_async_response_within(
+        mut response: reqwest::Response,
+        max_size: u64,
+    ) -> Result<OwnedResponse> {
+        let version = response.version();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if max_size > 0 && body.len() as u64 > max_size {
+                body.truncate(max_size as usize);
+                truncated = true;
+                break;
+            }
+        }
+        Ok(OwnedResponse {
+            version,
+            status,
+            headers,
+            body,
+            saved: None,
+            events: None,
+            truncated,
+        })
+    }
+
+    /// Like `save_response`, for the async client.
+    #[cfg(feature = "async")]
+    pub async fn save_async_response(
+        mut response: reqwest::Response,
+        path: &Path,
+        show_progress: bool,
+    ) -> Result<OwnedResponse> {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            // the file is left as it is
+            return OwnedResponse::from_async_response(response).await;
+        }
+        let mut file = progress::ProgressWriter {
+            inner: create_output(path)?,
+            progress: progress::Progress::new(response.content_length(), show_progress),
+        };
+        let mut size = 0;
+        let write_error = || format!("unable to write the response to {}", path.display());
+        let copied: Result<()> = async {
+            while let Some(chunk) = response.chunk().await.with_context(write_error)? {
+                file.write_all(&chunk).with_context(write_error)?;
+                size += chunk.len() as u64;
+            }
+            file.flush().with_context(write_error)?;
+            Ok(())
+        }
+        .await;
+        let elapsed = file.progress.finish();
+        copied?;
+        Ok(OwnedResponse {
+            version: response.version(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: Vec::new(),
+            saved: Some(SavedBody {
+                path: path.to_path_buf(),
+                size,
+                skipped: 0,
+                elapsed,
+            }),
+            events: None,
+            truncated: false,
+        })
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// Tells whether the body is binary, from the `Content-Type` and the
+    /// `Content-Encoding` of the bodies not decompressed, or else from a NUL
+    /// byte in its first 8 KiB.
+    pub fn is_binary(&self) -> bool {
+        if self.content_encoding().is_some() {
+            return true;
+        }
+        let content_type = self
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| parse_content_type(ct).ok())
+            .map(|ct| ct.trim().to_ascii_lowercase());
+        match content_type.as_deref() {
+            Some(ct) if is_binary_content_type(ct) => true,
+            _ => self.body.iter().take(8192).any(|b| *b == 0),
+        }
+    }
+
+    /// What's printed instead of a binary body, as
+    /// `[binary body: 48213 bytes, image/png]`, or
+    /// `[binary body: 812 bytes, application/json, gzip]` when encoded.
+    pub fn binary_placeholder(&self) -> String {
+        let mut placeholder = format!("[binary body: {} bytes", self.body.len());
+        if let Some(ct) = self.headers.get(header::CONTENT_TYPE) {
+            placeholder.push_str(&format!(", {}", ct.to_str().unwrap_or("?")));
+        }
+        if let Some(encoding) = self.content_encoding() {
+            placeholder.push_str(&format!(", {}", encoding));
+        }
+        placeholder.push(']');
+        placeholder
+    }
+
+    /// The `Content-Encoding` of a body not decompressed, like `gzip`.
+    fn content_encoding(&self) -> Option<&str> {
+        self.headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|encoding| encoding.to_str().ok())
+            .map(|encoding| encoding.trim())
+            .filter(|encoding| !encoding.is_empty() && !encoding.eq_ignore_ascii_case("identity"))
+    }
+}
+
+/// The content types of the images, audio, video, fonts, archives and other
+/// formats that aren't text.
+fn is_binary_content_type(content_type: &str) -> bool {
+    let (family, subtype) = content_type.split_once('/').unwrap_or((content_type, ""));
+    match family {
+        "image" => subtype != "svg+xml",
+        "audio" | "video" | "font" => true,
+        "application" => matches!(
+            subtype,
+            "octet-stream"
+                | "pdf"
+                | "zip"
+                | "gzip"
+                | "x-gzip"
+                | "x-tar"
+                | "x-7z-compressed"
+                | "wasm"
+                | "protobuf"
+                | "x-protobuf"
+                | "vnd.google.protobuf"
+                | "grpc"
+                | "msgpack"
+                | "x-msgpack"
+                | "cbor"
+        ),
+        _ => false,
+    }
+}
+
+/// Creates, or truncates, the file to write a response body to.
+fn create_output(path: &Path) -> Result<BufWriter<File>> {
+    let file = File::create(path)
+        .with_context(|| format!("unable to create the output file {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// The file to append to after its first `length` bytes, dropping the ones
+/// after them, written by a failed attempt.
+fn append_output(path: &Path, length: u64) -> Result<BufWriter<File>> {
+    let open_error = || format!("unable to append to the output file {}", path.display());
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(open_error)?;
+    file.set_len(length).with_context(open_error)?;
+    file.seek(io::SeekFrom::End(0)).with_context(open_error)?;
+    Ok(BufWriter::new(file))
+}
+
+/// Checks that a 206 response starts where the download was resumed, as
+/// `Content-Range: bytes 1000-1999/2000` does for `resume` 1000.
+fn check_content_range(headers: &header::HeaderMap, resume: u64) -> Result<()> {
+    let content_range = headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let start = content_range
+        .strip_prefix("bytes ")
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.trim().parse::<u64>().ok());
+    match start {
+        Some(start) if start == resume => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "unexpected Content-Range \"{}\" resuming the download at byte {}",
+            content_range,
+            resume
+        )),
+    }
+}
+
+fn print_response_headers(h: &header::HeaderMap, color: bool) -> String {
     let mut header_buffer = "".to_string();
     for k in h {
-        header_buffer.push_str(&format!("{}: {:?}\n", k.0, k.1));
+        header_buffer.push_str(&format!(
+            "{}: {:?}\n",
+            color::header_name(k.0.as_str(), color),
+            k.1
+        ));
     }
     header_buffer
 }
 
-pub fn verbose_print_response(response: Response, elapsed: &Duration) -> Result<String> {
-    let status = response.status();
-    let headers = response.headers();
-    Ok(format!(
-        "{} - {:?}\n{}\n{}",
-        status,
+/// Status, time and headers of a response, printed with `-v` before the
+/// body, telling whether the client decompressed it, dropping its
+/// `Content-Encoding` and `Content-Length`; with `color`, the status is
+/// colored by its class and the header names dimmed.
+pub fn verbose_print_head(
+    response: &OwnedResponse,
+    elapsed: &Duration,
+    decompress: bool,
+    color: bool,
+) -> String {
+    let size = match response.events {
+        // printed before the body
+        Some(_) => "streamed".to_string(),
+        None => format!(
+            "size: {}, rate: {}",
+            progress::format_size(response.size()),
+            progress::format_rate(response.size(), *elapsed)
+        ),
+    };
+    format!(
+        "{} - {:?} - {}{}\n{}\n",
+        color::status(
+            &format!("{:?} {}", response.version, response.status),
+            response.status,
+            color
+        ),
         elapsed,
-        print_response_headers(headers),
-        match headers.get(reqwest::header::CONTENT_TYPE) {
-            None => response.text()?,
-            Some(ct) => {
+        size,
+        match decompress {
+            true if cfg!(feature = "decompress") => " - decompression on",
+            true => "",
+            false => " - decompression off",
+        },
+        print_response_headers(&response.headers, color),
+    )
+}
+
+/// Status, headers and body of a response: the JSON and XML bodies are
+/// pretty-printed, unless longer than `max_pretty_size` bytes, while the
+/// binary ones and the saved ones are described instead; nothing follows the
+/// headers of an empty body, as of the responses to `HEAD` or the 204 ones.
+/// With `color`, the keys of the JSON bodies are highlighted too.
+pub fn verbose_print_response(
+    response: &OwnedResponse,
+    elapsed: &Duration,
+    max_pretty_size: usize,
+    decompress: bool,
+    color: bool,
+) -> Result<String> {
+    Ok(format!(
+        "{}{}",
+        verbose_print_head(response, elapsed, decompress, color),
+        match (
+            &response.saved,
+            response.headers.get(reqwest::header::CONTENT_TYPE)
+        ) {
+            (Some(saved), _) if saved.skipped > 0 => format!(
+                "saved {} bytes to {} ({}, {}), skipping the {} bytes already there",
+                saved.size,
+                saved.path.display(),
+                progress::format_size(saved.size),
+                progress::format_rate(saved.size, saved.elapsed),
+                saved.skipped
+            ),
+            (Some(saved), _) => format!(
+                "saved {} bytes to {} ({}, {})",
+                saved.size,
+                saved.path.display(),
+                progress::format_size(saved.size),
+                progress::format_rate(saved.size, saved.elapsed)
+            ),
+            (None, _) if response.body.is_empty() => String::new(),
+            (None, _) if response.is_binary() => response.binary_placeholder(),
+            (None, Some(ct)) if response.body.len() <= max_pretty_size => {
                 match parse_content_type(&ct)? {
-                    "application/json" => beautify_json(response.text()?)?,
-                    _ => response.text()?,
+                    "application/json" => beautify_json(response.text(), color)?,
+                    content_type if is_xml(content_type) => beautify_xml(response.text()),
+                    _ => response.text(),
                 }
             }
+            (None, _) => response.text(),
         }
     ))
 }
 
+/// Formats bytes as `xxd` does, 16 per line, with their offset and the
+/// printable ones on the right.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, b) in chunk.iter().enumerate() {
+            hex.push_str(&format!("{:02x} ", b));
+            if i == 7 {
+                hex.push(' ');
+            }
+        }
+        let printable: String = chunk
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+        dump.push_str(&format!("{:08x}  {:<49} |{}|\n", line * 16, hex, printable));
+    }
+    dump
+}
+
+/// The error reading a body, reqwest's own when it is one, as when reading
+/// it whole.
+pub(crate) fn body_read_error(error: io::Error) -> anyhow::Error {
+    if !matches!(error.get_ref(), Some(inner) if inner.is::<reqwest::Error>()) {
+        return error.into();
+    }
+    match error
+        .into_inner()
+        .map(|inner| inner.downcast::<reqwest::Error>())
+    {
+        Some(Ok(error)) => (*error).into(),
+        _ => anyhow::anyhow!("unable to read the body"),
+    }
+}
+
 fn parse_content_type(ct: &reqwest::header::HeaderValue) -> Result<&str> {
     let type_splitted: Vec<&str> = ct.to_str()?.split(";").collect();
     Ok(type_splitted[0])
 }
 
-fn beautify_json(json_text: String) -> Result<String> {
+fn beautify_json(json_text: String, color: bool) -> Result<String> {
     let parsed = json::parse(&json_text)?;
-    Ok(json::stringify_pretty(parsed, 2))
+    Ok(color::json_keys(
+        &json::stringify_pretty(parsed, PRETTY_INDENT),
+        color,
+    ))
+}
+
+/// Whether a content type is XML, as `application/xml`, `text/xml` or
+/// `application/soap+xml`.
+fn is_xml(content_type: &str) -> bool {
+    matches!(content_type, "application/xml" | "text/xml") || content_type.ends_with("+xml")
+}
+
+/// The XML body pretty-printed, or as it is when it's malformed.
+fn beautify_xml(xml_text: String) -> String {
+    xml::pretty(&xml_text, usize::from(PRETTY_INDENT)).unwrap_or(xml_text)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server;
+    use std::io::{BufRead, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+
+    /// A server answering `done` after `delay`.
+    fn slow_server(delay: Duration) -> SocketAddr {
+        // not delaying the next requests
+        test_server::serve_concurrently(move |_| {
+            std::thread::sleep(delay);
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\ndone"
+        })
+    }
+
+    #[test]
+    fn no_timeout() {
+        let address = slow_server(Duration::from_secs(2));
+        let mut req = Request::new();
+        req.method = "GET".to_string();
+        req.url = format!("http://{}/export", address);
+        assert!(req.execute(Duration::from_secs(1)).is_err());
+        assert_eq!(
+            req.execute(Duration::from_secs(0)).unwrap().text().unwrap(),
+            "done"
+        );
+        // `# @timeout 0`
+        req.timeout = Some(Duration::from_secs(0));
+        assert_eq!(
+            req.execute(Duration::from_secs(1)).unwrap().text().unwrap(),
+            "done"
+        );
+        req.timeout = Some(Duration::from_millis(500));
+        assert!(req.execute(Duration::from_secs(0)).is_err());
+    }
+
+    /// A server answering with `size` bytes, written a chunk at a time.
+    fn large_server(size: usize) -> SocketAddr {
+        test_server::serve_with(move |stream, _| {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                size
+            )
+            .unwrap();
+            let chunk = [b'x'; 64 * 1024];
+            let mut written = 0;
+            while written < size {
+                let length = chunk.len().min(size - written);
+                stream.write_all(&chunk[..length]).unwrap();
+                written += length;
+            }
+        })
+    }
+
+    #[test]
+    fn content_range() {
+        let mut headers = header::HeaderMap::new();
+        assert!(check_content_range(&headers, 1000).is_err());
+        headers.insert(
+            header::CONTENT_RANGE,
+            "bytes 1000-1999/2000".parse().unwrap(),
+        );
+        assert!(check_content_range(&headers, 1000).is_ok());
+        assert_eq!(
+            check_content_range(&headers, 10).unwrap_err().to_string(),
+            "unexpected Content-Range \"bytes 1000-1999/2000\" resuming the download at byte 10"
+        );
+        let saved = OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::PARTIAL_CONTENT,
+            headers,
+            body: Vec::new(),
+            saved: Some(SavedBody {
+                path: PathBuf::from("big.bin"),
+                size: 1000,
+                skipped: 1000,
+                elapsed: Duration::from_secs(1),
+            }),
+            events: None,
+            truncated: false,
+        };
+        let printed =
+            verbose_print_response(&saved, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with(
+            "saved 1000 bytes to big.bin (1000 B, 1000 B/s), skipping the 1000 bytes already there"
+        ));
+    }
+
+    #[test]
+    fn saved_body() {
+        let size = 100 * 1024 * 1024;
+        let address = large_server(size);
+        let path =
+            std::env::temp_dir().join(format!("httpclient-saved-{}.bin", std::process::id()));
+        let mut req = Request::new();
+        req.method = "GET".to_string();
+        req.url = format!("http://{}/download", address);
+        let response = OwnedResponse::save_response(
+            req.execute(Duration::from_secs(60)).unwrap().into(),
+            &path,
+            0,
+            false,
+        )
+        .unwrap();
+        // never held in memory
+        assert!(response.body.is_empty());
+        let saved = response.saved.as_ref().unwrap();
+        assert_eq!((&saved.path, saved.size), (&path, size as u64));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), size as u64);
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.contains(&format!(
+            "\nsaved {} bytes to {} (100.0 MiB, ",
+            size,
+            path.display()
+        )));
+
+        // `>> path` keeps the existing file
+        let output = OutputFile {
+            path: path.clone(),
+            overwrite: false,
+        };
+        let numbered =
+            path.with_file_name(format!("httpclient-saved-{}-1.bin", std::process::id()));
+        assert_eq!(output.destination(), numbered);
+        std::fs::write(&numbered, "").unwrap();
+        assert_eq!(
+            output.destination(),
+            path.with_file_name(format!("httpclient-saved-{}-2.bin", std::process::id()))
+        );
+        let output = OutputFile {
+            overwrite: true,
+            ..output
+        };
+        assert_eq!(output.destination(), path);
+        std::fs::remove_file(&numbered).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn large_json() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        let response = OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            headers,
+            body: br#"{"a":1}"#.to_vec(),
+            saved: None,
+            events: None,
+            truncated: false,
+        };
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with("\n{\n  \"a\": 1\n}"));
+        // past the limit, as received
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 4, true, false).unwrap();
+        assert!(printed.ends_with("\n{\"a\":1}"));
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 4, false, false).unwrap();
+        // the size received, not the one beautified
+        assert!(printed
+            .starts_with("HTTP/1.1 200 OK - 1s - size: 7 B, rate: 7 B/s - decompression off\n"));
+        assert_eq!((response.size(), response.header_size()), (7, 32));
+    }
+
+    #[test]
+    fn empty_json() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        let response = OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::NO_CONTENT,
+            headers,
+            body: Vec::new(),
+            saved: None,
+            events: None,
+            truncated: false,
+        };
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.starts_with("HTTP/1.1 204 No Content - 1s - size: 0 B"));
+        assert!(printed.ends_with("content-type: \"application/json\"\n\n"));
+    }
+
+    #[test]
+    fn xml_bodies() {
+        let response = |content_type: &'static str, body: &[u8]| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            );
+            OwnedResponse {
+                version: Version::HTTP_11,
+                status: StatusCode::OK,
+                headers,
+                body: body.to_vec(),
+                saved: None,
+                events: None,
+                truncated: false,
+            }
+        };
+        let soap = response(
+            "application/soap+xml; charset=utf-8",
+            b"<Envelope><Body a=\"1\"><Value><![CDATA[<x>]]></Value></Body></Envelope>",
+        );
+        let printed =
+            verbose_print_response(&soap, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with(
+            "\n<Envelope>\n  <Body a=\"1\">\n    <Value><![CDATA[<x>]]></Value>\n  </Body>\n</Envelope>"
+        ));
+        // malformed, as received
+        let malformed = response("text/xml", b"<a><b></a>");
+        let printed =
+            verbose_print_response(&malformed, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with("\n<a><b></a>"));
+        let html = response("text/html", b"<p><b>bold</b></p>");
+        let printed =
+            verbose_print_response(&html, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with("\n<p><b>bold</b></p>"));
+    }
+
+    #[test]
+    fn colored_json() {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        let response = OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::NOT_FOUND,
+            headers,
+            body: br#"{"error":"not found"}"#.to_vec(),
+            saved: None,
+            events: None,
+            truncated: false,
+        };
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 1024, false, false).unwrap();
+        assert_eq!(
+            printed,
+            "HTTP/1.1 404 Not Found - 1s - size: 21 B, rate: 21 B/s - decompression off\n\
+             content-type: \"application/json\"\n\
+             \n\
+             {\n  \"error\": \"not found\"\n}"
+        );
+        let printed =
+            verbose_print_response(&response, &Duration::from_secs(1), 1024, false, true).unwrap();
+        assert_eq!(
+            printed,
+            "\x1b[33mHTTP/1.1 404 Not Found\x1b[0m - 1s - size: 21 B, rate: 21 B/s - decompression off\n\
+             \x1b[2mcontent-type\x1b[0m: \"application/json\"\n\
+             \n\
+             {\n  \x1b[34m\"error\"\x1b[0m: \"not found\"\n}"
+        );
+    }
+
+    #[test]
+    fn binary_bodies() {
+        let response = |content_type: Option<&'static str>, body: &[u8]| {
+            let mut headers = header::HeaderMap::new();
+            if let Some(content_type) = content_type {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    header::HeaderValue::from_static(content_type),
+                );
+            }
+            OwnedResponse {
+                version: Version::HTTP_11,
+                status: StatusCode::OK,
+                headers,
+                body: body.to_vec(),
+                saved: None,
+                events: None,
+                truncated: false,
+            }
+        };
+        let png = response(Some("image/png"), b"\x89PNG\r\n");
+        assert!(png.is_binary());
+        assert_eq!(
+            png.binary_placeholder(),
+            "[binary body: 6 bytes, image/png]"
+        );
+        assert!(response(Some("application/x-protobuf; proto=a.B"), b"\x08\x01").is_binary());
+        assert!(response(None, b"ab\x00cd").is_binary());
+        assert_eq!(
+            response(None, b"ab\x00cd").binary_placeholder(),
+            "[binary body: 5 bytes]"
+        );
+        assert!(!response(Some("image/svg+xml"), b"<svg/>").is_binary());
+        assert!(!response(Some("application/json"), b"{}").is_binary());
+        assert!(!response(None, "caffè".as_bytes()).is_binary());
+        let printed =
+            verbose_print_response(&png, &Duration::from_secs(1), 1024, true, false).unwrap();
+        assert!(printed.ends_with("\n[binary body: 6 bytes, image/png]"));
+    }
+
+    #[test]
+    fn hex_dumps() {
+        assert_eq!(
+            hex_dump(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0"),
+            "00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+             00000010  00 00                                             |..|\n"
+        );
+        assert_eq!(hex_dump(b""), "");
+    }
+
+    #[test]
+    fn body_stdin() {
+        use sha2::{Digest, Sha256};
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // echoes the chunked body back, with its length
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream);
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                reader.read_line(&mut head).unwrap();
+            }
+            let mut body = Vec::new();
+            loop {
+                let mut size = String::new();
+                reader.read_line(&mut size).unwrap();
+                let size = usize::from_str_radix(size.trim(), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).unwrap();
+                if size == 0 {
+                    break;
+                }
+                body.extend_from_slice(&chunk[..size]);
+            }
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+            head
+        });
+        let data: Vec<u8> = (0..4 << 20).map(|i: u32| (i * 31 % 251) as u8).collect();
+        let mut req = Request::new();
+        req.method = "POST".to_string();
+        req.url = format!("http://{}/upload", address);
+        req.body_stdin = true;
+        assert!(req.to_string().ends_with("body:\n< - (standard input)"));
+
+        let client = req.client(&client::ClientOptions::default()).unwrap();
+        let response = req
+            .send_with(
+                &client,
+                Duration::from_secs(10),
+                Some((std::io::Cursor::new(data.clone()), None)),
+            )
+            .unwrap();
+        let echoed = response.bytes().unwrap();
+        assert_eq!(Sha256::digest(&echoed), Sha256::digest(&data));
+        let head = server.join().unwrap().to_lowercase();
+        assert!(head.contains("transfer-encoding: chunked\r\n"));
+        assert!(!head.contains("content-length"));
+    }
+
+    #[test]
+    fn body_file() {
+        let (address, server) =
+            test_server::serve_once(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let bytes = b"\x89PNG\r\n\x1a\n\x00\xc3\x28\xff\xfe";
+        let path = std::env::temp_dir().join(format!("httpclient-body-{}.png", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let mut req = Request::new();
+        req.method = "PUT".to_string();
+        req.url = format!("http://{}/avatar", address);
+        req.body_file = Some(path.clone());
+
+        assert!(req.to_string().ends_with(&format!(
+            "body:\n< {} (13 bytes)\n\
+             00000000  89 50 4e 47 0d 0a 1a 0a  00 c3 28 ff fe           |.PNG......(..|\n",
+            path.display()
+        )));
+        req.execute(Duration::from_secs(10)).unwrap();
+        let received = server.join().unwrap();
+        // sent as it is, not as UTF-8
+        assert!(received.ends_with(&[&b"\r\n\r\n"[..], &bytes[..]].concat()));
+        assert!(String::from_utf8_lossy(&received).contains("content-length: 13\r\n"));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(req
+            .execute(Duration::from_secs(10))
+            .unwrap_err()
+            .to_string()
+            .starts_with("unable to read the body file "));
+        assert!(req.to_string().starts_with("PUT "));
+    }
+
+    #[test]
+    fn binary_body() {
+        let (address, server) =
+            test_server::serve_once(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let bytes = b"\x89PNG\r\n\x1a\n\x00\xc3\x28\xff\xfe";
+        let mut req = Request::new();
+        req.method = "PUT".to_string();
+        req.url = format!("http://{}/avatar", address);
+        req.body = bytes.to_vec();
+
+        assert!(req.to_string().ends_with(
+            "body:\n(13 bytes)\n\
+             00000000  89 50 4e 47 0d 0a 1a 0a  00 c3 28 ff fe           |.PNG......(..|\n"
+        ));
+        req.execute(Duration::from_secs(10)).unwrap();
+        let received = server.join().unwrap();
+        assert!(received.ends_with(&[&b"\r\n\r\n"[..], &bytes[..]].concat()));
+    }
 
     #[test]
     fn format_headers() {
@@ -154,11 +1842,7 @@ mod tests {
         );
         let request = Request {
             headers: headers,
-            url: "".to_string(),
-            url_parameters: Vec::new(),
-            method: "".to_string(),
-            protocol: "HTTP/1.1".to_string(),
-            body: "".to_string(),
+            ..Request::new()
         };
         let formatted_headers = request.format_headers().unwrap();
         assert_eq!(
@@ -166,4 +1850,278 @@ mod tests {
             reqwest::header::HeaderValue::from_static("Bearer mysupresecrettoken")
         );
     }
+
+    #[test]
+    fn request_errors() {
+        let mut req = Request::new();
+        req.method = "POST".to_string();
+        req.url = "https://example.com/items".to_string();
+        req.headers.insert("X Bad".to_string(), "value".to_string());
+        assert_eq!(
+            req.format_headers().unwrap_err().to_string(),
+            "POST https://example.com/items: invalid header name \"X Bad\""
+        );
+        req.headers.clear();
+        req.headers
+            .insert("X-Token".to_string(), "a\nb".to_string());
+        assert!(matches!(
+            req.format_headers(),
+            Err(Error::InvalidHeaderValue { name, .. }) if name == "X-Token"
+        ));
+        req.url = "https://exa mple.com".to_string();
+        let error = req.target_url().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::InvalidUrl { method, url, .. })
+                if method == "POST" && url == "https://exa mple.com"
+        ));
+        assert!(error
+            .to_string()
+            .starts_with("POST https://exa mple.com: invalid URL ("));
+    }
+
+    #[test]
+    fn origin_form_url() {
+        let mut request = Request {
+            url: "/api/users".to_string(),
+            url_parameters: vec![("page".to_string(), Some("2".to_string()))],
+            ..Request::new()
+        };
+        request
+            .headers
+            .insert("host".to_string(), "api.example.com".to_string());
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://api.example.com/api/users?page=2"
+        );
+        request.scheme = "http".to_string();
+        request.url = "https://example.com/".to_string();
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/?page=2"
+        );
+    }
+
+    #[test]
+    fn valueless_url_parameters() {
+        let request = Request {
+            url: "https://example.com/debug".to_string(),
+            url_parameters: vec![
+                ("trace".to_string(), None),
+                ("flag".to_string(), Some("".to_string())),
+            ],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/debug?trace&flag="
+        );
+    }
+
+    #[test]
+    fn repeated_url_parameters() {
+        let request = Request {
+            url: "https://example.com/items?id=1".to_string(),
+            url_parameters: vec![
+                ("id".to_string(), Some("2".to_string())),
+                ("id".to_string(), Some("3".to_string())),
+            ],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/items?id=1&id=2&id=3"
+        );
+        assert_eq!(request.print_url_parameters(), "   id: 2\n   id: 3\n");
+    }
+
+    #[test]
+    fn url_fragment() {
+        let request = Request {
+            url: "https://example.com/page".to_string(),
+            fragment: Some("section".to_string()),
+            url_parameters: vec![("lang".to_string(), Some("en".to_string()))],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/page?lang=en#section"
+        );
+    }
+
+    #[test]
+    fn ipv6_host() {
+        let request = Request {
+            url: "http://[::1]:8080/status".to_string(),
+            url_parameters: vec![("x".to_string(), Some("y".to_string()))],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "http://[::1]:8080/status?x=y"
+        );
+        let request = Request {
+            url: "/status".to_string(),
+            scheme: "http".to_string(),
+            headers: [("Host".to_string(), "[::1]:8080".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "http://[::1]:8080/status"
+        );
+    }
+
+    #[test]
+    fn unicode_path() {
+        let request = Request {
+            url: "https://xn--bcher-kva.example/straße".to_string(),
+            url_parameters: vec![("q".to_string(), Some("grüße".to_string()))],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://xn--bcher-kva.example/stra%C3%9Fe?q=gr%C3%BC%C3%9Fe"
+        );
+    }
+
+    #[test]
+    fn merged_url_parameters() {
+        let mut request = Request {
+            url: "https://example.com/x?a=1&b=2&a=3&".to_string(),
+            fragment: Some("top".to_string()),
+            url_parameters: vec![
+                ("a".to_string(), Some("override".to_string())),
+                ("c".to_string(), None),
+            ],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/x?a=1&b=2&a=3&a=override&c#top"
+        );
+        request.replace_url_parameters = true;
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/x?b=2&a=override&c#top"
+        );
+
+        let mut request = Request {
+            url: "https://example.com/x?".to_string(),
+            url_parameters: vec![("b".to_string(), Some("2".to_string()))],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/x?b=2"
+        );
+        request.url_parameters.clear();
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/x"
+        );
+    }
+
+    #[test]
+    fn encoded_url_parameters() {
+        let mut request = Request {
+            url: "https://example.com/search?q=hello world&city=Zürich".to_string(),
+            url_parameters: vec![
+                ("filter".to_string(), Some("a+b&c".to_string())),
+                ("name".to_string(), Some("José Pérez".to_string())),
+                ("encoded".to_string(), Some("x%20y%2".to_string())),
+            ],
+            ..Request::new()
+        };
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/search?q=hello%20world&city=Z%C3%BCrich\
+             &filter=a%2Bb%26c&name=Jos%C3%A9%20P%C3%A9rez&encoded=x%20y%252"
+        );
+        request.encode_url_parameters = false;
+        assert_eq!(
+            request.get_url_with_parameters().unwrap(),
+            "https://example.com/search?q=hello%20world&city=Z%C3%BCrich\
+             &filter=a+b&c&name=Jos%C3%A9%20P%C3%A9rez&encoded=x%20y%2"
+        );
+    }
+
+    #[test]
+    fn format_basic_auth() {
+        let request = Request {
+            basic_auth: Some(("user".to_string(), "pass".to_string())),
+            ..Request::new()
+        };
+        let formatted_headers = request.format_headers().unwrap();
+        assert_eq!(
+            formatted_headers.get("Authorization").unwrap(),
+            reqwest::header::HeaderValue::from_static("Basic dXNlcjpwYXNz")
+        );
+    }
+
+    #[test]
+    fn format_cookies() {
+        let request = Request {
+            cookies: vec![
+                ("session".to_string(), "abc".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+            ],
+            ..Request::new()
+        };
+        let formatted_headers = request.format_headers().unwrap();
+        assert_eq!(
+            formatted_headers.get("Cookie").unwrap(),
+            reqwest::header::HeaderValue::from_static("session=abc; theme=dark")
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async")]
+    async fn async_execution() {
+        let (address, server) = test_server::serve_once(
+            b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\nX-Id: 7\r\n\r\nok",
+        );
+
+        let request = Request {
+            method: "POST".to_string(),
+            url: format!("http://{}/items", address),
+            url_parameters: vec![("q".to_string(), Some("a b".to_string()))],
+            headers: [("X-Test".to_string(), "1".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+            body: b"{}".to_vec(),
+            ..Request::new()
+        };
+        let response = request
+            .execute_async(&reqwest::Client::new(), Duration::from_secs(10))
+            .await
+            .unwrap();
+        let response = OwnedResponse::from_async_response(response).await.unwrap();
+        assert_eq!(response.status, StatusCode::CREATED);
+        assert_eq!(response.headers["x-id"], "7");
+        assert_eq!(response.text(), "ok");
+        let received = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(received.starts_with("POST /items?q=a%20b HTTP/1.1\r\n"));
+        assert!(received.contains("x-test: 1\r\n"));
+        assert!(received.ends_with("\r\n\r\n{}"));
+    }
+
+    #[test]
+    fn redacted_secrets() {
+        let mut request = Request::new();
+        request.method = "GET".to_string();
+        request.url = "https://example.com".to_string();
+        request
+            .headers
+            .insert("Authorization".to_string(), "Bearer s3cr3t".to_string());
+        request.secrets = vec!["s3cr3t".to_string()];
+        assert!(request.to_string().contains("Bearer s3cr3t"));
+        let redacted = request.redacted();
+        assert!(redacted.contains("Bearer ***"));
+        assert!(!redacted.contains("s3cr3t"));
+    }
 }
@@ -0,0 +1,259 @@
+use crate::request::OwnedResponse;
+use crate::{cookies, error};
+use anyhow::Result;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::fmt;
+use std::time::Duration;
+
+/// The longest wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// When, and how many times, a failed request is sent again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// how many times a request is sent again, 0 to never retry
+    pub retries: u32,
+    /// whether the 5xx responses are retried too
+    pub server_errors: bool,
+    /// whether the methods that aren't idempotent, like `POST`, are retried
+    /// for any reason, not only when the connection couldn't be established
+    pub all_methods: bool,
+    /// the wait before the first retry, doubled at each following one
+    pub delay: Duration,
+    /// whether the 429 and 503 responses are retried, after the wait of
+    /// their `Retry-After` if they have one
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            retries: 0,
+            server_errors: false,
+            all_methods: false,
+            delay: Duration::from_millis(500),
+            respect_retry_after: false,
+        }
+    }
+}
+
+/// Why an attempt failed in a way that can be retried.
+#[derive(Debug, PartialEq)]
+pub enum RetryReason {
+    /// the connection couldn't be established, so nothing was sent
+    Connection,
+    Timeout,
+    ServerError(u16),
+    /// a 429 or 503 response, asking to wait `delay` if known
+    RetryAfter {
+        status: u16,
+        delay: Option<Duration>,
+    },
+}
+
+impl fmt::Display for RetryReason {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RetryReason::Connection => write!(dest, "connection error"),
+            RetryReason::Timeout => write!(dest, "timeout"),
+            RetryReason::ServerError(status) => write!(dest, "status {}", status),
+            RetryReason::RetryAfter {
+                status,
+                delay: Some(_),
+            } => write!(dest, "status {} with Retry-After", status),
+            RetryReason::RetryAfter {
+                status,
+                delay: None,
+            } => write!(dest, "status {}", status),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Tells whether the result of an attempt should be retried, and why.
+    pub fn reason(&self, method: &str, result: &Result<OwnedResponse>) -> Option<RetryReason> {
+        let reason = match result {
+            Ok(response)
+                if self.respect_retry_after && matches!(response.status.as_u16(), 429 | 503) =>
+            {
+                RetryReason::RetryAfter {
+                    status: response.status.as_u16(),
+                    delay: retry_after(&response.headers, cookies::now()),
+                }
+            }
+            Ok(response) if self.server_errors && response.status.is_server_error() => {
+                RetryReason::ServerError(response.status.as_u16())
+            }
+            Ok(_) => return None,
+            Err(e) if error::is_connect(e) => RetryReason::Connection,
+            Err(e) if error::is_timeout(e) => RetryReason::Timeout,
+            Err(_) => return None,
+        };
+        // the request could have been processed: sending it again is safe
+        // only if it has the same effect
+        let idempotent = ["GET", "HEAD", "PUT", "DELETE", "OPTIONS", "TRACE"]
+            .contains(&method.to_ascii_uppercase().as_str());
+        // a 429 response tells the request wasn't processed
+        let rejected = matches!(reason, RetryReason::RetryAfter { status: 429, .. });
+        if reason == RetryReason::Connection || rejected || idempotent || self.all_methods {
+            Some(reason)
+        } else {
+            None
+        }
+    }
+
+    /// The wait before sending the request again, after the `attempt`th one
+    /// failed: exponential, plus up to 50% of random jitter.
+    pub fn delay(&self, attempt: u32, random: u64) -> Duration {
+        let delay = self
+            .delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .unwrap_or(MAX_DELAY)
+            .min(MAX_DELAY);
+        let jitter = match delay.as_millis() as u64 / 2 {
+            0 => 0,
+            max_jitter => random % max_jitter,
+        };
+        delay + Duration::from_millis(jitter)
+    }
+
+    /// The wait before sending the request again, after the `attempt`th one
+    /// failed for `reason`: the one of `Retry-After`, if any, else `delay`.
+    pub fn wait(&self, reason: &RetryReason, attempt: u32, random: u64) -> Duration {
+        match reason {
+            RetryReason::RetryAfter {
+                delay: Some(delay), ..
+            } => *delay,
+            _ => self.delay(attempt, random),
+        }
+    }
+}
+
+/// The wait asked by the `Retry-After` header, in seconds or until a date,
+/// `now` being seconds since the epoch.
+fn retry_after(headers: &HeaderMap, now: u64) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let seconds = match value.parse::<u64>() {
+        Ok(seconds) => seconds,
+        Err(_) => cookies::parse_date(value)?.saturating_sub(now),
+    };
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use reqwest::{StatusCode, Version};
+
+    fn response(status: u16) -> Result<OwnedResponse> {
+        Ok(OwnedResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            saved: None,
+            events: None,
+            truncated: false,
+        })
+    }
+
+    fn connection_error() -> Result<OwnedResponse> {
+        // nothing listens on the discard port
+        let error = reqwest::blocking::get("http://127.0.0.1:9/").unwrap_err();
+        Err(error.into())
+    }
+
+    #[test]
+    fn retry_reasons() {
+        let policy = RetryPolicy {
+            retries: 3,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.reason("GET", &response(200)), None);
+        assert_eq!(policy.reason("GET", &response(503)), None);
+        assert_eq!(
+            policy.reason("POST", &connection_error()),
+            Some(RetryReason::Connection)
+        );
+        assert_eq!(
+            policy.reason("GET", &Err(anyhow::anyhow!("invalid header"))),
+            None
+        );
+
+        let policy = RetryPolicy {
+            server_errors: true,
+            ..policy
+        };
+        assert_eq!(
+            policy.reason("GET", &response(503)),
+            Some(RetryReason::ServerError(503))
+        );
+        assert_eq!(policy.reason("POST", &response(503)), None);
+        assert_eq!(policy.reason("GET", &response(404)), None);
+
+        let policy = RetryPolicy {
+            all_methods: true,
+            ..policy
+        };
+        assert_eq!(
+            policy.reason("POST", &response(500)),
+            Some(RetryReason::ServerError(500))
+        );
+    }
+
+    #[test]
+    fn retry_after_responses() {
+        let policy = RetryPolicy {
+            retries: 3,
+            respect_retry_after: true,
+            ..RetryPolicy::default()
+        };
+        let mut limited = response(429).unwrap();
+        limited
+            .headers
+            .insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        let limited = Ok(limited);
+        let reason = policy.reason("POST", &limited).unwrap();
+        assert_eq!(
+            reason,
+            RetryReason::RetryAfter {
+                status: 429,
+                delay: Some(Duration::from_secs(7))
+            }
+        );
+        assert_eq!(reason.to_string(), "status 429 with Retry-After");
+        assert_eq!(policy.wait(&reason, 1, 0), Duration::from_secs(7));
+        // else the usual delay
+        let reason = policy.reason("GET", &response(503)).unwrap();
+        assert_eq!(reason.to_string(), "status 503");
+        assert_eq!(policy.wait(&reason, 1, 0), Duration::from_millis(500));
+        assert_eq!(policy.reason("POST", &response(503)), None);
+        assert_eq!(policy.reason("GET", &response(500)), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(
+            retry_after(&headers, 1445412470),
+            Some(Duration::from_secs(10))
+        );
+        // already passed
+        assert_eq!(retry_after(&headers, 1445412490), Some(Duration::ZERO));
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers, 0), None);
+    }
+
+    #[test]
+    fn exponential_delays() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, 0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2, 0), Duration::from_secs(1));
+        assert_eq!(policy.delay(3, 0), Duration::from_secs(2));
+        assert_eq!(policy.delay(3, 1499), Duration::from_millis(2499));
+        assert_eq!(policy.delay(20, 0), MAX_DELAY);
+        assert_eq!(policy.delay(40, 0), MAX_DELAY);
+    }
+}
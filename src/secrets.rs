@@ -0,0 +1,86 @@
+use anyhow::Result;
+
+/// Reads the secret of a `{{$keyring service/entry}}` placeholder from the
+/// system keyring (Secret Service, Keychain or Credential Manager).
+#[cfg(feature = "keyring")]
+pub fn get(name: &str) -> Result<String> {
+    let (service, entry) = split(name)?;
+    keyring::Entry::new(service, entry)
+        .get_password()
+        .map_err(|e| match e {
+            keyring::Error::NoEntry => anyhow::anyhow!(
+                "no keyring entry {} for service {}, add it with `httpclient secret set {}`",
+                entry,
+                service,
+                name
+            ),
+            e => anyhow::anyhow!(
+                "unable to read keyring entry {} for service {}: {}",
+                entry,
+                service,
+                e
+            ),
+        })
+}
+
+/// Stores a secret in the system keyring, for the `{{$keyring service/entry}}`
+/// placeholders.
+#[cfg(feature = "keyring")]
+pub fn set(name: &str, secret: &str) -> Result<()> {
+    let (service, entry) = split(name)?;
+    keyring::Entry::new(service, entry)
+        .set_password(secret)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "unable to store keyring entry {} for service {}: {}",
+                entry,
+                service,
+                e
+            )
+        })
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn get(name: &str) -> Result<String> {
+    split(name)?;
+    Err(not_supported())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn set(name: &str, _secret: &str) -> Result<()> {
+    split(name)?;
+    Err(not_supported())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn not_supported() -> anyhow::Error {
+    anyhow::anyhow!("keyring support is not enabled: build httpclient with --features keyring")
+}
+
+/// Splits `service/entry` in its two parts.
+fn split(name: &str) -> Result<(&str, &str)> {
+    match name.split_once('/') {
+        Some((service, entry)) if !service.is_empty() && !entry.is_empty() => Ok((service, entry)),
+        _ => Err(anyhow::anyhow!(
+            "expected a keyring entry as service/entry, found \"{}\"",
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_names() {
+        assert_eq!(split("github/token").unwrap(), ("github", "token"));
+        assert_eq!(split("api/user/token").unwrap(), ("api", "user/token"));
+        assert_eq!(
+            split("token").unwrap_err().to_string(),
+            "expected a keyring entry as service/entry, found \"token\""
+        );
+        assert!(split("/token").is_err());
+        assert!(split("github/").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::cookies;
+use anyhow::Result;
+use hmac::{Hmac, Mac, NewMac};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// AWS credentials, as read from the standard environment variables.
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn from_env() -> Result<Credentials> {
+        let required = |key: &str| {
+            env::var(key).map_err(|_| {
+                anyhow::anyhow!("you must provide a value for {} to sign the request", key)
+            })
+        };
+        Ok(Credentials {
+            access_key_id: required("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Signs requests for an AWS service with Signature Version 4.
+pub struct Signer {
+    pub region: String,
+    pub service: String,
+    pub credentials: Credentials,
+}
+
+impl Signer {
+    /// Computes the signature of a request, returning the headers to add to
+    /// it (`Authorization`, `X-Amz-Date` and, if needed,
+    /// `X-Amz-Security-Token` and `X-Amz-Content-Sha256`).
+    ///
+    /// All the given `headers` are signed, together with `Host`.
+    pub fn sign(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &HeaderMap,
+        body: &[u8],
+        time: SystemTime,
+    ) -> Result<HeaderMap> {
+        let (region, service, credentials) = (&self.region, &self.service, &self.credentials);
+        let amz_date = format_amz_date(time)?;
+        let payload_hash = format!("{:x}", Sha256::digest(body));
+
+        let mut signature_headers = HeaderMap::new();
+        signature_headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        if let Some(token) = &credentials.session_token {
+            signature_headers.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+        }
+        if service == "s3" {
+            signature_headers.insert(
+                "x-amz-content-sha256",
+                HeaderValue::from_str(&payload_hash)?,
+            );
+        }
+
+        // lowercase names, sorted, with trimmed values
+        let mut canonical_headers: Vec<(String, String)> = vec![("host".to_string(), host(url)?)];
+        for (name, value) in headers.iter().chain(signature_headers.iter()) {
+            if name == header::HOST {
+                continue;
+            }
+            let value = value
+                .to_str()?
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            match canonical_headers
+                .iter_mut()
+                .find(|(n, _)| n == name.as_str())
+            {
+                Some((_, existing)) => *existing = format!("{},{}", existing, value),
+                None => canonical_headers.push((name.as_str().to_string(), value)),
+            }
+        }
+        canonical_headers.sort();
+        let signed_headers = canonical_headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = [
+            method.to_string(),
+            canonical_uri(url, service),
+            canonical_query(url),
+            canonical_headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers.clone(),
+            payload_hash,
+        ]
+        .join("\n");
+
+        let scope = format!("{}/{}/{}/aws4_request", &amz_date[..8], region, service);
+        let string_to_sign = [
+            ALGORITHM.to_string(),
+            amz_date.clone(),
+            scope.clone(),
+            format!("{:x}", Sha256::digest(canonical_request.as_bytes())),
+        ]
+        .join("\n");
+
+        let mut signing_key = format!("AWS4{}", credentials.secret_access_key).into_bytes();
+        for part in &[&amz_date[..8], region, service, "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature: String = hmac_sha256(&signing_key, string_to_sign.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        signature_headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!(
+                "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                ALGORITHM, credentials.access_key_id, scope, signed_headers, signature
+            ))?,
+        );
+        Ok(signature_headers)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn host(url: &Url) -> Result<String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("cannot sign a request without host: {}", url))?;
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+/// Percent-encodes everything but the unreserved characters, as required by
+/// the canonical request.
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn canonical_uri(url: &Url, service: &str) -> String {
+    if service == "s3" {
+        // S3 expects the path encoded only once, as it's sent
+        return url.path().to_string();
+    }
+    url.path()
+        .split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k), uri_encode(&v)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Formats a time as `YYYYMMDDTHHMMSSZ`, in UTC.
+fn format_amz_date(time: SystemTime) -> Result<String> {
+    let seconds = time.duration_since(UNIX_EPOCH)?.as_secs();
+    let day_seconds = seconds % 86400;
+    let (year, month, day) = cookies::civil_from_days((seconds / 86400) as i64);
+    Ok(format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        day_seconds / 3600,
+        day_seconds % 3600 / 60,
+        day_seconds % 60
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // from the AWS Signature Version 4 test suite
+    fn sign_test_vector(method: &str, url: &str, headers: HeaderMap, body: &str) -> String {
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        // 2015-08-30T12:36:00Z
+        let time = UNIX_EPOCH + Duration::from_secs(1440938160);
+        let signer = Signer {
+            region: "us-east-1".to_string(),
+            service: "service".to_string(),
+            credentials,
+        };
+        let signature_headers = signer
+            .sign(
+                method,
+                &Url::parse(url).unwrap(),
+                &headers,
+                body.as_bytes(),
+                time,
+            )
+            .unwrap();
+        assert_eq!(signature_headers["x-amz-date"], "20150830T123600Z");
+        signature_headers[header::AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn get_vanilla() {
+        assert_eq!(
+            sign_test_vector(
+                "GET",
+                "https://example.amazonaws.com/",
+                HeaderMap::new(),
+                ""
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn get_vanilla_query_order_key_case() {
+        assert_eq!(
+            sign_test_vector(
+                "GET",
+                "https://example.amazonaws.com/?Param2=value2&Param1=value1",
+                HeaderMap::new(),
+                ""
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn post_x_www_form_urlencoded() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        assert_eq!(
+            sign_test_vector(
+                "POST",
+                "https://example.amazonaws.com/",
+                headers,
+                "Param1=value1"
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+        );
+    }
+
+    #[test]
+    fn amz_date() {
+        assert_eq!(
+            format_amz_date(UNIX_EPOCH + Duration::from_secs(951782400)).unwrap(),
+            "20000229T000000Z"
+        );
+    }
+}
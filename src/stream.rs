@@ -0,0 +1,393 @@
+//! The bodies that don't end until the server closes the connection, as the
+//! Server-Sent Events (`text/event-stream`) and the JSON lines
+//! (`application/x-ndjson`) ones, read as they arrive, printing each event
+//! or line as soon as it's received, until the end or Ctrl-C.
+
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the reading checks for a Ctrl-C.
+const INTERRUPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The `Accept` and `Content-Type` values of the streamed bodies.
+const EVENT_STREAM: &str = "text/event-stream";
+const JSON_LINES: [&str; 3] = [
+    "application/x-ndjson",
+    "application/ndjson",
+    "application/jsonl",
+];
+
+/// How a body is split to be printed as it arrives.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    /// the events of Server-Sent Events, each one ending with a blank line
+    Events,
+    /// a JSON value by line
+    JsonLines,
+    #[default]
+    Lines,
+}
+
+impl Format {
+    /// The format of a body, from its `Content-Type`: none for the bodies
+    /// read whole.
+    pub fn of(headers: &HeaderMap) -> Option<Format> {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| ct.split(';').next())?
+            .trim()
+            .to_ascii_lowercase();
+        match content_type.as_str() {
+            EVENT_STREAM => Some(Format::Events),
+            ct if JSON_LINES.contains(&ct) => Some(Format::JsonLines),
+            _ => None,
+        }
+    }
+
+    /// Tells whether an `Accept` header only asks for a streamed body.
+    pub fn accepted(accept: &str) -> bool {
+        let accept = accept.trim().to_ascii_lowercase();
+        accept == EVENT_STREAM || JSON_LINES.contains(&accept.as_str())
+    }
+
+    /// What the parts are named, in the `-v` summary.
+    fn unit(self) -> &'static str {
+        match self {
+            Format::Events => "events",
+            Format::JsonLines | Format::Lines => "lines",
+        }
+    }
+}
+
+/// What was received of a stream.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Events {
+    pub format: Format,
+    /// the events, or the lines
+    pub count: usize,
+    /// how long the stream lasted
+    pub elapsed: Duration,
+    /// whether a Ctrl-C stopped it, rather than the server
+    pub interrupted: bool,
+    /// whether it was longer than the limit it was read with, and stopped
+    /// there
+    pub truncated: bool,
+}
+
+impl Events {
+    /// The `-v` summary, like `Streamed 3 events in 1.2s`.
+    pub fn summary(&self) -> String {
+        format!(
+            "Streamed {} {} in {:?}{}",
+            self.count,
+            self.format.unit(),
+            self.elapsed,
+            match self.interrupted {
+                true => ", until interrupted",
+                false => "",
+            }
+        )
+    }
+}
+
+/// Reads `inner` to the end, writing to `out` each event, its lines followed
+/// by a blank one, or each line, reassembled when split across chunks; the
+/// comments of the events, the lines starting with `:` as the keep-alive
+/// ones, are left out, as the blank JSON lines. With `pretty`, the JSON
+/// lines are pretty-printed.
+///
+/// Fails when nothing is received for `idle_timeout`, and stops once
+/// `interrupted` is set, or past `max_size` bytes unless zero; returns the
+/// body received, as it was.
+pub fn read<R: Read + Send + 'static>(
+    inner: R,
+    format: Format,
+    pretty: bool,
+    max_size: u64,
+    idle_timeout: Option<Duration>,
+    interrupted: &AtomicBool,
+    out: &mut dyn Write,
+) -> Result<(Vec<u8>, Events)> {
+    // a thread reads, so that waiting can stop on a timeout or a Ctrl-C
+    let (sender, lines) = mpsc::channel();
+    let limit = match max_size {
+        0 => u64::MAX,
+        // the byte past the limit tells that the body is longer
+        _ => max_size + 1,
+    };
+    thread::spawn(move || {
+        let mut reader = BufReader::new(inner.take(limit));
+        loop {
+            let mut line = Vec::new();
+            let read = reader.read_until(b'\n', &mut line);
+            let end = !matches!(read, Ok(read) if read > 0);
+            if sender.send(read.map(|_| line)).is_err() || end {
+                return;
+            }
+        }
+    });
+
+    let start = Instant::now();
+    let mut last_received = start;
+    let mut body = Vec::new();
+    let mut event = Vec::new();
+    let mut count = 0;
+    let mut truncated = false;
+    let write_error = "unable to write the stream";
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Some(idle_timeout) = idle_timeout {
+            if last_received.elapsed() >= idle_timeout {
+                return Err(anyhow::anyhow!(
+                    "nothing received for {:?}, after {} {}",
+                    idle_timeout,
+                    count,
+                    format.unit()
+                ));
+            }
+        }
+        let line = match lines.recv_timeout(INTERRUPT_INTERVAL) {
+            Ok(line) => line.context("unable to read the stream")?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        last_received = Instant::now();
+        // the end of the body, ending the last event too
+        let closed = line.is_empty();
+        if max_size > 0 && (body.len() + line.len()) as u64 > max_size {
+            // the event or the line cut isn't printed
+            body.extend_from_slice(&line[..max_size as usize - body.len()]);
+            truncated = true;
+            break;
+        }
+        body.extend_from_slice(&line);
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        match format {
+            Format::Events if text.starts_with(':') => continue,
+            Format::Events if !text.is_empty() => {
+                event.extend_from_slice(text.as_bytes());
+                event.push(b'\n');
+                continue;
+            }
+            Format::Events if !event.is_empty() => event.push(b'\n'),
+            Format::Events => (),
+            Format::JsonLines if text.trim().is_empty() => (),
+            Format::JsonLines if pretty => {
+                let pretty = json::parse(text).map(|value| json::stringify_pretty(value, 2));
+                event.extend_from_slice(pretty.as_deref().unwrap_or(text).as_bytes());
+                event.push(b'\n');
+            }
+            Format::JsonLines | Format::Lines if !closed => {
+                event.extend_from_slice(text.as_bytes());
+                event.push(b'\n');
+            }
+            Format::JsonLines | Format::Lines => (),
+        }
+        if !event.is_empty() {
+            out.write_all(&event).context(write_error)?;
+            out.flush().context(write_error)?;
+            event.clear();
+            count += 1;
+        }
+        if closed {
+            break;
+        }
+    }
+    Ok((
+        body,
+        Events {
+            format,
+            count,
+            elapsed: start.elapsed(),
+            interrupted: interrupted.load(Ordering::SeqCst),
+            truncated,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::io::Cursor;
+
+    #[test]
+    fn formats() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Format::of(&headers), None);
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream; charset=utf-8"),
+        );
+        assert_eq!(Format::of(&headers), Some(Format::Events));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-ndjson"),
+        );
+        assert_eq!(Format::of(&headers), Some(Format::JsonLines));
+        assert!(Format::accepted("text/event-stream"));
+        assert!(Format::accepted("application/x-ndjson"));
+        assert!(!Format::accepted("application/json, text/event-stream"));
+    }
+
+    fn read_all(stream: &[u8], format: Format, pretty: bool) -> (String, Vec<u8>, Events) {
+        let mut out = Vec::new();
+        let (body, events) = read(
+            Cursor::new(stream.to_vec()),
+            format,
+            pretty,
+            0,
+            None,
+            &AtomicBool::new(false),
+            &mut out,
+        )
+        .unwrap();
+        (String::from_utf8(out).unwrap(), body, events)
+    }
+
+    #[test]
+    fn events() {
+        let stream = b": keep-alive\n\nevent: price\ndata: {\"eur\": 1}\n\nid: 2\r\ndata: a\r\ndata: b\r\n\r\ndata: last";
+        let (out, body, events) = read_all(stream, Format::Events, false);
+        assert_eq!(
+            out,
+            "event: price\ndata: {\"eur\": 1}\n\nid: 2\ndata: a\ndata: b\n\ndata: last\n\n"
+        );
+        assert_eq!(body, stream);
+        assert_eq!(events.count, 3);
+        assert!(!events.interrupted);
+        assert!(events.summary().starts_with("Streamed 3 events in "));
+
+        // the lines of other bodies
+        let (out, _, events) = read_all(b"started\n\nrunning\n", Format::Lines, false);
+        assert_eq!(out, "started\n\nrunning\n");
+        assert_eq!(events.count, 3);
+    }
+
+    #[test]
+    fn json_lines() {
+        let stream = b"{\"status\":\"pulling\"}\n\n{\"status\":\"done\",\"id\":1}\n";
+        let (out, body, events) = read_all(stream, Format::JsonLines, false);
+        assert_eq!(
+            out,
+            "{\"status\":\"pulling\"}\n{\"status\":\"done\",\"id\":1}\n"
+        );
+        assert_eq!(body, stream);
+        assert_eq!(events.count, 2);
+        assert!(events.summary().starts_with("Streamed 2 lines in "));
+
+        let (out, _, _) = read_all(b"{\"id\":1}\nnot json\n", Format::JsonLines, true);
+        assert_eq!(out, "{\n  \"id\": 1\n}\nnot json\n");
+    }
+
+    #[test]
+    fn truncated_streams() {
+        let mut out = Vec::new();
+        let (body, events) = read(
+            Cursor::new(b"data: 1\n\ndata: 2\n\ndata: 3\n\n".to_vec()),
+            Format::Events,
+            false,
+            12,
+            None,
+            &AtomicBool::new(false),
+            &mut out,
+        )
+        .unwrap();
+        // the second event is cut
+        assert_eq!(out, b"data: 1\n\n");
+        assert_eq!(body, b"data: 1\n\ndat");
+        assert_eq!(events.count, 1);
+        assert!(events.truncated);
+    }
+
+    /// Sends its chunks one by one, slowly.
+    struct Chunks(Vec<&'static [u8]>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            thread::sleep(Duration::from_millis(20));
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn split_lines() {
+        let chunks = Chunks(vec![b"{\"tok", b"en\":\"a\"}\n{\"token\"", b":\"b\"}\n"]);
+        let mut out = Vec::new();
+        let (_, events) = read(
+            chunks,
+            Format::JsonLines,
+            false,
+            0,
+            None,
+            &AtomicBool::new(false),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(out, b"{\"token\":\"a\"}\n{\"token\":\"b\"}\n");
+        assert_eq!(events.count, 2);
+    }
+
+    /// Sends an event, then nothing.
+    struct Stalled(bool);
+
+    impl Read for Stalled {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0 {
+                thread::sleep(Duration::from_secs(5));
+                return Ok(0);
+            }
+            self.0 = true;
+            buf[..12].copy_from_slice(b"data: once\n\n");
+            Ok(12)
+        }
+    }
+
+    #[test]
+    fn idle_streams() {
+        let mut out = Vec::new();
+        let error = read(
+            Stalled(false),
+            Format::Events,
+            false,
+            0,
+            Some(Duration::from_millis(300)),
+            &AtomicBool::new(false),
+            &mut out,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "nothing received for 300ms, after 1 events"
+        );
+        assert_eq!(out, b"data: once\n\n");
+
+        let (body, events) = read(
+            Stalled(false),
+            Format::Events,
+            false,
+            0,
+            None,
+            &AtomicBool::new(true),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(body.is_empty());
+        assert!(events.interrupted);
+    }
+}
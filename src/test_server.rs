@@ -0,0 +1,110 @@
+//! The local HTTP/1.1 servers of the tests, each on a port of its own,
+//! answering with the responses the tests write by hand.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// Reads a request from `stream`: the head, up to the blank line, and the
+/// body of its `Content-Length`, if any, but for the requests waiting for a
+/// `100 Continue` to send it; less if the client closes the connection
+/// first.
+pub fn read_request(stream: &mut impl Read) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut read = |received: &mut Vec<u8>| match stream.read(&mut buffer) {
+        Ok(read) if read > 0 => {
+            received.extend_from_slice(&buffer[..read]);
+            true
+        }
+        _ => false,
+    };
+    let head_end = loop {
+        if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if !read(&mut received) {
+            return received;
+        }
+    };
+    let head = String::from_utf8_lossy(&received[..head_end]).to_ascii_lowercase();
+    if head.contains("\r\nexpect: 100-continue\r\n") {
+        return received;
+    }
+    let length = head
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .and_then(|length| length.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while received.len() < head_end + length && read(&mut received) {}
+    received
+}
+
+/// A server handing each connection in turn to `handle`, with the request
+/// read from it.
+pub fn serve_with<F>(mut handle: F) -> SocketAddr
+where
+    F: FnMut(&mut TcpStream, &str) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let received = read_request(&mut stream);
+            handle(&mut stream, &String::from_utf8_lossy(&received));
+        }
+    });
+    address
+}
+
+/// A server answering each connection in turn with what `respond` returns
+/// for its request.
+pub fn serve<F, R>(mut respond: F) -> SocketAddr
+where
+    F: FnMut(&str) -> R + Send + 'static,
+    R: AsRef<[u8]>,
+{
+    serve_with(move |stream, received| {
+        // the clients can leave without waiting for it
+        let _ = stream.write_all(respond(received).as_ref());
+    })
+}
+
+/// Like `serve`, answering each connection in a thread of its own, not to
+/// delay the others.
+pub fn serve_concurrently<F, R>(respond: F) -> SocketAddr
+where
+    F: Fn(&str) -> R + Send + Sync + 'static,
+    R: AsRef<[u8]>,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let respond = Arc::new(respond);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let respond = respond.clone();
+            thread::spawn(move || {
+                let received = read_request(&mut stream);
+                let _ = stream.write_all(respond(&String::from_utf8_lossy(&received)).as_ref());
+            });
+        }
+    });
+    address
+}
+
+/// A server answering a single connection with `response`, then returning
+/// the request it received.
+pub fn serve_once(response: &'static [u8]) -> (SocketAddr, thread::JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let received = read_request(&mut stream);
+        stream.write_all(response).unwrap();
+        received
+    });
+    (address, server)
+}
@@ -0,0 +1,322 @@
+use crate::client::ClientOptions;
+use crate::error::TransportError;
+use crate::http1;
+use crate::request::{self, Request};
+use anyhow::Result;
+use reqwest::blocking::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Version};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Sends the requests, in place of the clients of reqwest: a mock in the
+/// tests, or the responses of an archive with `--replay`. The redirects, the
+/// streams and the bodies written to a file are handled on top of it, as
+/// for any response.
+pub trait HttpTransport: Send + Sync {
+    /// Readies the transport to send `req` with `options`, failing before
+    /// the first attempt if it can't, as when the client can't be built.
+    fn prepare(&self, _req: &Request, _options: &ClientOptions) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sends `req` with `options`, as `ClientOptions::for_request` made them,
+    /// returning the response once its head arrives, the body left to read.
+    /// `timeout` is the longest wait for the whole response, zero for no
+    /// limit; the `# @timeout` of the request overrides it.
+    fn send(&self, req: &Request, options: &ClientOptions, timeout: Duration) -> Result<Response>;
+}
+
+/// A response as a transport received it: the head, with the body left to
+/// read as it arrives.
+pub struct Response {
+    version: Version,
+    status: StatusCode,
+    headers: HeaderMap,
+    content_length: Option<u64>,
+    body: Box<dyn Read + Send>,
+}
+
+impl Response {
+    /// A response with the body read from `body`, of `content_length` bytes
+    /// if known in advance.
+    pub fn new<R: Read + Send + 'static>(
+        version: Version,
+        status: StatusCode,
+        headers: HeaderMap,
+        content_length: Option<u64>,
+        body: R,
+    ) -> Response {
+        Response {
+            version,
+            status,
+            headers,
+            content_length,
+            body: Box::new(body),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The size of the body, if known before reading it: not for the
+    /// decompressed ones.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Reads the whole body.
+    pub fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.body
+            .read_to_end(&mut body)
+            .map_err(request::body_read_error)?;
+        Ok(body)
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("version", &self.version)
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl From<http::Response<Vec<u8>>> for Response {
+    fn from(response: http::Response<Vec<u8>>) -> Response {
+        let (head, body) = response.into_parts();
+        let size = body.len() as u64;
+        Response::new(
+            head.version,
+            head.status,
+            head.headers,
+            Some(size),
+            io::Cursor::new(body),
+        )
+    }
+}
+
+impl From<reqwest::blocking::Response> for Response {
+    fn from(response: reqwest::blocking::Response) -> Response {
+        Response {
+            version: response.version(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            content_length: response.content_length(),
+            body: Box::new(response),
+        }
+    }
+}
+
+/// The transport of reqwest, the default one, reusing a client, and its
+/// connections, for the requests with the same options and protocol.
+#[derive(Default)]
+pub struct Reqwest {
+    clients: Mutex<Vec<((ClientOptions, String), Client)>>,
+}
+
+impl Reqwest {
+    pub fn new() -> Reqwest {
+        Reqwest::default()
+    }
+
+    /// The client sending `req` with `options`, built by the first request
+    /// with the same options and protocol.
+    pub fn client(&self, req: &Request, options: &ClientOptions) -> Result<Client> {
+        let key = (options.clone(), req.protocol.clone());
+        let mut clients = self.clients.lock().unwrap();
+        if let Some((_, client)) = clients.iter().find(|(k, _)| *k == key) {
+            return Ok(client.clone());
+        }
+        let client = req.client(options)?;
+        clients.push((key, client.clone()));
+        Ok(client)
+    }
+}
+
+impl HttpTransport for Reqwest {
+    fn prepare(&self, req: &Request, options: &ClientOptions) -> Result<()> {
+        if req.expect_continue {
+            // sent by hand, without the client
+            http1::check_options(options, &req.target_url()?)?;
+        }
+        self.client(req, options).map(|_| ())
+    }
+
+    fn send(&self, req: &Request, options: &ClientOptions, timeout: Duration) -> Result<Response> {
+        req.execute_with(&self.client(req, options)?, timeout)
+            .map(Response::from)
+    }
+}
+
+/// A reply given in advance to a `Mock`.
+type Reply = std::result::Result<(u16, Vec<(String, String)>, Vec<u8>), TransportError>;
+
+/// A transport answering with the responses, or failing with the errors,
+/// given in advance, in order, and keeping the requests sent.
+///
+/// # Examples
+///
+/// ```
+/// use httpclient::client::ClientOptions;
+/// use httpclient::error::TransportError;
+/// use httpclient::transport::{HttpTransport, Mock};
+/// use std::time::Duration;
+///
+/// let mock = Mock::new()
+///     .fail(TransportError::Timeout)
+///     .respond(200, &[("content-type", "text/plain")], b"ok");
+/// let mut req = httpclient::request::Request::new();
+/// req.url = "https://example.com".to_string();
+/// let (options, timeout) = (ClientOptions::default(), Duration::from_secs(1));
+/// assert!(mock.send(&req, &options, timeout).is_err());
+/// let response = mock.send(&req, &options, timeout).unwrap();
+/// assert_eq!(response.bytes().unwrap(), b"ok");
+/// assert_eq!(mock.sent().len(), 2);
+/// ```
+#[derive(Default)]
+pub struct Mock {
+    replies: Mutex<VecDeque<Reply>>,
+    sent: Mutex<Vec<Request>>,
+}
+
+impl Mock {
+    pub fn new() -> Mock {
+        Mock::default()
+    }
+
+    /// Answers the next request with `status`, `headers` and `body`, over
+    /// HTTP/1.1.
+    pub fn respond(self, status: u16, headers: &[(&str, &str)], body: &[u8]) -> Mock {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.replies
+            .lock()
+            .unwrap()
+            .push_back(Ok((status, headers, body.to_vec())));
+        self
+    }
+
+    /// Fails the next request with `error`.
+    pub fn fail(self, error: TransportError) -> Mock {
+        self.replies.lock().unwrap().push_back(Err(error));
+        self
+    }
+
+    /// The requests sent so far, as they were sent, with their cookies.
+    pub fn sent(&self) -> Vec<Request> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl HttpTransport for Mock {
+    fn send(
+        &self,
+        req: &Request,
+        _options: &ClientOptions,
+        _timeout: Duration,
+    ) -> Result<Response> {
+        self.sent.lock().unwrap().push(req.clone());
+        let (status, headers, body) = match self.replies.lock().unwrap().pop_front() {
+            Some(reply) => reply?,
+            None => {
+                return Err(anyhow::anyhow!(
+                    "no response left for {} {}",
+                    req.method,
+                    req.url
+                ))
+            }
+        };
+        let mut response = http::Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name.as_str(), value);
+        }
+        Ok(response.body(body)?.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error, test_server};
+
+    #[test]
+    fn reqwest_transport() {
+        let (address, server) = test_server::serve_once(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123456789",
+        );
+        let transport = Reqwest::new();
+        let options = ClientOptions::default();
+        let mut req = Request::new();
+        req.method = "GET".to_string();
+        req.url = format!("http://{}/", address);
+        transport.prepare(&req, &options).unwrap();
+        let response = transport
+            .send(&req, &options, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(response.bytes().unwrap(), b"0123456789");
+        // a client for each protocol
+        assert_eq!(transport.clients.lock().unwrap().len(), 1);
+        req.protocol = "HTTP/2".to_string();
+        transport.prepare(&req, &options).unwrap();
+        assert_eq!(transport.clients.lock().unwrap().len(), 2);
+        req.protocol = "HTTP/1.1".to_string();
+
+        // the listener is gone
+        server.join().unwrap();
+        let error = transport
+            .send(&req, &options, Duration::from_secs(10))
+            .unwrap_err();
+        assert!(error::is_connect(&error));
+        assert!(error::is_transport(&error));
+        assert!(!error::is_timeout(&error));
+        assert_eq!(transport.clients.lock().unwrap().len(), 2);
+
+        req.protocol = "HTTP/0.9".to_string();
+        let error = transport.prepare(&req, &options).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<error::Error>(),
+            Some(error::Error::UnsupportedProtocol { protocol, .. }) if protocol == "HTTP/0.9"
+        ));
+    }
+
+    #[test]
+    fn mock_errors() {
+        let mock = Mock::new().fail(TransportError::Connect("refused".to_string()));
+        let (req, options) = (Request::new(), ClientOptions::default());
+        let error = mock
+            .send(&req, &options, Duration::from_secs(1))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "unable to connect: refused");
+        assert!(error::is_connect(&error));
+        assert!(error::is_transport(&error));
+        // nothing left
+        let error = mock
+            .send(&req, &options, Duration::from_secs(1))
+            .unwrap_err();
+        assert!(!error::is_transport(&error));
+    }
+}
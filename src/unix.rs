@@ -0,0 +1,208 @@
+//! Requests to the servers listening on a unix domain socket, like the Docker
+//! daemon: reqwest only connects to TCP ports, so they're written and read
+//! here, with HTTP/1.1 and a connection each.
+
+use crate::http1::{self, Length};
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Method, Url, Version};
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The socket a request goes to, and the URL it's for: the one of
+/// `# @unix-socket`, or the one of a `unix:///var/run/docker.sock:/path` URL,
+/// that is for `http://localhost/path`.
+pub fn target(socket: Option<&Path>, url: &Url) -> Result<Option<(PathBuf, Url)>> {
+    if url.scheme() != "unix" {
+        return Ok(socket.map(|socket| (socket.to_path_buf(), url.clone())));
+    }
+    let (socket, path) = url.path().split_once(':').ok_or_else(|| {
+        anyhow::anyhow!(
+            "the URL {} has no path after the socket: expected unix:///path/to.sock:/path",
+            url
+        )
+    })?;
+    let mut http_url = Url::parse(&format!(
+        "http://localhost/{}",
+        path.trim_start_matches('/')
+    ))?;
+    http_url.set_query(url.query());
+    Ok(Some((PathBuf::from(socket), http_url)))
+}
+
+/// Sends a request through a socket, and reads the whole response, or
+/// `max_size` bytes of its body and one more, unless zero.
+#[allow(clippy::too_many_arguments)]
+pub fn send(
+    socket: &Path,
+    method: &Method,
+    url: &Url,
+    version: Version,
+    headers: &HeaderMap,
+    body: &[u8],
+    timeout: Option<Duration>,
+    max_size: u64,
+) -> Result<http::Response<Vec<u8>>> {
+    let version = match version {
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        _ => {
+            return Err(anyhow::anyhow!(
+                "{:?} isn't supported over unix sockets: expected HTTP/1.0 or HTTP/1.1",
+                version
+            ))
+        }
+    };
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("unable to connect to the unix socket {}", socket.display()))?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let sends_body =
+        !body.is_empty() || [Method::POST, Method::PUT, Method::PATCH].contains(method);
+    let length = Some(Length::Known(body.len() as u64))
+        .filter(|_| sends_body && !headers.contains_key(header::CONTENT_LENGTH));
+    let mut request = http1::head(method, url, version, headers, length);
+    request.extend(body);
+    stream.write_all(&request).with_context(|| {
+        format!(
+            "unable to send the request to the unix socket {}",
+            socket.display()
+        )
+    })?;
+
+    http1::Received::new(stream)
+        .response(method, max_size)
+        .with_context(|| {
+            format!(
+                "unable to read the response from the unix socket {}",
+                socket.display()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+
+    /// A server on a socket in the temporary directory, answering a request
+    /// with `response` and returning what it received.
+    fn socket_server(
+        name: &str,
+        response: &'static [u8],
+    ) -> (PathBuf, std::thread::JoinHandle<String>) {
+        let path =
+            std::env::temp_dir().join(format!("httpclient-{}-{}.sock", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut buffer = [0; 4096];
+            while !received.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(response).unwrap();
+            String::from_utf8_lossy(&received).to_string()
+        });
+        (path, server)
+    }
+
+    #[test]
+    fn targets() {
+        let url = Url::parse("unix:///var/run/docker.sock:/v1.41/containers/json?all=1").unwrap();
+        let (socket, http_url) = target(None, &url).unwrap().unwrap();
+        assert_eq!(socket, PathBuf::from("/var/run/docker.sock"));
+        assert_eq!(
+            http_url.as_str(),
+            "http://localhost/v1.41/containers/json?all=1"
+        );
+
+        let url = Url::parse("http://docker/v1.41/info").unwrap();
+        assert_eq!(target(None, &url).unwrap(), None);
+        assert_eq!(
+            target(Some(Path::new("/var/run/docker.sock")), &url).unwrap(),
+            Some((PathBuf::from("/var/run/docker.sock"), url))
+        );
+
+        let url = Url::parse("unix:///var/run/docker.sock").unwrap();
+        assert_eq!(
+            target(None, &url).err().unwrap().to_string(),
+            "the URL unix:///var/run/docker.sock has no path after the socket: expected unix:///path/to.sock:/path"
+        );
+    }
+
+    #[test]
+    fn send_through_a_socket() {
+        let (socket, server) = socket_server(
+            "chunked",
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\n[{}\r\n1\r\n]\r\n0\r\n\r\n",
+        );
+        let url = Url::parse("http://docker/v1.41/containers/json?all=1").unwrap();
+        let response = send(
+            &socket,
+            &Method::GET,
+            &url,
+            Version::HTTP_11,
+            &HeaderMap::new(),
+            b"",
+            Some(Duration::from_secs(10)),
+            0,
+        )
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert_eq!(response.body(), b"[{}]");
+        assert_eq!(
+            server.join().unwrap(),
+            "GET /v1.41/containers/json?all=1 HTTP/1.1\r\nHost: docker\r\nConnection: close\r\n\r\n"
+        );
+        std::fs::remove_file(&socket).ok();
+    }
+
+    #[test]
+    fn requests() {
+        let (socket, server) = socket_server(
+            "request",
+            b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+        );
+        let mut req = crate::request::Request::new();
+        req.method = "DELETE".to_string();
+        req.url = format!("unix://{}:/v1.41/containers/web", socket.display());
+        req.headers
+            .insert("Authorization".to_string(), "Bearer t0k3n".to_string());
+        let response = req.execute(Duration::from_secs(10)).unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            server.join().unwrap(),
+            "DELETE /v1.41/containers/web HTTP/1.1\r\nHost: localhost\r\nauthorization: Bearer t0k3n\r\nConnection: close\r\n\r\n"
+        );
+        std::fs::remove_file(&socket).ok();
+    }
+
+    #[test]
+    fn missing_sockets() {
+        let socket = Path::new("/nonexistent/docker.sock");
+        let error = send(
+            socket,
+            &Method::GET,
+            &Url::parse("http://localhost/").unwrap(),
+            Version::HTTP_11,
+            &HeaderMap::new(),
+            b"",
+            Some(Duration::from_secs(10)),
+            0,
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("unable to connect to the unix socket /nonexistent/docker.sock"));
+    }
+}
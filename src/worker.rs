@@ -1,9 +1,44 @@
+use crate::error::{ExpansionError, ParseError};
 use crate::*;
+use rand::Rng;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
-use std::io::{Error, ErrorKind, Result as IoResult};
+use std::io::ErrorKind;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How many `# @import` levels can be nested.
+const MAX_IMPORT_DEPTH: usize = 16;
+/// How many variables can be nested one into another's value.
+const MAX_VARIABLE_DEPTH: usize = 10;
+/// The longest value `{{$randomString}}` and `{{$randomHex}}` can generate.
+const MAX_RANDOM_LENGTH: usize = 1024;
+
+/// State of a body written between `<<<` and `>>>` lines.
+#[derive(PartialEq)]
+enum BodyFence {
+    None,
+    /// opened in the given line
+    Open(usize),
+    Closed,
+}
+
+/// What the variable replacement found out about a request block, for its
+/// parsing.
+#[derive(Default)]
+struct BlockContext {
+    // the `@host` the paths are relative to
+    base_url: Option<String>,
+    // the `{{$random...}}` placeholders, with their values
+    generated_values: Vec<(String, String)>,
+    // the values read from the keyring, to hide when printing the request
+    secrets: Vec<String>,
+}
 
 /// HTTPParser parses an HTTP request text into a single valid `request` struct.
 ///
@@ -34,27 +69,136 @@ pub struct HTTPParser {
     pub request: request::Request,
     head_done: bool,
     body_buffer: Vec<String>,
+    // set by `# @raw-body`: the body is kept as is, without trimming it
+    raw_body: bool,
+    // set by `# @explode`: `?id=1,2` URL parameters are sent as `id=1&id=2`
+    explode: bool,
+    // number, in the whole file, of the first line of the parsed text
+    first_line: usize,
+    // number of the line being parsed, for error messages
+    line_number: usize,
+    // once the `??` assertions start, nothing else but comments can follow
+    assertions_started: bool,
+    // after the `>> file` line, only comments and assertions can follow
+    output_started: bool,
+    // number of the line with the URL
+    url_line: usize,
+    body_fence: BodyFence,
+    // the value of the `@host` variable, prepended to the URLs that are paths
+    base_url: Option<String>,
+    // the scheme for the URLs without one, from `# @scheme` or the caller
+    default_scheme: Option<String>,
+    // the name of each header, and key of each URL parameter, with its line
+    header_lines: Vec<(String, usize)>,
+    parameter_lines: Vec<(String, usize)>,
 }
 
 impl HTTPParser {
-    pub fn new() -> IoResult<HTTPParser> {
+    pub fn new() -> Result<HTTPParser, ParseError> {
         let w = HTTPParser {
             request: request::Request::new(),
             head_done: false,
             body_buffer: Vec::new(),
+            raw_body: false,
+            explode: false,
+            first_line: 1,
+            line_number: 1,
+            assertions_started: false,
+            output_started: false,
+            url_line: 1,
+            base_url: None,
+            body_fence: BodyFence::None,
+            default_scheme: None,
+            header_lines: Vec::new(),
+            parameter_lines: Vec::new(),
         };
 
         Ok(w)
     }
 
-    pub fn parse(&mut self, contents: &str) -> IoResult<()> {
-        for line in contents.split("\n") {
+    pub fn parse(&mut self, contents: &str) -> Result<(), ParseError> {
+        for (index, line) in contents.split("\n").enumerate() {
+            self.line_number = self.first_line + index;
             if line.trim().len() == 0 && self.request.url == "" {
                 // just an empty line before the request text starts
                 continue;
             }
-            if line.starts_with("//") || line.starts_with("#") {
-                // this is a comment!
+            if let BodyFence::Open(_) = self.body_fence {
+                // fenced body: everything until `>>>`, as is
+                if line.trim() == ">>>" {
+                    self.body_fence = BodyFence::Closed;
+                } else {
+                    self.body_buffer.push(line.to_string());
+                }
+                continue;
+            }
+            if self.is_output_file(line) {
+                if self.raw_body
+                    && !self.assertions_started
+                    && !self.output_started
+                    && self.body_fence == BodyFence::None
+                {
+                    // the blank line separating the output file from the body
+                    self.body_buffer.pop();
+                }
+                self.parse_output_file(line)?;
+                continue;
+            }
+            if self.is_assertion(line) {
+                if self.raw_body
+                    && !self.assertions_started
+                    && !self.output_started
+                    && self.body_fence == BodyFence::None
+                {
+                    // the blank line separating the assertions from the body
+                    self.body_buffer.pop();
+                }
+                self.parse_assertion(line)?;
+                continue;
+            }
+            if self.assertions_started || self.output_started {
+                if line.trim().len() == 0 || HTTPParser::is_comment(line.trim_start()) {
+                    continue;
+                }
+                return Err(ParseError::UnexpectedLine {
+                    line: self.line_number,
+                    after: match self.assertions_started {
+                        true => "assertions",
+                        false => "the output file",
+                    },
+                });
+            }
+            if self.body_fence == BodyFence::Closed {
+                if line.trim().len() == 0 || HTTPParser::is_comment(line.trim_start()) {
+                    continue;
+                }
+                return Err(ParseError::UnexpectedLine {
+                    line: self.line_number,
+                    after: "the body",
+                });
+            }
+            if self.head_done
+                && line.trim() == "<<<"
+                && self.body_buffer.iter().all(|l| l.trim().len() == 0)
+            {
+                self.body_buffer.clear();
+                self.body_fence = BodyFence::Open(self.line_number);
+                continue;
+            }
+            if self.head_done && self.raw_body {
+                // RAW body, comments included
+                self.body_buffer.push(line.to_string());
+                continue;
+            }
+            // in the head section comments can be indented, e.g. to be aligned
+            // with the URL parameters; in the body they're part of the payload
+            let comment_candidate = match self.head_done {
+                true => line,
+                false => line.trim_start(),
+            };
+            if HTTPParser::is_comment(comment_candidate) {
+                // this is a comment, possibly carrying a directive
+                self.parse_directive(comment_candidate)?;
                 continue;
             }
             if self.head_done {
@@ -79,92 +223,516 @@ impl HTTPParser {
                 }
             }
         }
-        self.request.body = self.body_buffer.join("\n").trim().to_string();
+        if self.request.url.starts_with('/') {
+            if let Some(base_url) = &self.base_url {
+                self.request.url =
+                    format!("{}{}", base_url.trim_end_matches('/'), self.request.url);
+            } else if self.request.host_header().is_none() && self.request.unix_socket.is_none() {
+                // the servers of the unix sockets are local
+                return Err(ParseError::RelativeUrl {
+                    line: self.url_line,
+                    url: self.request.url.clone(),
+                });
+            }
+        }
+        let url = &self.request.url;
+        if !url.is_empty()
+            && !url.starts_with('/')
+            && !url.starts_with("{{")
+            && !url.contains("://")
+        {
+            // like `localhost:8080/health`
+            self.request.url = format!(
+                "{}://{}",
+                self.default_scheme.as_deref().unwrap_or("http"),
+                url
+            );
+        }
+        if self.request.url.contains("://") && !self.request.url.contains("{{") {
+            // the references to other responses are checked once resolved
+            let url =
+                reqwest::Url::parse(&self.request.url).map_err(|e| ParseError::InvalidUrl {
+                    line: self.url_line,
+                    url: self.request.url.clone(),
+                    reason: e.to_string(),
+                })?;
+            self.normalize_host(url.host_str().unwrap_or(""));
+        }
+        if self.explode {
+            self.request.url_parameters = self
+                .request
+                .url_parameters
+                .drain(..)
+                .flat_map(|(key, value)| match value {
+                    Some(values) => values
+                        .split(',')
+                        .map(|value| (key.to_string(), Some(value.to_string())))
+                        .collect(),
+                    None => vec![(key, None)],
+                })
+                .collect();
+        }
+        if let BodyFence::Open(line) = self.body_fence {
+            return Err(ParseError::UnclosedBody { line });
+        }
+        let mut body = self.body_buffer.join("\n");
+        if !self.raw_body && self.body_fence == BodyFence::None {
+            body = body.trim().to_string();
+            // `< ./photo.jpg`: the body is the file, sent as it is
+            let body_file = body
+                .strip_prefix('<')
+                .filter(|path| path.starts_with(char::is_whitespace) && !path.contains('\n'))
+                .map(|path| PathBuf::from(path.trim()));
+            if body_file.as_deref() == Some(Path::new("-")) {
+                self.request.body_stdin = true;
+                body.clear();
+            } else if body_file.is_some() {
+                self.request.body_file = body_file;
+                body.clear();
+            }
+        }
+        self.request.body = body.into_bytes();
+
+        Ok(())
+    }
+
+    /// Replaces an internationalized domain name in the URL with its
+    /// punycode form, that is what's sent, keeping the written one apart.
+    fn normalize_host(&mut self, ascii_host: &str) {
+        let url = &self.request.url;
+        let authority_start = url.find("://").map(|i| i + 3).unwrap_or(0);
+        let authority_end = url[authority_start..]
+            .find(&['/', '?'][..])
+            .map(|i| authority_start + i)
+            .unwrap_or_else(|| url.len());
+        let host_start = url[authority_start..authority_end]
+            .rfind('@')
+            .map(|i| authority_start + i + 1)
+            .unwrap_or(authority_start);
+        let host_end = url[host_start..authority_end]
+            .rfind(':')
+            .map(|i| host_start + i)
+            .unwrap_or(authority_end);
+        let host = &url[host_start..host_end];
+        if host.is_ascii() {
+            return;
+        }
+        self.request.unicode_host = Some(host.to_string());
+        self.request.url = format!("{}{}{}", &url[..host_start], ascii_host, &url[host_end..]);
+    }
+
+    /// Tells whether a line is a `??` assertion: in the body, assertions must
+    /// be separated from the payload by a blank line.
+    fn is_assertion(&self, line: &str) -> bool {
+        line.trim_start().starts_with("??") && self.ends_body()
+    }
+
+    /// Tells whether a line is the `>> file` the response is written to,
+    /// following the body as the assertions do.
+    fn is_output_file(&self, line: &str) -> bool {
+        let line = line.trim();
+        line.starts_with(">>") && line != ">>>" && self.ends_body()
+    }
+
+    /// Tells whether the request can end with the line being parsed, that
+    /// is, in the body, when following a blank line.
+    fn ends_body(&self) -> bool {
+        if self.request.url == "" {
+            return false;
+        }
+        if !self.head_done
+            || self.assertions_started
+            || self.output_started
+            || self.body_fence == BodyFence::Closed
+        {
+            return true;
+        }
+        match self.body_buffer.last() {
+            Some(previous) => previous.trim().is_empty(),
+            None => true,
+        }
+    }
+
+    /// Parses `>> path`, or `>>! path` to overwrite the file.
+    fn parse_output_file(&mut self, line: &str) -> Result<(), ParseError> {
+        let text = &line.trim()[2..];
+        let (overwrite, path) = match text.strip_prefix('!') {
+            Some(path) => (true, path.trim()),
+            None => (false, text.trim()),
+        };
+        if path.is_empty() || self.request.output.is_some() {
+            return Err(match path.is_empty() {
+                true => ParseError::Missing {
+                    line: self.line_number,
+                    what: "the path of the output file",
+                },
+                false => ParseError::OutputFiles {
+                    line: self.line_number,
+                },
+            });
+        }
+        self.request.output = Some(request::OutputFile {
+            path: PathBuf::from(path),
+            overwrite,
+        });
+        self.output_started = true;
+        Ok(())
+    }
+
+    fn parse_assertion(&mut self, line: &str) -> Result<(), ParseError> {
+        let text = line.trim_start().trim_start_matches('?');
+        let assertion = assertion::Assertion::parse(text).map_err(|e| self.directive_error(e))?;
+        self.request.assertions.push(assertion);
+        self.assertions_started = true;
+        Ok(())
+    }
+
+    /// The error of the parser of a directive, or of an assertion, in the
+    /// line being parsed.
+    fn directive_error(&self, error: anyhow::Error) -> ParseError {
+        ParseError::Directive {
+            line: self.line_number,
+            error,
+        }
+    }
 
+    /// Parses comment lines in the form `# @key value`; comments that are not
+    /// directives are ignored.
+    fn parse_directive(&mut self, line: &str) -> Result<(), ParseError> {
+        let content = line.trim_start_matches(&['/', '#'][..]).trim();
+        let directive = match content.strip_prefix('@') {
+            Some(directive) => directive,
+            None => return Ok(()),
+        };
+        let mut split = directive.splitn(2, char::is_whitespace);
+        let key = split.next().unwrap_or("");
+        let value = split.next().unwrap_or("").trim();
+        match key {
+            "name" => {
+                if value.is_empty() || value.contains(char::is_whitespace) {
+                    return Err(ParseError::Invalid {
+                        line: self.line_number,
+                        what: "request name",
+                        value: value.to_string(),
+                    });
+                }
+                self.request.name = Some(value.to_string());
+            }
+            "depends-on" => {
+                if value.is_empty() {
+                    return Err(ParseError::Missing {
+                        line: self.line_number,
+                        what: "request name for depends-on",
+                    });
+                }
+                for name in value.split_whitespace() {
+                    if !self.request.depends_on.iter().any(|n| n == name) {
+                        self.request.depends_on.push(name.to_string());
+                    }
+                }
+            }
+            "tag" => {
+                if value.is_empty() {
+                    return Err(ParseError::Missing {
+                        line: self.line_number,
+                        what: "tag",
+                    });
+                }
+                for tag in value.split_whitespace() {
+                    if !self.request.tags.iter().any(|t| t == tag) {
+                        self.request.tags.push(tag.to_string());
+                    }
+                }
+            }
+            "retry" => {
+                let mut parts = value.split_whitespace();
+                let retries = parts.next().unwrap_or("");
+                self.request.retries =
+                    Some(retries.parse().map_err(|_| ParseError::Unexpected {
+                        line: self.line_number,
+                        expected: "a number of retries",
+                        found: retries.to_string(),
+                    })?);
+                for option in parts {
+                    let backoff =
+                        option
+                            .strip_prefix("backoff=")
+                            .ok_or_else(|| ParseError::RetryOption {
+                                line: self.line_number,
+                                option: option.to_string(),
+                            })?;
+                    self.request.retry_delay =
+                        Some(duration::parse(backoff).map_err(|e| self.directive_error(e))?);
+                }
+            }
+            "delay" => {
+                self.request.delay =
+                    Some(duration::parse(value).map_err(|e| self.directive_error(e))?);
+            }
+            "timeout" => {
+                self.request.timeout =
+                    Some(duration::parse(value).map_err(|e| self.directive_error(e))?);
+            }
+            "connect-timeout" => {
+                self.request.connect_timeout =
+                    Some(duration::parse(value).map_err(|e| self.directive_error(e))?);
+            }
+            "local-address" => {
+                self.request.local_address =
+                    Some(value.parse().map_err(|_| ParseError::LocalAddress {
+                        line: self.line_number,
+                        address: value.to_string(),
+                    })?);
+            }
+            "proxy" => {
+                if let Err(e) = reqwest::Url::parse(value) {
+                    return Err(ParseError::Proxy {
+                        line: self.line_number,
+                        proxy: value.to_string(),
+                        reason: e.to_string(),
+                    });
+                }
+                self.request.proxy = Some(value.to_string());
+            }
+            "raw-body" => self.raw_body = true,
+            "body-stdin" => self.request.body_stdin = true,
+            "no-redirect" => self.request.follow_redirects = false,
+            "no-decompress" => self.request.decompress = false,
+            "cache" => self.request.cache = true,
+            "stream" => self.request.stream = true,
+            "expect-continue" => self.request.expect_continue = true,
+            "insecure" => self.request.insecure = true,
+            "if-newer" if value.is_empty() => {
+                return Err(ParseError::Missing {
+                    line: self.line_number,
+                    what: "the path of the file for if-newer",
+                });
+            }
+            "if-newer" => self.request.if_newer = Some(PathBuf::from(value)),
+            "unix-socket" if value.is_empty() => {
+                return Err(ParseError::Missing {
+                    line: self.line_number,
+                    what: "the path of the unix socket",
+                });
+            }
+            "unix-socket" => self.request.unix_socket = Some(PathBuf::from(value)),
+            "resolve" => {
+                let resolve = client::Resolve::parse(value).map_err(|e| self.directive_error(e))?;
+                self.request.resolve.push(resolve);
+            }
+            "client-cert" => {
+                self.request.client_certificate = Some(
+                    client::ClientCertificate::parse(value).map_err(|e| self.directive_error(e))?,
+                );
+            }
+            "explode" => self.explode = true,
+            "no-encode" => self.request.encode_url_parameters = false,
+            "merge-params" => match value {
+                "append" => self.request.replace_url_parameters = false,
+                "replace" => self.request.replace_url_parameters = true,
+                _ => {
+                    return Err(ParseError::Unexpected {
+                        line: self.line_number,
+                        expected: "append or replace for merge-params",
+                        found: value.to_string(),
+                    })
+                }
+            },
+            "scheme" => {
+                if value != "http" && value != "https" {
+                    return Err(ParseError::Invalid {
+                        line: self.line_number,
+                        what: "scheme",
+                        value: value.to_string(),
+                    });
+                }
+                self.request.scheme = value.to_string();
+                self.default_scheme = Some(value.to_string());
+            }
+            "cookie" => self.parse_cookie(value)?,
+            "aws-sigv4" => {
+                let arguments: Vec<&str> = value.split_whitespace().collect();
+                if arguments.len() != 2 {
+                    return Err(ParseError::Unexpected {
+                        line: self.line_number,
+                        expected: "region and service for aws-sigv4",
+                        found: value.to_string(),
+                    });
+                }
+                self.request.aws_sigv4 = Some((arguments[0].to_string(), arguments[1].to_string()));
+            }
+            "oauth2" => {
+                self.request.oauth2 = Some(
+                    oauth2::ClientCredentials::parse(value).map_err(|e| self.directive_error(e))?,
+                );
+            }
+            "basic-auth" => {
+                let mut credentials = value.splitn(2, char::is_whitespace);
+                let user = credentials.next().unwrap_or("");
+                if user.is_empty() {
+                    return Err(ParseError::Missing {
+                        line: self.line_number,
+                        what: "user for basic auth",
+                    });
+                }
+                let password = credentials.next().unwrap_or("").trim();
+                self.request.basic_auth = Some((user.to_string(), password.to_string()));
+            }
+            _ => {}
+        }
         Ok(())
     }
 
-    fn parse_url_parameter(&mut self, line: &str) -> IoResult<()> {
-        let trimmed = line.trim_start();
-        if trimmed == line {
+    fn parse_url_parameter(&mut self, line: &str) -> Result<(), ParseError> {
+        if !HTTPParser::is_indented(line) {
             // this is an header
             return Ok(());
         }
-        let splitted: Vec<&str> = trimmed.splitn(2, '=').collect();
-        if splitted.len() < 2 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("invalid url parameter in {}", line),
-            ));
-        }
-        if splitted[0].len() == 0 || splitted[1].len() == 0 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("invalid url parameter in {}", line),
-            ));
+        let trimmed = line.trim_start_matches(&[' ', '\t'][..]);
+        // `?flag` is sent without a value, `?flag=` with an empty one
+        let (key, value) = match trimmed.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (trimmed.trim_end(), None),
+        };
+        if key.len() < 2 {
+            return Err(ParseError::Invalid {
+                line: self.line_number,
+                what: "URL parameter",
+                value: trimmed.trim_end().to_string(),
+            });
         }
         self.request
             .url_parameters
-            .push((splitted[0][1..].to_string(), splitted[1].to_string()));
+            .push((key[1..].to_string(), value));
+        self.parameter_lines
+            .push((key[1..].to_string(), self.line_number));
         Ok(())
     }
 
-    fn parse_header(&mut self, line: &str) -> IoResult<()> {
-        if line.trim_start() != line {
+    fn parse_header(&mut self, line: &str) -> Result<(), ParseError> {
+        if HTTPParser::is_indented(line) {
             // this is an URL parameter
             return Ok(());
         }
-        let split = line.split(":").collect::<Vec<&str>>();
-        if &split.len() < &2 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("invalid header in {}", line),
-            ));
+        let (key, raw_value) = line.split_once(':').ok_or_else(|| ParseError::Invalid {
+            line: self.line_number,
+            what: "header",
+            value: line.trim_end().to_string(),
+        })?;
+        // the value is everything after the first colon, but the leading
+        // spaces and tabs
+        let value = raw_value.trim_start_matches(&[' ', '\t'][..]);
+        self.header_lines.push((key.to_string(), self.line_number));
+        if key.eq_ignore_ascii_case("cookie") {
+            // merged with the `# @cookie` ones into a single header
+            for cookie in value.split(';').filter(|c| !c.trim().is_empty()) {
+                self.parse_cookie(cookie.trim())?;
+            }
+            return Ok(());
         }
-        let key = split[0];
-        let values = &split[1..split.len()];
-        let raw_value = values
-            .iter()
-            .fold(String::from(""), |acc, x| acc.to_string() + x);
-        let value = raw_value.trim_start();
         self.request
             .headers
             .insert(key.to_string(), value.to_string());
         Ok(())
     }
 
+    /// Parses a `name=value` cookie, checking it only contains the characters
+    /// allowed by RFC 6265, but in the parts with a `{{<name>.response...}}`
+    /// placeholder, which are resolved when the request is executed.
+    fn parse_cookie(&mut self, cookie: &str) -> Result<(), ParseError> {
+        let (name, value) = cookie.split_once('=').unwrap_or((cookie, ""));
+        let chained = |part: &str| part.contains("{{");
+        let invalid_name = name.is_empty()
+            || !chained(name)
+                && name
+                    .chars()
+                    .any(|c| !c.is_ascii_graphic() || "()<>@,;:\\\"/[]?={}".contains(c));
+        let unquoted_value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        let invalid_value = !chained(value)
+            && unquoted_value
+                .chars()
+                .any(|c| !c.is_ascii_graphic() || "\",;\\".contains(c));
+        if invalid_name || invalid_value {
+            return Err(ParseError::Invalid {
+                line: self.line_number,
+                what: match invalid_name {
+                    true => "cookie name",
+                    false => "cookie value",
+                },
+                value: cookie.to_string(),
+            });
+        }
+        self.request
+            .cookies
+            .push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
     fn could_be_headers_or_attr(&mut self, line: &str) -> bool {
         self.request.url != "" && !HTTPParser::is_section_break(line) && !self.head_done
     }
 
+    /// Lines indented with spaces or tabs are URL parameters.
+    fn is_indented(line: &str) -> bool {
+        line.starts_with(' ') || line.starts_with('\t')
+    }
+
+    fn is_comment(line: &str) -> bool {
+        line.starts_with("//") || line.starts_with("#")
+    }
+
     fn is_section_break(line: &str) -> bool {
         line.trim().len() == 0
     }
 
-    fn parse_url(&mut self, line: &str) -> IoResult<()> {
-        let split = line.split(" ").collect::<Vec<&str>>();
-        if &split.len() < &1 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("URL not found in {}", line),
-            ));
-        }
-
-        let mut url_candidate = split[split.len() - 1];
-        // TODO
+    fn parse_url(&mut self, line: &str) -> Result<(), ParseError> {
+        let mut split = line.trim().split(" ").collect::<Vec<&str>>();
+        let last = split[split.len() - 1];
         let protocol_regexp: Regex = Regex::new(r"HTTP/(\d)(\.\d)?($|\n|\r)").unwrap();
 
-        if protocol_regexp.is_match(url_candidate) && &split.len() > &2 {
-            self.request.protocol = url_candidate.to_string();
-            url_candidate = split[split.len() - 2];
-        } else if protocol_regexp.is_match(url_candidate) && &split.len() <= &2 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("invalid URL: {}", line),
-            ));
+        if protocol_regexp.is_match(last) && &split.len() > &2 {
+            let protocol = last.trim_end();
+            if request::parse_http_version(protocol).is_none() {
+                return Err(ParseError::UnsupportedProtocol {
+                    line: self.line_number,
+                    protocol: protocol.to_string(),
+                });
+            }
+            self.request.protocol = protocol.to_string();
+            split.pop();
+        } else if protocol_regexp.is_match(last) && &split.len() <= &2 {
+            return Err(ParseError::Invalid {
+                line: self.line_number,
+                what: "request line",
+                value: line.trim().to_string(),
+            });
+        }
+        if split.len() > 1 && split[0].chars().all(|c| c.is_ascii_alphabetic()) {
+            // the method
+            split.remove(0);
+        }
+        // what's left is the URL, that can contain spaces (encoded later)
+        let url_candidate = split.join(" ");
+        let url_candidate = url_candidate.trim();
+        if url_candidate.is_empty() {
+            return Err(ParseError::Missing {
+                line: self.line_number,
+                what: "the URL",
+            });
         }
 
-        self.request.url = url_candidate.to_string();
+        // the fragment is kept apart, so that parameters are added before it
+        match url_candidate.split_once('#') {
+            Some((url, fragment)) => {
+                self.request.url = url.to_string();
+                self.request.fragment = Some(fragment.to_string());
+            }
+            None => self.request.url = url_candidate.to_string(),
+        }
+        self.url_line = self.line_number;
         Ok(())
     }
 
@@ -185,67 +753,863 @@ impl fmt::Display for HTTPParser {
     }
 }
 
-pub struct FileParser {}
+/// FileParser parses a whole `.http` file, possibly containing many requests
+/// separated by `###`, replacing `{{KEY}}` placeholders with the values of
+/// the `@KEY = value` variables defined in the file or, if not defined there,
+/// of the variables given by the caller (see `with_vars`) and finally of the
+/// environment variables.
+///
+/// Variables defined before the first `###` separator are global, while the
+/// ones defined after it only apply to the request block they are in,
+/// shadowing the global ones with the same name.
+///
+/// Variable values can reference other variables, e.g.
+/// `@base = https://{{HOST}}/api`, regardless of where in the file they are
+/// declared; when the same variable is defined more than once in the same
+/// scope, the last definition wins.
+pub struct FileParser {
+    variables: HashMap<String, String>,
+    // provided by the caller, looked up after the ones defined in the file
+    caller_variables: HashMap<String, String>,
+    // whether the environment is looked up for the variables not found elsewhere
+    use_env: bool,
+    // the `.env` file next to the parsed one, for `{{$dotenv KEY}}`
+    dotenv_path: PathBuf,
+    // its variables, read the first time they're needed
+    dotenv: RefCell<Option<HashMap<String, String>>>,
+    // filled while replacing the variables of the current block
+    block_context: RefCell<BlockContext>,
+    // whether `{{$shell command}}` can run commands
+    allow_shell: bool,
+    // the scheme for the URLs without one, `http` if not set
+    default_scheme: Option<String>,
+    // the protocol of the request lines without one, `HTTP/1.1` if not set
+    default_protocol: Option<String>,
+}
+
+/// A request of a `.http` file, along with where it is in the file, for the
+/// tools working on the file rather than executing it.
+#[derive(Clone)]
+pub struct ParsedRequest {
+    /// the request, with the variables replaced
+    pub request: request::Request,
+    /// the `# @name` of the request or, if missing, the text after the `###`
+    /// separator starting its block
+    pub title: Option<String>,
+    /// the lines of the block, from its separator to the next one
+    pub lines: RangeInclusive<usize>,
+    pub url_line: usize,
+    /// the name of each header line, with its line
+    pub header_lines: Vec<(String, usize)>,
+    /// the key of each URL parameter line, with its line
+    pub parameter_lines: Vec<(String, usize)>,
+    /// the text of the block, before replacing the variables
+    pub raw: String,
+}
+
+/// The requests of a `.http` file. Lines are numbered from 1 and, when the
+/// file has `# @import`s, refer to the content with the imported files merged
+/// in, as in the error messages.
+#[derive(Clone)]
+pub struct ParsedFile {
+    pub requests: Vec<ParsedRequest>,
+}
+
+impl ParsedFile {
+    /// The requests alone, as returned by `FileParser::parse_from_file`.
+    pub fn into_requests(self) -> Vec<request::Request> {
+        self.requests
+            .into_iter()
+            .map(|parsed| parsed.request)
+            .collect()
+    }
+}
+
+/// The variables referenced in a file without a value, each one with the
+/// number of the line where it is first used.
+#[derive(Debug, PartialEq)]
+pub struct MissingVariables(pub Vec<(String, usize)>);
+
+impl fmt::Display for MissingVariables {
+    fn fmt(&self, dest: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.as_slice() {
+            [(key, line)] => write!(
+                dest,
+                "you must provide a value for key {}, used in line {}",
+                key, line
+            ),
+            keys => write!(
+                dest,
+                "you must provide a value for keys {}",
+                keys.iter()
+                    .map(|(key, line)| format!("{} (line {})", key, line))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MissingVariables {}
 
 impl FileParser {
-    pub fn parse_from_file(mut self, file_path: &str) -> Result<Vec<request::Request>> {
-        let raw_contents = fs::read_to_string(file_path)?;
+    pub fn new() -> FileParser {
+        FileParser::with_vars(HashMap::new())
+    }
+
+    /// Creates a parser that replaces the placeholders not defined in the
+    /// file with the given variables, before looking up the environment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// let mut vars = HashMap::new();
+    /// vars.insert("HOST".to_string(), "example.com".to_string());
+    /// let reqs = httpclient::worker::FileParser::with_vars(vars)
+    ///     .use_env(false)
+    ///     .parse_from_str("GET https://{{HOST}}/users")
+    ///     .unwrap();
+    /// assert_eq!(reqs[0].url, "https://example.com/users");
+    /// ```
+    pub fn with_vars(vars: HashMap<String, String>) -> FileParser {
+        FileParser {
+            variables: HashMap::new(),
+            caller_variables: vars,
+            use_env: true,
+            dotenv_path: PathBuf::from(".env"),
+            dotenv: RefCell::new(None),
+            block_context: RefCell::new(BlockContext::default()),
+            allow_shell: false,
+            default_scheme: None,
+            default_protocol: None,
+        }
+    }
+
+    /// Sets whether the environment variables are looked up (the default) or
+    /// only the variables of the file and of the caller are used.
+    pub fn use_env(mut self, use_env: bool) -> FileParser {
+        self.use_env = use_env;
+        self
+    }
+
+    /// Sets whether the `{{$shell command}}` placeholders run their command
+    /// (disabled by default, so that parsing a file can't run anything).
+    pub fn allow_shell(mut self, allow_shell: bool) -> FileParser {
+        self.allow_shell = allow_shell;
+        self
+    }
+
+    /// Sets the scheme of the URLs written without one, like
+    /// `localhost:8080/health`, and of the path-only ones (`http` and
+    /// `https` respectively, if not set); `# @scheme` overrides it.
+    pub fn default_scheme(mut self, scheme: &str) -> FileParser {
+        self.default_scheme = Some(scheme.to_string());
+        self
+    }
+
+    /// Sets the protocol of the request lines without one, like `HTTP/2`
+    /// (`HTTP/1.1` if not set).
+    pub fn default_protocol(mut self, protocol: &str) -> FileParser {
+        self.default_protocol = Some(protocol.to_string());
+        self
+    }
+
+    pub fn parse_from_file(self, file_path: &str) -> Result<Vec<request::Request>> {
+        Ok(self.parse_file(file_path)?.into_requests())
+    }
+
+    /// Parses a `.http` file like `parse_from_file`, keeping track of where
+    /// each request is in the file.
+    pub fn parse_file(mut self, file_path: &str) -> Result<ParsedFile> {
+        self.dotenv_path = Path::new(file_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(".env");
+        let (raw_contents, source) =
+            self.read_with_imports(Path::new(file_path), &mut Vec::new())?;
+
+        self.parse_blocks(&raw_contents, &source)
+    }
+
+    /// Parses the content of a `.http` file, replacing variables like
+    /// `parse_from_file` does (`# @import` directives are not resolved).
+    pub fn parse_from_str(self, raw_contents: &str) -> Result<Vec<request::Request>> {
+        Ok(self.parse_str(raw_contents)?.into_requests())
+    }
+
+    /// Parses the content of a `.http` file like `parse_from_str`, keeping
+    /// track of where each request is in the content.
+    pub fn parse_str(mut self, raw_contents: &str) -> Result<ParsedFile> {
+        let source = FileParser::strip_bom(raw_contents);
+        let raw_contents = FileParser::collect_variables(&source, true, &mut self.variables);
+
+        self.parse_blocks(&raw_contents, &source)
+    }
+
+    /// Replaces the variables in each request block, using the block's own
+    /// variables before the global ones, then parses the requests.
+    ///
+    /// All the variables without a value are reported together, as a
+    /// `MissingVariables` error.
+    ///
+    /// `source` is the content before collecting the variables, for the
+    /// text of the blocks.
+    fn parse_blocks(self, raw_contents: &str, source: &str) -> Result<ParsedFile> {
         let mut content_lines: Vec<String> = Vec::new();
-        for line in raw_contents.split("\n") {
-            content_lines.push(self.replace_env(line)?);
+        let mut missing_variables: Vec<(String, usize)> = Vec::new();
+        let mut contexts: Vec<BlockContext> = Vec::new();
+        for block in FileParser::split_blocks(raw_contents) {
+            let mut block_variables = HashMap::new();
+            let block =
+                FileParser::collect_variables(&block.join("\n"), false, &mut block_variables);
+            let mut missing = Vec::new();
+            // the paths of the request lines are relative to the `@host` variable
+            let base_url =
+                if block_variables.contains_key("host") || self.variables.contains_key("host") {
+                    Some(self.replace_env("{{host}}", &block_variables, &mut missing)?)
+                } else {
+                    None
+                };
+            self.block_context.borrow_mut().base_url = base_url;
+            for line in block.split("\n") {
+                let line = self
+                    .replace_env(line, &block_variables, &mut missing)
+                    .map_err(|error| ParseError::Expansion {
+                        line: content_lines.len() + 1,
+                        error,
+                    })?;
+                content_lines.push(line);
+                for key in missing.drain(..) {
+                    if !missing_variables.iter().any(|(k, _)| k == &key) {
+                        missing_variables.push((key, content_lines.len()));
+                    }
+                }
+            }
+            contexts.push(self.block_context.replace(BlockContext::default()));
+        }
+        if !missing_variables.is_empty() {
+            return Err(MissingVariables(missing_variables).into());
         }
         let content = content_lines.join("\n");
 
-        self.parse_many(&content)
+        self.parse_requests(
+            FileParser::split_blocks(&content),
+            &FileParser::split_blocks(source),
+            &contexts,
+        )
     }
 
-    pub fn parse_many(self, file_content: &str) -> Result<Vec<request::Request>> {
-        let mut requests: Vec<request::Request> = Vec::new();
-        let mut raw_requests: Vec<Vec<String>> = vec![vec![]];
-        for line in file_content.split("\n") {
-            if line.starts_with("###") {
-                raw_requests.push(Vec::new());
+    /// Splits the content in blocks, each one (but the first) starting with
+    /// its `###` separator line.
+    fn split_blocks(content: &str) -> Vec<Vec<&str>> {
+        let mut blocks: Vec<Vec<&str>> = vec![vec![]];
+        let lines: Vec<&str> = content.split("\n").collect();
+        for (line, fenced) in lines.iter().zip(FileParser::fenced_lines(&lines)) {
+            if line.starts_with("###") && !fenced {
+                blocks.push(Vec::new());
+            }
+            blocks.last_mut().unwrap().push(line);
+        }
+        blocks
+    }
+
+    /// Tells, for each line, whether it's part of a body written between
+    /// `<<<` and `>>>` lines, where separators, variable definitions and
+    /// imports are just text.
+    fn fenced_lines(lines: &[&str]) -> Vec<bool> {
+        let mut inside = false;
+        lines
+            .iter()
+            .map(|line| match line.trim() {
+                "<<<" if !inside => {
+                    inside = true;
+                    false
+                }
+                ">>>" if inside => {
+                    inside = false;
+                    false
+                }
+                _ => inside,
+            })
+            .collect()
+    }
+
+    /// Reads a file, replacing every `# @import <path>` line with the content
+    /// of the referenced file (resolved relative to the importing one), so
+    /// that the imported requests come before the following ones.
+    ///
+    /// The merged content is returned along with the same content before
+    /// collecting the global variables.
+    fn read_with_imports(
+        &mut self,
+        file_path: &Path,
+        import_stack: &mut Vec<PathBuf>,
+    ) -> Result<(String, String)> {
+        let canonical_path = fs::canonicalize(file_path)
+            .map_err(|e| anyhow::anyhow!("unable to read {}: {}", file_path.display(), e))?;
+        if let Some(position) = import_stack.iter().position(|p| p == &canonical_path) {
+            let cycle: Vec<String> = import_stack[position..]
+                .iter()
+                .chain(std::iter::once(&canonical_path))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(anyhow::anyhow!(
+                "import cycle detected: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        if import_stack.len() >= MAX_IMPORT_DEPTH {
+            return Err(anyhow::anyhow!(
+                "too many nested imports (more than {}) importing {}",
+                MAX_IMPORT_DEPTH,
+                file_path.display()
+            ));
+        }
+        import_stack.push(canonical_path);
+
+        let raw_contents = fs::read_to_string(file_path)
+            .map_err(|e| anyhow::anyhow!("unable to read {}: {}", file_path.display(), e))?;
+        let source = FileParser::strip_bom(&raw_contents);
+        // the global variables of each file are collected here, as once the
+        // imports are merged the following separators would make them local
+        let raw_contents = FileParser::collect_variables(&source, true, &mut self.variables);
+        let mut content_lines: Vec<String> = Vec::new();
+        let mut source_lines: Vec<String> = Vec::new();
+        let lines: Vec<&str> = raw_contents.split("\n").collect();
+        let fenced_lines = FileParser::fenced_lines(&lines);
+        // whether the block so far has more than blank lines and comments
+        let mut in_request = false;
+        for (number, ((line, fenced), source_line)) in lines
+            .iter()
+            .zip(fenced_lines)
+            .zip(source.split("\n"))
+            .enumerate()
+        {
+            match FileParser::import_path(line).filter(|_| !fenced) {
+                Some(_) if in_request => {
+                    return Err(anyhow::anyhow!(
+                        "# @import in line {} of {} isn't between requests: it goes at the start of the file, or after a ###",
+                        number + 1,
+                        file_path.display()
+                    ))
+                }
+                Some(import_path) => {
+                    let import_path = file_path
+                        .parent()
+                        .unwrap_or_else(|| Path::new(""))
+                        .join(import_path);
+                    let (imported, imported_source) =
+                        self.read_with_imports(&import_path, import_stack)?;
+                    // keeps the imported requests apart from the ones around
+                    content_lines.extend(vec!["###".to_string(), imported, "###".to_string()]);
+                    source_lines.extend(vec![
+                        "###".to_string(),
+                        imported_source,
+                        "###".to_string(),
+                    ]);
+                }
+                None => {
+                    in_request = match line.trim() {
+                        _ if fenced => true,
+                        separator if separator.starts_with("###") => false,
+                        "" => in_request,
+                        text => in_request || !FileParser::is_plain_comment(text),
+                    };
+                    content_lines.push(line.to_string());
+                    source_lines.push(source_line.to_string());
+                }
+            }
+        }
+
+        import_stack.pop();
+        Ok((content_lines.join("\n"), source_lines.join("\n")))
+    }
+
+    /// Stores the `@KEY = value` definitions found in the content (only the
+    /// ones before the first `###` separator when `global` is set), returning
+    /// the content with the collected definitions blanked out as comments.
+    fn collect_variables(
+        content: &str,
+        global: bool,
+        variables: &mut HashMap<String, String>,
+    ) -> String {
+        let re = Regex::new(r"^@(?P<key>\w+)\s*=(?P<value>.*)$").unwrap();
+        let mut content_lines: Vec<&str> = Vec::new();
+        let mut separator_found = false;
+        let lines: Vec<&str> = content.split("\n").collect();
+        for (line, fenced) in lines.iter().zip(FileParser::fenced_lines(&lines)) {
+            separator_found = separator_found || (global && line.starts_with("###") && !fenced);
+            match re.captures(line.trim_end_matches('\r')) {
+                Some(caps) if !separator_found && !fenced => {
+                    variables.insert(caps["key"].to_string(), caps["value"].trim().to_string());
+                    content_lines.push("#");
+                }
+                _ => content_lines.push(line),
             }
-            raw_requests.last_mut().unwrap().push(line.to_string());
         }
-        for raw_request in raw_requests {
+        content_lines.join("\n")
+    }
+
+    /// Whether a trimmed line is a comment, but not a directive such as
+    /// `# @name`.
+    fn is_plain_comment(line: &str) -> bool {
+        (line.starts_with('#') || line.starts_with("//"))
+            && !line
+                .trim_start_matches(&['/', '#'][..])
+                .trim_start()
+                .starts_with('@')
+    }
+
+    fn import_path(line: &str) -> Option<&str> {
+        let content = line.trim_start_matches(&['/', '#'][..]).trim();
+        if content.len() == line.trim().len() {
+            // not a comment
+            return None;
+        }
+        match content.strip_prefix("@import") {
+            Some(path) if path.starts_with(char::is_whitespace) => Some(path.trim()),
+            _ => None,
+        }
+    }
+
+    /// Parses the requests separated by `###`; blocks without a request, such
+    /// as the ones with only comments, are skipped.
+    pub fn parse_many(self, file_content: &str) -> Result<Vec<request::Request>> {
+        let file_content = FileParser::strip_bom(file_content);
+        let blocks = FileParser::split_blocks(&file_content);
+        Ok(self
+            .parse_requests(blocks.clone(), &blocks, &[])?
+            .into_requests())
+    }
+
+    /// Removes the UTF-8 byte order marks some editors put at the start of
+    /// the files, also from the start of the lines, as they end up there
+    /// when files are concatenated.
+    fn strip_bom(content: &str) -> String {
+        content
+            .split('\n')
+            .map(|line| line.strip_prefix('\u{feff}').unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Parses each block into a request, joining the paths with the block's
+    /// base URL, if any, and recording the values generated for it.
+    fn parse_requests(
+        &self,
+        blocks: Vec<Vec<&str>>,
+        raw_blocks: &[Vec<&str>],
+        contexts: &[BlockContext],
+    ) -> Result<ParsedFile> {
+        let mut requests: Vec<ParsedRequest> = Vec::new();
+        let mut first_line = 1;
+        for (index, raw_request) in blocks.into_iter().enumerate() {
+            let lines = first_line..=first_line + raw_request.len() - 1;
+            // `### Title`
+            let separator_title = raw_request
+                .first()
+                .and_then(|line| line.strip_prefix("###"))
+                .map(|title| title.trim())
+                .filter(|title| !title.is_empty())
+                .map(|title| title.to_string());
             let mut w = HTTPParser::new()?;
+            w.first_line = first_line;
+            if let Some(scheme) = &self.default_scheme {
+                w.default_scheme = Some(scheme.to_string());
+                w.request.scheme = scheme.to_string();
+            }
+            if let Some(protocol) = &self.default_protocol {
+                w.request.protocol = protocol.to_string();
+            }
+            let context = contexts.get(index);
+            w.base_url = context.and_then(|c| c.base_url.clone());
+            first_line += raw_request.len();
             w.parse(&raw_request.join("\n"))?;
+            if let Some(context) = context {
+                w.request.generated_values = context.generated_values.clone();
+                w.request.secrets = context.secrets.clone();
+            }
             if w.request.url != "" {
-                requests.push(w.request);
+                requests.push(ParsedRequest {
+                    title: w.request.name.clone().or(separator_title),
+                    lines,
+                    url_line: w.url_line,
+                    header_lines: w.header_lines,
+                    parameter_lines: w.parameter_lines,
+                    raw: raw_blocks
+                        .get(index)
+                        .map(|block| block.join("\n"))
+                        .unwrap_or_default(),
+                    request: w.request,
+                });
             }
         }
+        FileParser::check_dependencies(&requests)?;
 
-        Ok(requests)
+        Ok(ParsedFile { requests })
+    }
+
+    /// Checks that the `# @depends-on` directives name requests of the file,
+    /// without cycles.
+    fn check_dependencies(requests: &[ParsedRequest]) -> Result<()> {
+        let named: HashMap<&str, &request::Request> = requests
+            .iter()
+            .filter_map(|parsed| Some((parsed.request.name.as_deref()?, &parsed.request)))
+            .collect();
+        for parsed in requests {
+            if let Some(name) = parsed
+                .request
+                .depends_on
+                .iter()
+                .find(|name| !named.contains_key(name.as_str()))
+            {
+                return Err(anyhow::anyhow!(
+                    "the request in line {} depends on {}, not a request of the file",
+                    parsed.url_line,
+                    name
+                ));
+            }
+        }
+        // depth first, `path` being the requests being visited
+        fn visit<'a>(
+            name: &'a str,
+            named: &HashMap<&str, &'a request::Request>,
+            path: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> Result<()> {
+            if let Some(position) = path.iter().position(|n| *n == name) {
+                let mut cycle = path[position..].to_vec();
+                cycle.push(name);
+                return Err(anyhow::anyhow!(
+                    "dependency cycle detected: {}",
+                    cycle.join(" -> ")
+                ));
+            }
+            if !done.insert(name) {
+                return Ok(());
+            }
+            path.push(name);
+            for dependency in &named[name].depends_on {
+                visit(dependency, named, path, done)?;
+            }
+            path.pop();
+            Ok(())
+        }
+        let mut done = HashSet::new();
+        for parsed in requests {
+            if let Some(name) = &parsed.request.name {
+                visit(name, &named, &mut Vec::new(), &mut done)?;
+            }
+        }
+        Ok(())
     }
 
-    fn replace_env(&mut self, candidate_str: &str) -> IoResult<String> {
-        // TODO refactor here
-        let re = Regex::new(r"\{\{(?P<key>\w+)\}\}").unwrap();
+    /// Replaces `{{KEY}}` placeholders with the value of the corresponding
+    /// variable; `\{{` and `\}}` are turned into literal braces in the same
+    /// pass, so `\{{KEY}}` ends up as `{{KEY}}`.
+    ///
+    /// Placeholders without a value are left as they are, and their keys
+    /// added to `missing`.
+    fn replace_env(
+        &self,
+        candidate_str: &str,
+        block_variables: &HashMap<String, String>,
+        missing: &mut Vec<String>,
+    ) -> Result<String, ExpansionError> {
+        self.expand(candidate_str, block_variables, &mut Vec::new(), missing)
+    }
 
-        let mut result: IoResult<String> = Ok(candidate_str.to_string());
-        for m in re.find_iter(candidate_str) {
-            let key = candidate_str.get(m.start() + 2..m.end() - 2).unwrap();
-            result = self.replace_single_env_var(&result?, key);
+    /// Expands the placeholders in `candidate_str`; `expanding` holds the
+    /// variables whose values are being expanded, to detect cycles.
+    ///
+    /// The arguments of a `{{$function ...}}` can contain placeholders too,
+    /// expanded before calling the function.
+    fn expand(
+        &self,
+        candidate_str: &str,
+        block_variables: &HashMap<String, String>,
+        expanding: &mut Vec<String>,
+        missing: &mut Vec<String>,
+    ) -> Result<String, ExpansionError> {
+        let mut result = String::new();
+        let mut rest = candidate_str;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("\\{{") || rest.starts_with("\\}}") {
+                // an escaped brace pair: drop the backslash
+                result.push_str(&rest[1..3]);
+                rest = &rest[3..];
+            } else if let Some(call) = rest.strip_prefix("{{$") {
+                let function_end = call
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(call.len());
+                let function = &call[..function_end];
+                if function.is_empty() {
+                    result.push(c);
+                    rest = &rest[1..];
+                    continue;
+                }
+                let arguments_end =
+                    FileParser::closing_braces(&call[function_end..]).ok_or_else(|| {
+                        ExpansionError::UnclosedCall {
+                            function: function.to_string(),
+                        }
+                    })?;
+                let arguments = self.expand(
+                    &call[function_end..function_end + arguments_end],
+                    block_variables,
+                    expanding,
+                    missing,
+                )?;
+                result.push_str(&self.call_function(function, arguments.trim(), missing)?);
+                rest = &call[function_end + arguments_end + 2..];
+            } else if let Some(key) = FileParser::variable_key(rest) {
+                result.push_str(&self.variable_value(key, block_variables, expanding, missing)?);
+                rest = &rest[key.len() + 4..];
+            } else {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
         }
 
-        result
+        Ok(result)
     }
 
-    fn replace_single_env_var(&mut self, candidate_str: &str, key: &str) -> IoResult<String> {
-        if env::var(key).is_ok() {
-            return Ok(self.rpl(candidate_str, key, &env::var(key).unwrap()));
+    /// The key of the `{{KEY}}` placeholder `candidate_str` starts with, if any.
+    fn variable_key(candidate_str: &str) -> Option<&str> {
+        let key = candidate_str.strip_prefix("{{")?;
+        let key_end = key.find("}}")?;
+        let key = &key[..key_end];
+        if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            Some(key)
         } else {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("you must provide a value for key {}", key),
-            ));
+            None
+        }
+    }
+
+    /// The position of the `}}` closing a function call, skipping the nested
+    /// placeholders and the escaped braces.
+    fn closing_braces(arguments: &str) -> Option<usize> {
+        let mut depth = 0;
+        let mut position = 0;
+        while position < arguments.len() {
+            let rest = &arguments[position..];
+            if rest.starts_with("\\{{") || rest.starts_with("\\}}") {
+                position += 3;
+            } else if rest.starts_with("{{") {
+                depth += 1;
+                position += 2;
+            } else if rest.starts_with("}}") {
+                if depth == 0 {
+                    return Some(position);
+                }
+                depth -= 1;
+                position += 2;
+            } else {
+                position += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        None
+    }
+
+    /// Evaluates a `{{$function arguments}}` placeholder.
+    fn call_function(
+        &self,
+        function: &str,
+        arguments: &str,
+        missing: &mut Vec<String>,
+    ) -> Result<String, ExpansionError> {
+        match function {
+            // always from the environment, whatever is defined in the file
+            "processEnv" => {
+                if arguments.is_empty() || arguments.contains(char::is_whitespace) {
+                    return Err(ExpansionError::Arguments {
+                        function: function.to_string(),
+                        expected: "a variable name".to_string(),
+                        found: arguments.to_string(),
+                    });
+                }
+                Ok(env::var(arguments).unwrap_or_else(|_| {
+                    missing.push(arguments.to_string());
+                    format!("{{{{$processEnv {}}}}}", arguments)
+                }))
+            }
+            // only from the `.env` file next to the parsed one
+            "dotenv" => {
+                if arguments.is_empty() || arguments.contains(char::is_whitespace) {
+                    return Err(ExpansionError::Arguments {
+                        function: function.to_string(),
+                        expected: "a variable name".to_string(),
+                        found: arguments.to_string(),
+                    });
+                }
+                if self.dotenv.borrow().is_none() {
+                    let content = match fs::read_to_string(&self.dotenv_path) {
+                        Ok(content) => content,
+                        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                        Err(e) => {
+                            return Err(ExpansionError::Dotenv {
+                                path: self.dotenv_path.display().to_string(),
+                                error: e,
+                            })
+                        }
+                    };
+                    *self.dotenv.borrow_mut() = Some(dotenv::parse(&content));
+                }
+                let value = self
+                    .dotenv
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .get(arguments)
+                    .cloned();
+                Ok(value.unwrap_or_else(|| {
+                    missing.push(format!("{} in {}", arguments, self.dotenv_path.display()));
+                    format!("{{{{$dotenv {}}}}}", arguments)
+                }))
+            }
+            // a new value for each occurrence
+            "randomString" | "randomHex" => {
+                let length = match arguments.parse::<usize>() {
+                    Ok(length) if (1..=MAX_RANDOM_LENGTH).contains(&length) => length,
+                    _ => {
+                        return Err(ExpansionError::Arguments {
+                            function: function.to_string(),
+                            expected: format!("a length between 1 and {}", MAX_RANDOM_LENGTH),
+                            found: arguments.to_string(),
+                        })
+                    }
+                };
+                let alphabet: &[u8] = if function == "randomHex" {
+                    b"0123456789abcdef"
+                } else {
+                    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"
+                };
+                let mut rng = rand::thread_rng();
+                let value: String = (0..length)
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())] as char)
+                    .collect();
+                self.block_context.borrow_mut().generated_values.push((
+                    format!("{{{{${} {}}}}}", function, arguments),
+                    value.to_string(),
+                ));
+                Ok(value)
+            }
+            "shell" => self.run_shell(arguments),
+            "keyring" => {
+                let secret = secrets::get(arguments).map_err(ExpansionError::Keyring)?;
+                self.block_context
+                    .borrow_mut()
+                    .secrets
+                    .push(secret.to_string());
+                Ok(secret)
+            }
+            "base64" => Ok(base64::encode(arguments)),
+            "urlencode" => Ok(url_encode(arguments)),
+            _ => Err(ExpansionError::UnknownFunction {
+                function: function.to_string(),
+            }),
+        }
+    }
+
+    /// Runs the command of a `{{$shell command}}` placeholder, returning its
+    /// output without the trailing whitespace.
+    fn run_shell(&self, command: &str) -> Result<String, ExpansionError> {
+        if !self.allow_shell {
+            return Err(ExpansionError::ShellNotAllowed {
+                command: command.to_string(),
+            });
+        }
+        if command.is_empty() {
+            return Err(ExpansionError::MissingCommand);
+        }
+        let output = if cfg!(windows) {
+            Command::new("cmd").args(["/C", command]).output()
+        } else {
+            Command::new("sh").args(["-c", command]).output()
+        }
+        .map_err(|error| ExpansionError::Command {
+            command: command.to_string(),
+            error,
+        })?;
+        if !output.status.success() {
+            return Err(ExpansionError::CommandFailed {
+                command: command.to_string(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string())
+    }
+
+    fn variable_value(
+        &self,
+        key: &str,
+        block_variables: &HashMap<String, String>,
+        expanding: &mut Vec<String>,
+        missing: &mut Vec<String>,
+    ) -> Result<String, ExpansionError> {
+        let value = match block_variables.get(key).or_else(|| self.variables.get(key)) {
+            Some(value) => value,
+            None => {
+                let value = match self.caller_variables.get(key) {
+                    Some(value) => Some(value.to_string()),
+                    None if self.use_env => env::var(key).ok(),
+                    None => None,
+                };
+                return Ok(value.unwrap_or_else(|| {
+                    missing.push(key.to_string());
+                    format!("{{{{{}}}}}", key)
+                }));
+            }
         };
+        if let Some(position) = expanding.iter().position(|k| k == key) {
+            let mut cycle = expanding[position..].to_vec();
+            cycle.push(key.to_string());
+            return Err(ExpansionError::Cycle(cycle));
+        }
+        if expanding.len() >= MAX_VARIABLE_DEPTH {
+            return Err(ExpansionError::TooDeep {
+                max: MAX_VARIABLE_DEPTH,
+                variable: expanding[0].to_string(),
+            });
+        }
+        expanding.push(key.to_string());
+        let expanded = self.expand(value, block_variables, expanding, missing)?;
+        expanding.pop();
+        Ok(expanded)
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters, so that the
+/// text can be used in any part of a URL.
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
     }
+    encoded
+}
+
+/// A random number, from the thread-local generator of `rand`, seeded by the
+/// operating system.
+pub(crate) fn random_u64() -> u64 {
+    rand::random()
+}
 
-    fn rpl(&self, candidate_str: &str, key: &str, subs: &str) -> String {
-        let key_subs: String = "{{".to_string() + key + "}}";
-        candidate_str.replacen(&key_subs, subs, 1)
+impl Default for FileParser {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -284,7 +1648,7 @@ mod tests {
         &hrp.parse(contents).unwrap();
         assert_eq!(&hrp.request.method, "POST");
         assert_eq!(&hrp.request.url, "https://it.wikipedia.org");
-        assert_eq!(&hrp.request.body, "{\"a\":1}");
+        assert_eq!(hrp.request.body, b"{\"a\":1}");
     }
 
     #[test]
@@ -310,29 +1674,381 @@ mod tests {
         let contents = "POST https://it.wikipedia.org\n#comment\n\nthis is\nthe body";
         let mut hrp = HTTPParser::new().unwrap();
         &hrp.parse(contents).unwrap();
-        assert_eq!(&hrp.request.body, "this is\nthe body");
+        assert_eq!(hrp.request.body, b"this is\nthe body");
     }
 
     #[test]
-    fn url_parameters() {
-        let input_text = "  ?foo=bar";
-        let mut worker = HTTPParser::new().unwrap();
-        worker
+    fn raw_body() {
+        let body = "  indented: true\n# not a comment\n\n";
+        let contents = format!("POST https://it.wikipedia.org\n# @raw-body\n\n{}", body);
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(&contents).unwrap();
+        assert_eq!(hrp.request.body, body.as_bytes());
+
+        let contents = format!(
+            "# @raw-body\nPOST https://it.wikipedia.org\n\n{}###\n",
+            body
+        );
+        let result = FileParser::new().parse_many(&contents).unwrap();
+        assert_eq!(result[0].body, b"  indented: true\n# not a comment\n");
+    }
+
+    #[test]
+    fn assertions() {
+        let contents = "POST https://example.com\n\n{\"a\": 1}\n??not an assertion\n\n?? status == 201\n# a comment\n  ?? status in 200..300\n";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(hrp.request.body, b"{\"a\": 1}\n??not an assertion");
+        assert_eq!(hrp.request.assertions.len(), 2);
+        assert_eq!(hrp.request.assertions[1].to_string(), "status in 200..300");
+
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse("GET https://example.com\n?? status == 200")
+            .unwrap();
+        assert_eq!(hrp.request.assertions.len(), 1);
+
+        let contents = "GET https://example.com\n\n?? status == 200\nmore body";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unexpected line after assertions in line 4"
+        );
+        let contents = "GET https://example.com\n?? status is 200";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(error.to_string(), "unknown assertion operator is in line 2");
+    }
+
+    #[test]
+    fn fenced_body() {
+        let contents = "@title = Notes
+POST https://example.com/docs
+Content-Type: text/markdown
+
+<<<
+# {{title}}
+@not = a variable
+
+### Heading
+  indented text
+>>>
+
+?? status == 201
+
+###
+GET https://example.com/docs
+";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            result[0].body,
+            b"# Notes\n@not = a variable\n\n### Heading\n  indented text"
+        );
+        assert_eq!(result[0].assertions.len(), 1);
+        assert_eq!(&result[1].url, "https://example.com/docs");
+
+        let contents = "POST https://example.com\n\n<<<\n### body\n";
+        let error = FileParser::new().parse_from_str(contents).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "missing >>> closing the body opened in line 3"
+        );
+        let contents = "POST https://example.com\n\n<<<\nbody\n>>>\nmore body";
+        let error = FileParser::new().parse_from_str(contents).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unexpected line after the body in line 6"
+        );
+    }
+
+    #[test]
+    fn cookies() {
+        let contents = "GET https://example.com
+# @cookie session=abc
+Cookie: a=1; b=\"two\"
+# @cookie c=3";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.cookies,
+            vec![
+                ("session".to_string(), "abc".to_string()),
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "\"two\"".to_string()),
+                ("c".to_string(), "3".to_string()),
+            ]
+        );
+        assert!(!hrp.request.headers.contains_key("Cookie"));
+
+        let contents = "###\nGET https://example.com\n# @cookie a=1;2";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(error.to_string(), "invalid cookie value a=1;2 in line 3");
+    }
+
+    #[test]
+    fn basic_auth() {
+        let contents = "GET https://example.com\n# @basic-auth user pass word";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.basic_auth,
+            Some(("user".to_string(), "pass word".to_string()))
+        );
+        assert!(!hrp.to_string().contains("pass word"));
+    }
+
+    #[test]
+    fn aws_sigv4() {
+        let contents = "GET https://s3.amazonaws.com/bucket\n# @aws-sigv4 eu-west-1 s3";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.aws_sigv4,
+            Some(("eu-west-1".to_string(), "s3".to_string()))
+        );
+        let mut hrp = HTTPParser::new().unwrap();
+        assert!(hrp
+            .parse("GET https://s3.amazonaws.com\n# @aws-sigv4 s3")
+            .is_err());
+    }
+
+    #[test]
+    fn oauth2() {
+        let contents =
+            "# @oauth2 token_url=https://example.com/token client_id=id client_secret=s scope=read
+GET https://example.com";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        let credentials = hrp.request.oauth2.unwrap();
+        assert_eq!(credentials.token_url, "https://example.com/token");
+        assert_eq!(credentials.scope, Some("read".to_string()));
+
+        let contents = "GET https://example.com\n# @oauth2 client_id=id";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "missing oauth2 argument token_url in line 2"
+        );
+    }
+
+    #[test]
+    fn protocol() {
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse("GET https://example.com HTTP/2\r\n").unwrap();
+        assert_eq!(&hrp.request.protocol, "HTTP/2");
+        let mut hrp = HTTPParser::new().unwrap();
+        assert_eq!(
+            hrp.parse("GET https://example.com HTTP/3")
+                .unwrap_err()
+                .to_string(),
+            "unsupported protocol HTTP/3 in line 1"
+        );
+        assert!(matches!(
+            hrp.parse("GET https://example.com HTTP/0.9"),
+            Err(ParseError::UnsupportedProtocol { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn url_parameters() {
+        let input_text = "  ?foo=bar";
+        let mut worker = HTTPParser::new().unwrap();
+        worker
             .parse_url_parameter(input_text)
             .expect("should be able to parse");
         assert_eq!(worker.request.url_parameters.len(), 1);
         assert_eq!(worker.request.url_parameters[0].0, "foo");
-        assert_eq!(worker.request.url_parameters[0].1, "bar");
+        assert_eq!(worker.request.url_parameters[0].1, Some("bar".to_string()));
+    }
+
+    #[test]
+    fn origin_form() {
+        let contents = "# @scheme http\nGET /api/users HTTP/1.1\nHost: api.example.com\n";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(&hrp.request.url, "/api/users");
+        assert_eq!(&hrp.request.scheme, "http");
+        assert_eq!(hrp.request.host_header(), Some("api.example.com"));
+
+        let contents = "###\n\nGET /api/users HTTP/1.1\nAccept: */*";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the URL /api/users in line 3 is a path: add a Host header to the request \
+             or define the @host variable"
+        );
+        let contents = "# @scheme ftp\nGET /\nHost: example.com";
+        let error = FileParser::new().parse_many(contents).err().unwrap();
+        assert_eq!(error.to_string(), "invalid scheme ftp in line 1");
+    }
+
+    #[test]
+    fn host_variable() {
+        env::set_var("HTTPCLIENT_TEST_STAGE", "staging");
+        let contents = "@host = https://{{HTTPCLIENT_TEST_STAGE}}.example.com/
+GET /users/42
+
+###
+GET https://other.example.com/users
+
+###
+@host = http://10.0.0.1
+GET /users
+Host: api.example.com
+";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(&result[0].url, "https://staging.example.com/users/42");
+        assert_eq!(&result[1].url, "https://other.example.com/users");
+        assert_eq!(&result[2].url, "http://10.0.0.1/users");
+    }
+
+    #[test]
+    fn indented_comments() {
+        let contents = "GET https://example.com/items
+    ?page=1
+    # page size
+    &size=20
+  // @name items
+Accept: application/json
+    # a comment between headers
+X-Trace: on
+
+{
+  # not a comment
+}";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.url_parameters,
+            vec![
+                ("page".to_string(), Some("1".to_string())),
+                ("size".to_string(), Some("20".to_string())),
+            ]
+        );
+        assert_eq!(hrp.request.name, Some("items".to_string()));
+        assert_eq!(hrp.request.headers.len(), 2);
+        assert_eq!(hrp.request.body, b"{\n  # not a comment\n}");
+    }
+
+    #[test]
+    fn empty_blocks() {
+        let contents = "###
+GET https://example.com/1
+
+### only comments
+# nothing to see here
+// @name ignored
+
+###
+POST https://example.com/2
+###
+";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(&result[0].url, "https://example.com/1");
+        assert_eq!(&result[1].url, "https://example.com/2");
+        assert_eq!(result[1].name, None);
+        assert!(FileParser::new()
+            .parse_from_str("@a = 1\n###\n# just a comment\n###\n")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn tab_indentation() {
+        let contents = "GET https://example.com/items\n\t?page=2\n\t&limit=10\nX-Key:\tvalue\twith tab\nX-Url: https://example.com:8080";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.url_parameters,
+            vec![
+                ("page".to_string(), Some("2".to_string())),
+                ("limit".to_string(), Some("10".to_string())),
+            ]
+        );
+        assert_eq!(hrp.request.headers["X-Key"], "value\twith tab");
+        assert_eq!(hrp.request.headers["X-Url"], "https://example.com:8080");
+    }
+
+    #[test]
+    fn valueless_url_parameters() {
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse("GET https://example.com/debug\n  ?trace\n  &flag=\n  &level=2")
+            .unwrap();
+        assert_eq!(
+            hrp.request.url_parameters,
+            vec![
+                ("trace".to_string(), None),
+                ("flag".to_string(), Some("".to_string())),
+                ("level".to_string(), Some("2".to_string())),
+            ]
+        );
+        let mut hrp = HTTPParser::new().unwrap();
+        assert!(hrp.parse("GET https://example.com\n  ?=1").is_err());
+    }
+
+    #[test]
+    fn repeated_url_parameters() {
+        let contents = "GET https://example.com/items\n  ?id=1\n  &tag=a\n  &id=2";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        let keys: Vec<&str> = hrp
+            .request
+            .url_parameters
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect();
+        assert_eq!(keys, vec!["id", "tag", "id"]);
+
+        let contents = "GET https://example.com/items\n  ?id=1,2,3\n  &trace\n# @explode";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(
+            hrp.request.url_parameters,
+            vec![
+                ("id".to_string(), Some("1".to_string())),
+                ("id".to_string(), Some("2".to_string())),
+                ("id".to_string(), Some("3".to_string())),
+                ("trace".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn url_fragment() {
+        let contents = "GET https://example.com/page#section HTTP/1.1\n  ?lang=en";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(&hrp.request.url, "https://example.com/page");
+        assert_eq!(hrp.request.fragment, Some("section".to_string()));
+        assert_eq!(&hrp.request.protocol, "HTTP/1.1");
+        assert!(hrp
+            .to_string()
+            .starts_with("GET https://example.com/page#section HTTP/1.1\n"));
+    }
+
+    #[test]
+    fn url_with_spaces() {
+        let contents =
+            "GET https://example.com/search?q=hello world HTTP/1.1\n  &encoded=%20\n# @no-encode";
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse(contents).unwrap();
+        assert_eq!(&hrp.request.url, "https://example.com/search?q=hello world");
+        assert_eq!(&hrp.request.protocol, "HTTP/1.1");
+        assert!(!hrp.request.encode_url_parameters);
+        let mut hrp = HTTPParser::new().unwrap();
+        hrp.parse("https://example.com/a b").unwrap();
+        assert_eq!(&hrp.request.method, "GET");
+        assert_eq!(&hrp.request.url, "https://example.com/a b");
     }
 
     #[test]
     fn multi_requests() {
         let contents = "https://it.wikipedia.org\n###\nPOST https://en.wikipedia.org";
-        let hrp = FileParser {};
+        let hrp = FileParser::new();
         let result = &hrp.parse_many(contents).unwrap();
         assert_eq!(&result[0].url, "https://it.wikipedia.org");
         assert_eq!(&result[0].method, "GET");
-        assert_eq!(&result[0].body, "");
+        assert_eq!(result[0].body, b"");
         assert_eq!(&result[1].url, "https://en.wikipedia.org");
         assert_eq!(&result[1].method, "POST");
     }
@@ -355,7 +2071,7 @@ Authorization: Basic none
 
 ### Test
 GET https://it.wikipedia.org/something";
-        let hrp = FileParser {};
+        let hrp = FileParser::new();
         let result = &hrp.parse_many(contents).unwrap();
         assert_eq!(&result[0].url, "https://it.wikipedia.org");
         assert_eq!(&result[0].method, "GET");
@@ -364,10 +2080,10 @@ GET https://it.wikipedia.org/something";
             &result[0].headers.get("Authorization"),
             &Some(&"Basic none".to_string())
         );
-        assert_eq!(&result[0].body, "");
+        assert_eq!(result[0].body, b"");
         assert_eq!(&result[1].url, "https://it.wikipedia.org/something");
         assert_eq!(&result[1].method, "POST");
-        assert_eq!(&result[1].body, "payload=my_payload");
+        assert_eq!(result[1].body, b"payload=my_payload");
         assert_eq!(&result[2].url, "https://it.wikipedia.org/something");
         assert_eq!(&result[2].method, "DELETE");
     }
@@ -384,11 +2100,1094 @@ Content-Type: application/x-www-form-urlencoded
 
 payload=my_payload
 ";
-        let hrp = FileParser {};
+        let hrp = FileParser::new();
         let result = &hrp.parse_many(contents).unwrap();
         assert_eq!(result.len(), 1);
         assert_eq!(&result[0].url, "https://it.wikipedia.org/something");
         assert_eq!(&result[0].method, "POST");
-        assert_eq!(&result[0].body, "payload=my_payload");
+        assert_eq!(result[0].body, b"payload=my_payload");
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let result = FileParser::new()
+            .parse_from_file("testdata/bom.http")
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(&result[0].method, "GET");
+        assert_eq!(&result[0].url, "https://example.com/1");
+        assert_eq!(&result[1].method, "POST");
+
+        let result = FileParser::new()
+            .parse_many("\u{feff}DELETE https://example.com")
+            .unwrap();
+        assert_eq!(&result[0].method, "DELETE");
+    }
+
+    #[test]
+    fn imports() {
+        let dir = env::temp_dir().join(format!("httpclient-imports-{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(
+            dir.join("shared/auth.http"),
+            "POST https://example.com/login",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("shared/common.http"),
+            "# @import ./auth.http\nGET https://example.com/common",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.http"),
+            "# @import shared/common.http\nGET https://example.com/main",
+        )
+        .unwrap();
+        fs::write(dir.join("cycle_a.http"), "# @import cycle_b.http").unwrap();
+        fs::write(dir.join("cycle_b.http"), "# @import cycle_a.http").unwrap();
+
+        let result = FileParser::new()
+            .parse_from_file(dir.join("main.http").to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(&result[0].url, "https://example.com/login");
+        assert_eq!(&result[1].url, "https://example.com/common");
+        assert_eq!(&result[2].url, "https://example.com/main");
+
+        let error = FileParser::new()
+            .parse_from_file(dir.join("cycle_a.http").to_str().unwrap())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("import cycle detected:"));
+        assert!(error.contains("cycle_b.http"));
+
+        // between requests only, kept apart from them
+        fs::write(
+            dir.join("between.http"),
+            "GET https://example.com/first\n###\n# shared\n\n# @import shared/auth.http\n# @name last\nGET https://example.com/last",
+        )
+        .unwrap();
+        let result = FileParser::new()
+            .parse_from_file(dir.join("between.http").to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(&result[1].url, "https://example.com/login");
+        assert_eq!(result[2].name.as_deref(), Some("last"));
+        fs::write(
+            dir.join("inside.http"),
+            "# @name first\n# @import shared/auth.http\nGET https://example.com/first",
+        )
+        .unwrap();
+        let error = FileParser::new()
+            .parse_from_file(dir.join("inside.http").to_str().unwrap())
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "# @import in line 2 of {} isn't between requests: it goes at the start of the file, or after a ###",
+                dir.join("inside.http").display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn escaped_braces() {
+        env::set_var("HTTPCLIENT_TEST_ESCAPE", "value");
+        let hrp = FileParser::new();
+        let no_variables = HashMap::new();
+        let mut missing = Vec::new();
+        assert_eq!(
+            hrp.replace_env(
+                r"\{{name}} {{HTTPCLIENT_TEST_ESCAPE}} {{ .Values.x \}}",
+                &no_variables,
+                &mut missing
+            )
+            .unwrap(),
+            "{{name}} value {{ .Values.x }}"
+        );
+        assert_eq!(
+            hrp.replace_env(r"\{{HTTPCLIENT_TEST_ESCAPE}}", &no_variables, &mut missing)
+                .unwrap(),
+            "{{HTTPCLIENT_TEST_ESCAPE}}"
+        );
+        assert!(missing.is_empty());
+        hrp.replace_env("{{HTTPCLIENT_TEST_UNDEFINED}}", &no_variables, &mut missing)
+            .unwrap();
+        assert_eq!(missing, vec!["HTTPCLIENT_TEST_UNDEFINED".to_string()]);
+    }
+
+    #[test]
+    fn caller_variables() {
+        env::set_var("HTTPCLIENT_TEST_CALLER_ENV", "env");
+        let mut vars = HashMap::new();
+        vars.insert("token".to_string(), "caller".to_string());
+        vars.insert(
+            "HTTPCLIENT_TEST_CALLER_ENV".to_string(),
+            "caller".to_string(),
+        );
+        let contents = "@token = file
+GET https://example.com/{{token}}/{{HTTPCLIENT_TEST_CALLER_ENV}}";
+        let result = FileParser::with_vars(vars.clone())
+            .parse_from_str(contents)
+            .unwrap();
+        assert_eq!(&result[0].url, "https://example.com/file/caller");
+
+        let contents = "GET https://example.com/{{token}}/{{HTTPCLIENT_TEST_CALLER_ENV}}";
+        vars.remove("HTTPCLIENT_TEST_CALLER_ENV");
+        let result = FileParser::with_vars(vars.clone())
+            .parse_from_str(contents)
+            .unwrap();
+        assert_eq!(&result[0].url, "https://example.com/caller/env");
+        let error = FileParser::with_vars(vars)
+            .use_env(false)
+            .parse_from_str(contents)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "you must provide a value for key HTTPCLIENT_TEST_CALLER_ENV, used in line 1"
+        );
+    }
+
+    #[test]
+    fn process_env_function() {
+        env::set_var("HTTPCLIENT_TEST_PROCESS_ENV", "from-env");
+        let contents = "@HTTPCLIENT_TEST_PROCESS_ENV = from-file
+GET https://example.com/{{HTTPCLIENT_TEST_PROCESS_ENV}}
+X-Token: {{$processEnv HTTPCLIENT_TEST_PROCESS_ENV}}
+
+{\"token\": \"{{$processEnv HTTPCLIENT_TEST_PROCESS_ENV}}\"}";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(&result[0].url, "https://example.com/from-file");
+        assert_eq!(result[0].headers["X-Token"], "from-env");
+        assert_eq!(result[0].body, b"{\"token\": \"from-env\"}");
+
+        let error = FileParser::new()
+            .parse_from_str(
+                "GET https://example.com\nX-Token: {{$processEnv HTTPCLIENT_TEST_UNSET}}",
+            )
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "you must provide a value for key HTTPCLIENT_TEST_UNSET, used in line 2"
+        );
+        let error = FileParser::new()
+            .parse_from_str("\nGET https://example.com/{{$unknown x}}")
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "unknown function $unknown in line 2");
+    }
+
+    #[test]
+    fn dotenv_function() {
+        let dir = env::temp_dir().join(format!("httpclient-dotenv-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(".env"),
+            "# local secrets\nAPI_KEY=\"s3cr#t\" # the key\nHTTPCLIENT_TEST_DOTENV=from-dotenv\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("api.http"),
+            "GET https://example.com/{{$dotenv HTTPCLIENT_TEST_DOTENV}}\nX-Api-Key: {{$dotenv API_KEY}}",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("missing.http"),
+            "GET https://example.com\nX-Api-Key: {{$dotenv OTHER_KEY}}",
+        )
+        .unwrap();
+        env::set_var("HTTPCLIENT_TEST_DOTENV", "from-env");
+
+        let result = FileParser::new()
+            .parse_from_file(dir.join("api.http").to_str().unwrap())
+            .unwrap();
+        assert_eq!(&result[0].url, "https://example.com/from-dotenv");
+        assert_eq!(result[0].headers["X-Api-Key"], "s3cr#t");
+        let error = FileParser::new()
+            .parse_from_file(dir.join("missing.http").to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "you must provide a value for key OTHER_KEY in {}, used in line 2",
+                dir.join(".env").display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn random_functions() {
+        let result = FileParser::new()
+            .parse_from_str(
+                "POST https://example.com/users/{{$randomString 12}}\nX-Id: {{$randomHex 16}}\n\n{{$randomHex 16}}",
+            )
+            .unwrap();
+        let name = result[0]
+            .url
+            .strip_prefix("https://example.com/users/")
+            .unwrap();
+        assert_eq!(name.len(), 12);
+        assert!(name.chars().all(|c| c.is_ascii_alphanumeric()));
+        let id = &result[0].headers["X-Id"];
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        // each occurrence has its own value
+        assert_ne!(id.as_bytes(), &result[0].body[..]);
+        assert_eq!(
+            result[0].generated_values,
+            vec![
+                ("{{$randomString 12}}".to_string(), name.to_string()),
+                ("{{$randomHex 16}}".to_string(), id.to_string()),
+                (
+                    "{{$randomHex 16}}".to_string(),
+                    String::from_utf8(result[0].body.clone()).unwrap()
+                ),
+            ]
+        );
+
+        for (content, error) in &[
+            ("GET https://example.com/{{$randomHex}}", "found \"\""),
+            ("GET https://example.com/{{$randomHex 0}}", "found \"0\""),
+            (
+                "GET https://example.com/{{$randomString 1025}}",
+                "found \"1025\"",
+            ),
+            (
+                "GET https://example.com/{{$randomString ten}}",
+                "found \"ten\"",
+            ),
+        ] {
+            let function = if content.contains("Hex") {
+                "$randomHex"
+            } else {
+                "$randomString"
+            };
+            assert_eq!(
+                FileParser::new()
+                    .parse_from_str(content)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!(
+                    "expected a length between 1 and 1024 for {}, {} in line 1",
+                    function, error
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn encoding_functions() {
+        let mut vars = HashMap::new();
+        vars.insert("USER".to_string(), "jürgen".to_string());
+        vars.insert("PASS".to_string(), "p@ss".to_string());
+        let result = FileParser::with_vars(vars)
+            .use_env(false)
+            .parse_from_str(
+                "@callback = https://app.example.com/done?to={{USER}}
+GET https://example.com/login?redirect_uri={{$urlencode {{callback}}}}
+Authorization: Basic {{$base64 {{USER}}:{{PASS}}}}
+X-Literal: {{$base64 \\{{x\\}}}}
+X-Unicode: {{$urlencode café au lait}}",
+            )
+            .unwrap();
+        assert_eq!(
+            result[0].url,
+            "https://example.com/login?redirect_uri=https%3A%2F%2Fapp.example.com%2Fdone%3Fto%3Dj%C3%BCrgen"
+        );
+        assert_eq!(
+            result[0].headers["Authorization"],
+            format!("Basic {}", base64::encode("jürgen:p@ss"))
+        );
+        assert_eq!(result[0].headers["X-Literal"], base64::encode("{{x}}"));
+        assert_eq!(result[0].headers["X-Unicode"], "caf%C3%A9%20au%20lait");
+
+        assert_eq!(
+            FileParser::new()
+                .parse_from_str("GET https://example.com\nAuthorization: Basic {{$base64 {{USER}}")
+                .err()
+                .unwrap()
+                .to_string(),
+            "missing }} closing the call to $base64 in line 2"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shell_function() {
+        let content =
+            "GET https://example.com\nAuthorization: Bearer {{$shell printf 'tok%s\\n\\n' en}}";
+        assert_eq!(
+            FileParser::new()
+                .parse_from_str(content)
+                .err()
+                .unwrap()
+                .to_string(),
+            "running {{$shell printf 'tok%s\\n\\n' en}} is not allowed, use --allow-shell to enable it in line 2"
+        );
+        let result = FileParser::new()
+            .allow_shell(true)
+            .parse_from_str(content)
+            .unwrap();
+        assert_eq!(result[0].headers["Authorization"], "Bearer token");
+        assert_eq!(
+            FileParser::new()
+                .allow_shell(true)
+                .parse_from_str("GET https://example.com/{{$shell echo denied >&2; exit 3}}")
+                .err()
+                .unwrap()
+                .to_string(),
+            "command echo denied >&2; exit 3 failed (exit status: 3): denied in line 1"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "keyring"))]
+    fn keyring_function() {
+        assert_eq!(
+            FileParser::new()
+                .parse_from_str(
+                    "GET https://example.com\nAuthorization: Bearer {{$keyring api/token}}"
+                )
+                .err()
+                .unwrap()
+                .to_string(),
+            "keyring support is not enabled: build httpclient with --features keyring in line 2"
+        );
+    }
+
+    #[test]
+    fn tags() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @tag smoke
+# @tag read-only smoke
+GET https://example.com/health
+###
+GET https://example.com/users",
+            )
+            .unwrap();
+        assert_eq!(result[0].tags, vec!["smoke", "read-only"]);
+        assert!(result[1].tags.is_empty());
+        assert_eq!(
+            FileParser::new()
+                .parse_many("# @tag\nGET https://example.com")
+                .err()
+                .unwrap()
+                .to_string(),
+            "missing tag in line 1"
+        );
+    }
+
+    #[test]
+    fn depends_on() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @name create_user
+POST https://example.com/users
+###
+# @depends-on create_user
+# @depends-on login create_user
+DELETE https://example.com/users/1
+###
+# @name login
+POST https://example.com/login",
+            )
+            .unwrap();
+        assert!(result[0].depends_on.is_empty());
+        assert_eq!(result[1].depends_on, vec!["create_user", "login"]);
+        let error = |content: &str| {
+            FileParser::new()
+                .parse_many(content)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("# @depends-on\nGET https://example.com"),
+            "missing request name for depends-on in line 1"
+        );
+        assert_eq!(
+            error("GET https://example.com\n###\n# @depends-on login\nGET https://example.com/me"),
+            "the request in line 4 depends on login, not a request of the file"
+        );
+        assert_eq!(
+            error(
+                "# @name a
+# @depends-on c
+GET https://example.com/a
+###
+# @name b
+# @depends-on a
+GET https://example.com/b
+###
+# @name c
+# @depends-on b
+GET https://example.com/c"
+            ),
+            "dependency cycle detected: a -> c -> b -> a"
+        );
+        assert_eq!(
+            error("# @name a\n# @depends-on a\nGET https://example.com/a"),
+            "dependency cycle detected: a -> a"
+        );
+    }
+
+    #[test]
+    fn default_protocol() {
+        let content = "GET https://example.com\n###\nGET https://example.com HTTP/1.0";
+        let protocols = |parser: FileParser| -> Vec<String> {
+            parser
+                .parse_from_str(content)
+                .unwrap()
+                .into_iter()
+                .map(|req| req.protocol)
+                .collect()
+        };
+        assert_eq!(protocols(FileParser::new()), vec!["HTTP/1.1", "HTTP/1.0"]);
+        assert_eq!(
+            protocols(FileParser::new().default_protocol("HTTP/2")),
+            vec!["HTTP/2", "HTTP/1.0"]
+        );
+    }
+
+    #[test]
+    fn default_scheme() {
+        let content = "localhost
+###
+GET localhost:8080/health
+###
+GET example.com/users?id=1 HTTP/1.1
+###
+# @scheme https
+GET example.com
+###
+@host = localhost:3000
+GET /status";
+        let urls = |parser: FileParser| -> Vec<String> {
+            parser
+                .parse_from_str(content)
+                .unwrap()
+                .into_iter()
+                .map(|req| req.url)
+                .collect()
+        };
+        assert_eq!(
+            urls(FileParser::new()),
+            vec![
+                "http://localhost",
+                "http://localhost:8080/health",
+                "http://example.com/users?id=1",
+                "https://example.com",
+                "http://localhost:3000/status",
+            ]
+        );
+        assert_eq!(
+            urls(FileParser::new().default_scheme("https")),
+            vec![
+                "https://localhost",
+                "https://localhost:8080/health",
+                "https://example.com/users?id=1",
+                "https://example.com",
+                "https://localhost:3000/status",
+            ]
+        );
+        assert_eq!(
+            FileParser::new()
+                .parse_from_str("GET https://example.com\n###\n\nGET localhost:99999/health")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid URL http://localhost:99999/health in line 4: invalid port number"
+        );
+    }
+
+    #[test]
+    fn ipv6_hosts() {
+        let result = FileParser::new()
+            .parse_from_str(
+                "GET http://[::1]:8080/status HTTP/1.1
+    ?x=y
+###
+[::1]:8080/health#top
+###
+DELETE [2001:db8::1]/items/1",
+            )
+            .unwrap();
+        assert_eq!(&result[0].url, "http://[::1]:8080/status");
+        assert_eq!(&result[0].protocol, "HTTP/1.1");
+        assert_eq!(
+            result[0].url_parameters,
+            vec![("x".to_string(), Some("y".to_string()))]
+        );
+        assert_eq!(&result[1].url, "http://[::1]:8080/health");
+        assert_eq!(result[1].fragment, Some("top".to_string()));
+        assert_eq!(&result[2].method, "DELETE");
+        assert_eq!(&result[2].url, "http://[2001:db8::1]/items/1");
+        assert!(result.iter().all(|req| req.host_header().is_none()));
+        assert!(FileParser::new()
+            .parse_from_str("GET http://[::1:8080/status")
+            .is_err());
+    }
+
+    #[test]
+    fn internationalized_domain_names() {
+        let result = FileParser::new()
+            .parse_from_str(
+                "GET https://user@Bücher.example:8443/straße?q=ü
+###
+GET münchen.example
+###
+GET https://example.com/café",
+            )
+            .unwrap();
+        assert_eq!(
+            &result[0].url,
+            "https://user@xn--bcher-kva.example:8443/straße?q=ü"
+        );
+        assert_eq!(result[0].unicode_host, Some("Bücher.example".to_string()));
+        assert!(result[0]
+            .to_string()
+            .starts_with("GET https://user@xn--bcher-kva.example:8443/straße?q=ü HTTP/1.1\nhost: Bücher.example\n"));
+        assert_eq!(&result[1].url, "http://xn--mnchen-3ya.example");
+        assert_eq!(&result[2].url, "https://example.com/café");
+        assert_eq!(result[2].unicode_host, None);
+        assert!(FileParser::new()
+            .parse_from_str("GET https://exa\u{2488}mple.com/")
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("invalid URL https://exa\u{2488}mple.com/ in line 1: "));
+    }
+
+    #[test]
+    fn merge_params_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @merge-params replace
+GET https://example.com/x?a=1
+    ?a=2
+###
+GET https://example.com/x?a=1
+    ?a=2",
+            )
+            .unwrap();
+        assert!(result[0].replace_url_parameters);
+        assert!(!result[1].replace_url_parameters);
+        assert_eq!(
+            FileParser::new()
+                .parse_many("# @merge-params merge\nGET https://example.com")
+                .err()
+                .unwrap()
+                .to_string(),
+            "expected append or replace for merge-params, found \"merge\" in line 1"
+        );
+    }
+
+    #[test]
+    fn parsed_file() {
+        let content = "@host = https://example.com
+### Health check
+GET /health
+
+### Users
+# @name users
+GET /users
+    ?page=1
+Accept: application/json
+X-Token: {{TOKEN}}
+
+### only a comment
+";
+        let mut vars = HashMap::new();
+        vars.insert("TOKEN".to_string(), "abc".to_string());
+        let parsed = FileParser::with_vars(vars)
+            .use_env(false)
+            .parse_str(content)
+            .unwrap();
+        assert_eq!(parsed.requests.len(), 2);
+        let health = &parsed.requests[0];
+        assert_eq!(health.title, Some("Health check".to_string()));
+        assert_eq!(health.lines, 2..=4);
+        assert_eq!(health.url_line, 3);
+        assert_eq!(&health.request.url, "https://example.com/health");
+        assert_eq!(health.raw, "### Health check\nGET /health\n");
+        let users = &parsed.requests[1];
+        assert_eq!(users.title, Some("users".to_string()));
+        assert_eq!(users.lines, 5..=11);
+        assert_eq!(users.url_line, 7);
+        assert_eq!(users.parameter_lines, vec![("page".to_string(), 8)]);
+        assert_eq!(
+            users.header_lines,
+            vec![("Accept".to_string(), 9), ("X-Token".to_string(), 10)]
+        );
+        assert!(users.raw.contains("X-Token: {{TOKEN}}"));
+        assert_eq!(users.request.headers["X-Token"], "abc");
+        assert_eq!(parsed.into_requests().len(), 2);
+    }
+
+    #[test]
+    fn retry_directive() {
+        let result = FileParser::new()
+            .parse_many("# @retry 0\nPOST https://example.com\n###\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].retries, Some(0));
+        assert_eq!(result[1].retries, None);
+        assert_eq!(
+            FileParser::new()
+                .parse_many("GET https://example.com\n# @retry many")
+                .err()
+                .unwrap()
+                .to_string(),
+            "expected a number of retries, found \"many\" in line 2"
+        );
+        let result = FileParser::new()
+            .parse_many("# @retry 5 backoff=2s\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].retries, Some(5));
+        assert_eq!(result[0].retry_delay, Some(Duration::from_secs(2)));
+        assert_eq!(
+            FileParser::new()
+                .parse_many("GET https://example.com\n# @retry 5 wait=2s")
+                .err()
+                .unwrap()
+                .to_string(),
+            "unknown retry option \"wait=2s\" in line 2: expected backoff=DURATION"
+        );
+        assert!(FileParser::new()
+            .parse_many("GET https://example.com\n# @retry 5 backoff=later")
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with("in line 2"));
+    }
+
+    #[test]
+    fn delay_directive() {
+        let result = FileParser::new()
+            .parse_many("# @delay 1.5s\nPOST https://example.com\n###\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].delay, Some(Duration::from_millis(1500)));
+        assert_eq!(result[1].delay, None);
+        assert_eq!(
+            FileParser::new()
+                .parse_many("GET https://example.com\n# @delay soon")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid duration \"soon\": expected a number followed by ms, s, m or h in line 2"
+        );
+    }
+
+    #[test]
+    fn timeout_directive() {
+        let result = FileParser::new()
+            .parse_many("# @timeout 0\nGET https://example.com/export\n###\n# @timeout 10m\nGET https://example.com\n###\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].timeout, Some(Duration::from_secs(0)));
+        assert_eq!(result[1].timeout, Some(Duration::from_secs(600)));
+        assert_eq!(result[2].timeout, None);
+    }
+
+    #[test]
+    fn connect_timeout_directive() {
+        let result = FileParser::new()
+            .parse_many("# @connect-timeout 5\nGET https://example.com\n###\n# @connect-timeout 250ms\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(result[1].connect_timeout, Some(Duration::from_millis(250)));
+        assert!(FileParser::new()
+            .parse_many("# @connect-timeout fast\nGET https://example.com")
+            .is_err());
+    }
+
+    #[test]
+    fn local_address_directive() {
+        let result = FileParser::new()
+            .parse_many("# @local-address 10.0.0.12\nGET https://example.com\n###\n# @local-address ::1\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].local_address, Some("10.0.0.12".parse().unwrap()));
+        assert_eq!(result[1].local_address, Some("::1".parse().unwrap()));
+        let error = FileParser::new()
+            .parse_many("GET https://example.com\n# @local-address eth0")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid local address \"eth0\" in line 2: expected an IP address"
+        );
+    }
+
+    #[test]
+    fn no_redirect_directive() {
+        let result = FileParser::new()
+            .parse_many("# @no-redirect\nGET https://example.com/old\n###\nGET https://example.com")
+            .unwrap();
+        assert!(!result[0].follow_redirects);
+        assert!(result[1].follow_redirects);
+    }
+
+    #[test]
+    fn no_decompress_directive() {
+        let result = FileParser::new()
+            .parse_many("# @no-decompress\nGET https://cdn.example.com/app.js\n###\nGET https://cdn.example.com/app.js")
+            .unwrap();
+        assert!(!result[0].decompress);
+        assert!(result[1].decompress);
+    }
+
+    #[test]
+    fn if_newer_directive() {
+        let result = FileParser::new()
+            .parse_many("# @if-newer ./copy.bin\nGET https://example.com/copy.bin")
+            .unwrap();
+        assert_eq!(result[0].if_newer, Some(PathBuf::from("./copy.bin")));
+        assert_eq!(
+            FileParser::new()
+                .parse_many("GET https://example.com\n# @if-newer")
+                .err()
+                .unwrap()
+                .to_string(),
+            "missing the path of the file for if-newer in line 2"
+        );
+    }
+
+    #[test]
+    fn cache_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @cache\nGET https://example.com/report\n###\nGET https://example.com/report",
+            )
+            .unwrap();
+        assert!(result[0].cache);
+        assert!(!result[1].cache);
+    }
+
+    #[test]
+    fn stream_directive() {
+        let result = FileParser::new()
+            .parse_many("# @stream\nGET https://example.com/logs?follow=1\n###\nGET https://example.com/events\nAccept: text/event-stream\n###\nGET https://example.com/\n###\nGET https://example.com/watch\nAccept: application/x-ndjson")
+            .unwrap();
+        assert!(result[0].stream);
+        assert!(result[0].streams());
+        assert!(!result[1].stream);
+        assert!(result[1].streams());
+        assert!(!result[2].streams());
+        assert!(result[3].streams());
+    }
+
+    #[test]
+    fn expect_continue_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @expect-continue\nPUT http://example.com/upload\n\n< ./video.mp4\n###\nPUT http://example.com/upload",
+            )
+            .unwrap();
+        assert!(result[0].expect_continue);
+        assert_eq!(result[0].body_file, Some(PathBuf::from("./video.mp4")));
+        assert!(!result[1].expect_continue);
+    }
+
+    #[test]
+    fn unix_socket_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @unix-socket /var/run/docker.sock\nGET /v1.41/containers/json\n###\nGET unix:///var/run/docker.sock:/v1.41/info",
+            )
+            .unwrap();
+        assert_eq!(
+            result[0].unix_socket,
+            Some(PathBuf::from("/var/run/docker.sock"))
+        );
+        assert_eq!(
+            result[0].target_url().unwrap().as_str(),
+            "http://localhost/v1.41/containers/json"
+        );
+        assert_eq!(result[1].unix_socket, None);
+        assert_eq!(
+            result[1].target_url().unwrap().as_str(),
+            "unix:///var/run/docker.sock:/v1.41/info"
+        );
+        assert_eq!(
+            FileParser::new()
+                .parse_many("# @unix-socket\nGET /v1.41/info")
+                .err()
+                .unwrap()
+                .to_string(),
+            "missing the path of the unix socket in line 1"
+        );
+    }
+
+    #[test]
+    fn resolve_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @resolve api.example.com:443:10.0.0.5\n# @resolve cdn.example.com:443:[::1]\nGET https://api.example.com\n###\nGET https://api.example.com",
+            )
+            .unwrap();
+        assert_eq!(
+            result[0]
+                .resolve
+                .iter()
+                .map(|resolve| resolve.to_string())
+                .collect::<Vec<String>>(),
+            vec!["api.example.com:443:10.0.0.5", "cdn.example.com:443:[::1]"]
+        );
+        assert!(result[1].resolve.is_empty());
+        assert_eq!(
+            FileParser::new()
+                .parse_many("# @resolve api.example.com:10.0.0.5\nGET https://api.example.com")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid resolve api.example.com:10.0.0.5: expected host:port:address in line 1"
+        );
+    }
+
+    #[test]
+    fn client_cert_directive() {
+        let result = FileParser::new()
+            .parse_many(
+                "# @client-cert certs/client.p12 s3cr3t\nGET https://mtls.local\n###\n# @client-cert client.pem client.key\nGET https://mtls.local",
+            )
+            .unwrap();
+        assert_eq!(
+            result[0].client_certificate,
+            Some(client::ClientCertificate::Pkcs12 {
+                archive: PathBuf::from("certs/client.p12"),
+                password: "s3cr3t".to_string(),
+            })
+        );
+        assert_eq!(
+            result[1].client_certificate,
+            Some(client::ClientCertificate::Pem {
+                certificate: PathBuf::from("client.pem"),
+                key: PathBuf::from("client.key"),
+            })
+        );
+        assert!(FileParser::new()
+            .parse_many("# @client-cert client.pem\nGET https://mtls.local")
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with("in line 1"));
+    }
+
+    #[test]
+    fn insecure_directive() {
+        let result = FileParser::new()
+            .parse_many("# @insecure\nGET https://dev.local\n###\nGET https://example.com")
+            .unwrap();
+        assert!(result[0].insecure);
+        assert!(!result[1].insecure);
+    }
+
+    #[test]
+    fn proxy_directive() {
+        let result = FileParser::new()
+            .parse_many("# @proxy http://proxy.local:3128\nGET https://example.com\n###\nGET https://example.com")
+            .unwrap();
+        assert_eq!(result[0].proxy, Some("http://proxy.local:3128".to_string()));
+        assert_eq!(result[1].proxy, None);
+        assert_eq!(
+            FileParser::new()
+                .parse_many("# @proxy proxy.local\nGET https://example.com")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid proxy proxy.local in line 1: relative URL without a base"
+        );
+    }
+
+    #[test]
+    fn missing_variables() {
+        env::set_var("HTTPCLIENT_TEST_DEFINED", "ok");
+        let contents = "@base = https://{{HTTPCLIENT_TEST_MISSING_HOST}}
+GET {{base}}/{{HTTPCLIENT_TEST_DEFINED}}
+Authorization: Bearer {{HTTPCLIENT_TEST_MISSING_TOKEN}}
+
+###
+GET {{base}}?token={{HTTPCLIENT_TEST_MISSING_TOKEN}}";
+        let error = FileParser::new().parse_from_str(contents).err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<MissingVariables>(),
+            Some(&MissingVariables(vec![
+                ("HTTPCLIENT_TEST_MISSING_HOST".to_string(), 2),
+                ("HTTPCLIENT_TEST_MISSING_TOKEN".to_string(), 3),
+            ]))
+        );
+        assert_eq!(
+            error.to_string(),
+            "you must provide a value for keys HTTPCLIENT_TEST_MISSING_HOST (line 2), \
+             HTTPCLIENT_TEST_MISSING_TOKEN (line 3)"
+        );
+        let error = FileParser::new()
+            .parse_from_str("GET https://{{HTTPCLIENT_TEST_MISSING_HOST}}")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "you must provide a value for key HTTPCLIENT_TEST_MISSING_HOST, used in line 1"
+        );
+    }
+
+    #[test]
+    fn nested_variables() {
+        env::set_var("HTTPCLIENT_TEST_HOST", "example.com");
+        let contents = "GET {{users}}/42
+@users = {{base}}/users
+@base = https://{{HTTPCLIENT_TEST_HOST}}/api
+@a = {{b}}
+@b = {{a}}
+";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(&result[0].url, "https://example.com/api/users/42");
+        let error = FileParser::new()
+            .parse_from_str(&format!("{}GET {{{{a}}}}", contents))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "cyclic variable definition: a -> b -> a in line 6"
+        );
+        assert!(matches!(
+            error.downcast_ref::<ParseError>(),
+            Some(ParseError::Expansion {
+                line: 6,
+                error: ExpansionError::Cycle(_),
+            })
+        ));
+    }
+
+    #[test]
+    fn block_variables() {
+        let contents = "@id = 1
+@base = https://example.com/{{id}}
+GET {{base}}
+
+### shadows the global id
+@id = 2
+GET {{base}}?id={{id}}
+
+###
+GET https://example.com/{{id}}
+";
+        let result = FileParser::new().parse_from_str(contents).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(&result[0].url, "https://example.com/1");
+        assert_eq!(&result[1].url, "https://example.com/2?id=2");
+        assert_eq!(&result[2].url, "https://example.com/1");
+    }
+
+    #[test]
+    fn named_requests() {
+        let contents = "# @name login
+POST https://example.com/login
+
+###
+// @name profile
+GET https://example.com/me
+Authorization: Bearer {{login.response.body.$.token}}";
+        let hrp = FileParser::new();
+        let result = &hrp.parse_many(contents).unwrap();
+        assert_eq!(result[0].name, Some("login".to_string()));
+        assert_eq!(result[1].name, Some("profile".to_string()));
+        assert_eq!(
+            &result[1].headers.get("Authorization"),
+            &Some(&"Bearer {{login.response.body.$.token}}".to_string())
+        );
+    }
+
+    #[test]
+    fn output_file() {
+        let result = FileParser::new()
+            .parse_from_str(
+                "@year = 2021\nGET https://example.com/export.csv\n\n>> exports/{{year}}.csv\n?? status == 200\n###\nPOST https://example.com/reports\n\n{\"year\": 2021}\n\n>>! report.json\n###\nPOST https://example.com/shell\n\necho >> log.txt",
+            )
+            .unwrap();
+        assert_eq!(result[0].body, b"");
+        assert_eq!(
+            result[0].output,
+            Some(request::OutputFile {
+                path: PathBuf::from("exports/2021.csv"),
+                overwrite: false,
+            })
+        );
+        assert_eq!(result[0].assertions.len(), 1);
+        assert_eq!(result[1].body, b"{\"year\": 2021}");
+        assert_eq!(
+            result[1].output,
+            Some(request::OutputFile {
+                path: PathBuf::from("report.json"),
+                overwrite: true,
+            })
+        );
+        // not following a blank line, it's part of the body
+        assert_eq!(result[2].body, b"echo >> log.txt");
+        assert_eq!(result[2].output, None);
+
+        let error = |contents| {
+            FileParser::new()
+                .parse_many(contents)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("GET https://example.com\n>>"),
+            "missing the path of the output file in line 2"
+        );
+        assert_eq!(
+            error("GET https://example.com\n>> a.json\n>> b.json"),
+            "a request can be written to a single output file in line 3"
+        );
+        assert_eq!(
+            error("GET https://example.com\n\n>> a.json\nmore body"),
+            "unexpected line after the output file in line 4"
+        );
+    }
+
+    #[test]
+    fn body_file() {
+        let result = FileParser::new()
+            .parse_many(
+                "PUT https://example.com/avatar\nContent-Type: image/png\n\n< ./images/avatar.png\n###\nPOST https://example.com/html\n\n<p>a paragraph</p>\n###\nPOST https://example.com/compare\n\n< 3\n> 2",
+            )
+            .unwrap();
+        assert_eq!(
+            result[0].body_file,
+            Some(PathBuf::from("./images/avatar.png"))
+        );
+        assert_eq!(result[0].body, b"");
+        assert_eq!(result[1].body_file, None);
+        assert_eq!(result[1].body, b"<p>a paragraph</p>");
+        // not a single line
+        assert_eq!(result[2].body_file, None);
+        assert_eq!(result[2].body, b"< 3\n> 2");
+    }
+
+    #[test]
+    fn body_stdin() {
+        let result = FileParser::new()
+            .parse_many(
+                "POST https://example.com/upload\n\n< -\n###\n# @body-stdin\nPUT https://example.com/upload\n###\nPOST https://example.com/upload\n\n< ./-",
+            )
+            .unwrap();
+        assert!(result[0].body_stdin);
+        assert_eq!(
+            (&result[0].body[..], &result[0].body_file),
+            (&b""[..], &None)
+        );
+        assert!(result[1].body_stdin);
+        assert!(!result[2].body_stdin);
+        assert_eq!(result[2].body_file, Some(PathBuf::from("./-")));
     }
 }
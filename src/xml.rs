@@ -0,0 +1,311 @@
+/// A node of an XML document, as written: the tags keep their attributes as
+/// they are.
+#[derive(Debug)]
+enum Node {
+    Element {
+        /// the start tag, from `<` to `>`
+        tag: String,
+        name: String,
+        children: Vec<Node>,
+    },
+    /// a self-closing element, as `<br/>`
+    Empty(String),
+    /// text, as written
+    Text(String),
+    /// a `<![CDATA[...]]>` section, left as it is
+    CData(String),
+    /// a comment, a processing instruction as `<?xml ...?>`, or a doctype
+    Other(String),
+}
+
+/// Pretty-prints an XML document, with an element per line indented by
+/// `indent` spaces, and the ones with text, mixed with elements or not, on a
+/// line of their own as written; `None` if it's malformed. Only the text that
+/// is all whitespace between elements is dropped.
+///
+/// # Examples
+///
+/// ```
+/// let pretty = httpclient::xml::pretty("<a><b id=\"1\">text</b></a>", 2).unwrap();
+/// assert_eq!(pretty, "<a>\n  <b id=\"1\">text</b>\n</a>");
+/// assert_eq!(httpclient::xml::pretty("<a><b></a>", 2), None);
+/// ```
+pub fn pretty(text: &str, indent: usize) -> Option<String> {
+    let nodes = parse(text)?;
+    let mut lines = Vec::new();
+    for node in &nodes {
+        write(node, 0, indent, &mut lines);
+    }
+    Some(lines.join("\n"))
+}
+
+/// Parses a document: its prolog, a root element and what follows it.
+fn parse(text: &str) -> Option<Vec<Node>> {
+    // the elements open, with the children found so far
+    let mut open: Vec<(String, String, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut roots = 0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (node, length) = match rest.find('<') {
+            Some(0) => match markup(rest)? {
+                Markup::Start { tag, name, length } => {
+                    open.push((tag, name, Vec::new()));
+                    rest = &rest[length..];
+                    continue;
+                }
+                Markup::End { name, length } => {
+                    let (tag, start, children) = open.pop()?;
+                    if start != name {
+                        return None;
+                    }
+                    (
+                        Node::Element {
+                            tag,
+                            name,
+                            children,
+                        },
+                        length,
+                    )
+                }
+                Markup::Node(node, length) => (node, length),
+            },
+            Some(start) => (Node::Text(rest[..start].to_string()), start),
+            None => (Node::Text(rest.to_string()), rest.len()),
+        };
+        rest = &rest[length..];
+        match (&node, open.last_mut()) {
+            (_, Some((_, _, children))) => children.push(node),
+            (Node::Text(text), None) if is_blank(text) => {}
+            // outside of the root, only comments and the like
+            (Node::Text(_), None) | (Node::CData(_), None) => return None,
+            (Node::Element { .. }, None) | (Node::Empty(_), None) => {
+                roots += 1;
+                nodes.push(node);
+            }
+            (Node::Other(_), None) => nodes.push(node),
+        }
+    }
+    match (open.is_empty(), roots) {
+        (true, 1) => Some(nodes),
+        _ => None,
+    }
+}
+
+enum Markup {
+    Start {
+        tag: String,
+        name: String,
+        length: usize,
+    },
+    End {
+        name: String,
+        length: usize,
+    },
+    Node(Node, usize),
+}
+
+/// Reads the markup at the start of `text`, from its `<`.
+fn markup(text: &str) -> Option<Markup> {
+    for &(start, end) in &[("<![CDATA[", "]]>"), ("<!--", "-->"), ("<?", "?>")] {
+        if !text.starts_with(start) {
+            continue;
+        }
+        let length = text[start.len()..].find(end)? + start.len() + end.len();
+        let section = text[..length].to_string();
+        return Some(match start {
+            "<![CDATA[" => Markup::Node(Node::CData(section), length),
+            _ => Markup::Node(Node::Other(section), length),
+        });
+    }
+    if text.starts_with("<!") {
+        // a doctype, with its internal subset in brackets if any
+        let mut depth = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    return Some(Markup::Node(Node::Other(text[..=i].to_string()), i + 1))
+                }
+                _ => {}
+            }
+        }
+        return None;
+    }
+    if let Some(end) = text.strip_prefix("</") {
+        let length = end.find('>')?;
+        let name = end[..length].trim_end();
+        if name.is_empty() {
+            return None;
+        }
+        return Some(Markup::End {
+            name: name.to_string(),
+            length: length + 3,
+        });
+    }
+    let length = tag_length(text)?;
+    let tag = &text[..length];
+    let name: String = tag[1..]
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>')
+        .collect();
+    if name.is_empty() {
+        return None;
+    }
+    match tag.ends_with("/>") {
+        true => Some(Markup::Node(Node::Empty(tag.to_string()), length)),
+        false => Some(Markup::Start {
+            tag: tag.to_string(),
+            name,
+            length,
+        }),
+    }
+}
+
+/// The length of the start tag at the start of `text`, up to its `>`, out
+/// of the quoted attribute values.
+fn tag_length(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '<') if i > 0 => return None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_blank(text: &str) -> bool {
+    text.trim().is_empty()
+}
+
+/// Whether the children of an element are text, alone or mixed with
+/// elements, to write as they are.
+fn is_text(children: &[Node]) -> bool {
+    children
+        .iter()
+        .all(|child| matches!(child, Node::Text(_) | Node::CData(_)))
+        || children
+            .iter()
+            .any(|child| matches!(child, Node::Text(text) if !is_blank(text)))
+}
+
+fn write(node: &Node, depth: usize, indent: usize, lines: &mut Vec<String>) {
+    let margin = " ".repeat(depth * indent);
+    match node {
+        Node::Element {
+            tag,
+            name,
+            children,
+        } if !is_text(children) => {
+            lines.push(format!("{}{}", margin, tag));
+            // the text between the elements is only whitespace
+            for child in children {
+                if !matches!(child, Node::Text(_)) {
+                    write(child, depth + 1, indent, lines);
+                }
+            }
+            lines.push(format!("{}</{}>", margin, name));
+        }
+        node => lines.push(format!("{}{}", margin, inline(node))),
+    }
+}
+
+/// A node as it was written.
+fn inline(node: &Node) -> String {
+    match node {
+        Node::Element {
+            tag,
+            name,
+            children,
+        } => format!(
+            "{}{}</{}>",
+            tag,
+            children.iter().map(inline).collect::<String>(),
+            name
+        ),
+        Node::Empty(text) | Node::Text(text) | Node::CData(text) | Node::Other(text) => {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_elements() {
+        let text = "<?xml version=\"1.0\"?><!-- a catalog --><catalog><book id=\"1\" \
+                    lang='en'><title>Dune</title><tags><tag>sf</tag><tag/></tags>\
+                    <price currency=\"a>b\">9.99</price></book><empty></empty></catalog>";
+        assert_eq!(
+            pretty(text, 2).unwrap(),
+            "<?xml version=\"1.0\"?>
+<!-- a catalog -->
+<catalog>
+  <book id=\"1\" lang='en'>
+    <title>Dune</title>
+    <tags>
+      <tag>sf</tag>
+      <tag/>
+    </tags>
+    <price currency=\"a>b\">9.99</price>
+  </book>
+  <empty></empty>
+</catalog>"
+        );
+        // already indented, with another width
+        assert_eq!(
+            pretty("<a>\n  <b>\n    <c> text </c>\n  </b>\n</a>\n", 4).unwrap(),
+            "<a>\n    <b>\n        <c> text </c>\n    </b>\n</a>"
+        );
+    }
+
+    #[test]
+    fn cdata_sections() {
+        let text = "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\">\
+                    <soap:Body><script><![CDATA[if (a < b) { x = \"</script>\"; }]]></script>\
+                    <mixed>a <![CDATA[<b>]]></mixed></soap:Body></soap:Envelope>";
+        assert_eq!(
+            pretty(text, 2).unwrap(),
+            "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\">
+  <soap:Body>
+    <script><![CDATA[if (a < b) { x = \"</script>\"; }]]></script>
+    <mixed>a <![CDATA[<b>]]></mixed>
+  </soap:Body>
+</soap:Envelope>"
+        );
+    }
+
+    #[test]
+    fn mixed_content() {
+        assert_eq!(
+            pretty("<doc><p>Hello <b>big</b>\n  world!</p><br/></doc>", 2).unwrap(),
+            "<doc>\n  <p>Hello <b>big</b>\n  world!</p>\n  <br/>\n</doc>"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for text in &[
+            "",
+            "not XML",
+            "<a>",
+            "<a></b>",
+            "<a><b></a></b>",
+            "<a x=\"1></a>",
+            "<a></a><b></b>",
+            "<a></a>text",
+            "<a><![CDATA[never closed</a>",
+            "<>text</>",
+        ] {
+            assert_eq!(pretty(text, 2), None, "{}", text);
+        }
+    }
+}
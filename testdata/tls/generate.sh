@@ -0,0 +1,25 @@
+#!/bin/sh
+# Generates the certificates of the TLS tests: a CA, a certificate signed by
+# it for localhost and 127.0.0.1, a client certificate signed by it, also as
+# a PKCS#12 archive, and a self-signed client certificate.
+set -e
+cd "$(dirname "$0")"
+days=36500
+
+openssl req -x509 -newkey rsa:2048 -nodes -days $days -subj "/CN=httpclient test CA" \
+    -keyout ca.key -out ca.pem
+openssl req -newkey rsa:2048 -nodes -subj "/CN=localhost" -keyout server.key -out server.csr
+printf 'subjectAltName=DNS:localhost,IP:127.0.0.1\n' > server.ext
+openssl x509 -req -days $days -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial \
+    -extfile server.ext -out server.pem
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=httpclient test client" \
+    -keyout client.key -out client.csr
+openssl x509 -req -days $days -in client.csr -CA ca.pem -CAkey ca.key -CAcreateserial \
+    -out client.pem
+openssl pkcs12 -export -in client.pem -inkey client.key -passout pass:secret -out client.p12
+openssl req -x509 -newkey rsa:2048 -nodes -days $days -subj "/CN=unknown client" \
+    -keyout unknown.key -out unknown.pem
+openssl pkcs12 -export -in unknown.pem -inkey unknown.key -passout pass:secret -out unknown.p12
+
+rm -f server.csr server.ext client.csr unknown.key unknown.pem ca.srl